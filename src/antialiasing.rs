@@ -0,0 +1,317 @@
+use std::sync::Arc;
+
+use glam::{Mat4, Vec2};
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet},
+    format::Format,
+    image::{sampler::Sampler, view::ImageView, SampleCount},
+    pipeline::GraphicsPipeline,
+    shader::ShaderModule,
+};
+
+use crate::{
+    camera::Camera,
+    device::GPU,
+    keyboard, post,
+    render_graph::{CompiledGraph, PassId, RenderGraph, ResourceId},
+    shaders,
+};
+
+const MOTION_FORMAT: Format = Format::R16G16_SFLOAT;
+// quantidade de posições diferentes do jitter antes de repetir
+const JITTER_SAMPLES: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AntiAliasingMode {
+    None,
+    // barato, só olha a imagem atual
+    Fxaa,
+    // junta os frames anteriores com a câmera tremendo menos de um pixel
+    Taa,
+}
+
+impl AntiAliasingMode {
+    // na ordem em que a tecla troca os modos
+    pub const ALL: [AntiAliasingMode; 3] = [
+        AntiAliasingMode::None,
+        AntiAliasingMode::Fxaa,
+        AntiAliasingMode::Taa,
+    ];
+
+    pub fn next(self) -> AntiAliasingMode {
+        keyboard::cycle(&Self::ALL, self)
+    }
+}
+
+// sequência de Halton, index começa em 1 (o 0 daria sempre 0)
+pub fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+// deslocamento em NDC do frame, dentro de um pixel em volta do centro
+pub fn jitter(frame: u32, extent: [u32; 2]) -> Vec2 {
+    let index = frame % JITTER_SAMPLES + 1;
+    let offset = Vec2::new(halton(index, 2), halton(index, 3)) - 0.5;
+    offset * 2.0 / Vec2::new(extent[0] as f32, extent[1] as f32)
+}
+
+// passes do anti-aliasing no grafo do frame (veja AntiAliasing::declare)
+pub struct AntiAliasingPasses {
+    motion: PassId,
+    resolve: PassId,
+    source: ResourceId,
+    positions: ResourceId,
+    motion_image: ResourceId,
+    // resultado com qualquer modo, entrada do resto do PostChain
+    pub output: ResourceId,
+    // as duas imagens de history, o resolve escreve numa e lê a outra
+    history: ResourceId,
+    history_images: [Arc<ImageView>; 2],
+}
+
+// anti-aliasing logo depois da cena, antes das outras passes do PostChain.
+// O TAA reprojeta o histórico com vetores de movimento tirados das posições
+// do SSAO, então só leva em conta o movimento da câmera
+pub struct AntiAliasing {
+    pub mode: AntiAliasingMode,
+    // quanto do frame atual entra no histórico do TAA
+    pub current_weight: f32,
+    passes: AntiAliasingPasses,
+    fxaa: Arc<GraphicsPipeline>,
+    motion: Arc<GraphicsPipeline>,
+    resolve: Arc<GraphicsPipeline>,
+    fxaa_set: Arc<PersistentDescriptorSet>,
+    motion_set: Arc<PersistentDescriptorSet>,
+    // um para cada imagem de history escrita
+    resolve_sets: [Arc<PersistentDescriptorSet>; 2],
+    extent: [u32; 2],
+    // estado do TAA, atualizado uma vez por frame (veja begin_frame)
+    frame: u32,
+    jitter: Vec2,
+    view_projection: Mat4,
+    reprojection: Mat4,
+    history_valid: bool,
+    reset_history: bool,
+}
+
+impl AntiAliasing {
+    // source é a cena com o SSAO, positions vem do SsaoPasses. O histórico
+    // fica fora do grafo porque passa de um frame para o outro
+    pub fn declare(
+        device: &GPU,
+        graph: &mut RenderGraph,
+        source: ResourceId,
+        positions: ResourceId,
+    ) -> AntiAliasingPasses {
+        let samples = SampleCount::Sample1;
+        let history_images =
+            [(); 2].map(|_| post::target(device, graph.extent(), post::HDR_FORMAT));
+        let output = graph.transient("anti_aliasing", post::HDR_FORMAT, samples);
+        let motion_image = graph.transient("motion", MOTION_FORMAT, samples);
+        let history = graph.output("history", history_images.to_vec());
+        AntiAliasingPasses {
+            motion: graph
+                .add_pass("taa_motion")
+                .color(motion_image, None)
+                .sample(positions)
+                .id(),
+            // sem TAA a mesma pass roda o FXAA e o history fica sem uso
+            resolve: graph
+                .add_pass("anti_aliasing")
+                .color(output, None)
+                .color(history, None)
+                .sample(source)
+                .sample(motion_image)
+                .id(),
+            source,
+            positions,
+            motion_image,
+            output,
+            history,
+            history_images,
+        }
+    }
+
+    pub fn new(
+        device: &GPU,
+        vs: &Arc<ShaderModule>,
+        graph: &CompiledGraph,
+        passes: AntiAliasingPasses,
+        sampler: Arc<Sampler>,
+    ) -> AntiAliasing {
+        let pipeline = |fs: Arc<ShaderModule>, pass| {
+            post::fullscreen_pipeline(device, vs, &fs, graph.subpass(pass), None)
+        };
+        let fxaa = pipeline(
+            shaders::fxaa_fs::load(device.clone()).expect("failed to create shader module"),
+            passes.resolve,
+        );
+        let motion = pipeline(
+            shaders::taa_motion_fs::load(device.clone()).expect("failed to create shader module"),
+            passes.motion,
+        );
+        let resolve = pipeline(
+            shaders::taa_resolve_fs::load(device.clone()).expect("failed to create shader module"),
+            passes.resolve,
+        );
+
+        let descriptor_set_allocator =
+            StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+        let set = |pipeline: &Arc<GraphicsPipeline>, inputs: Vec<Arc<ImageView>>| {
+            post::input_set(&descriptor_set_allocator, &sampler, pipeline, inputs)
+        };
+        let source = graph.image(passes.source).unwrap();
+        let motion_image = graph.image(passes.motion_image).unwrap();
+        let fxaa_set = set(&fxaa, vec![source.clone()]);
+        let motion_set = set(&motion, vec![graph.image(passes.positions).unwrap()]);
+        let resolve_sets = [0, 1].map(|index| {
+            set(
+                &resolve,
+                vec![
+                    source.clone(),
+                    passes.history_images[1 - index].clone(),
+                    motion_image.clone(),
+                ],
+            )
+        });
+
+        AntiAliasing {
+            mode: AntiAliasingMode::None,
+            current_weight: 0.1,
+            extent: graph.extent(passes.resolve),
+            passes,
+            fxaa,
+            motion,
+            resolve,
+            fxaa_set,
+            motion_set,
+            resolve_sets,
+            frame: 0,
+            jitter: Vec2::ZERO,
+            view_projection: Mat4::IDENTITY,
+            reprojection: Mat4::IDENTITY,
+            history_valid: false,
+            reset_history: true,
+        }
+    }
+
+    // imagem de history escrita neste frame (veja PostChain::outputs)
+    pub fn history(&self) -> (ResourceId, usize) {
+        (self.passes.history, (self.frame % 2) as usize)
+    }
+
+    // chamado antes de gravar os command buffers do frame: com o TAA muda o
+    // jitter da câmera e guarda as matrizes para a reprojeção, nos outros
+    // modos tira o jitter
+    pub fn begin_frame(&mut self, camera: &mut Camera) {
+        if self.mode != AntiAliasingMode::Taa {
+            self.history_valid = false;
+            if camera.jitter() != Vec2::ZERO {
+                camera.set_jitter(Vec2::ZERO);
+            }
+            return;
+        }
+
+        self.frame = self.frame.wrapping_add(1);
+        self.jitter = jitter(self.frame, self.extent);
+        camera.set_jitter(self.jitter);
+
+        // a reprojeção usa as matrizes sem jitter, o do frame atual é
+        // descontado no shader
+        let unjittered = Mat4::from_translation(-self.jitter.extend(0.0)) * camera.projection;
+        let view_projection = unjittered * camera.view;
+        let previous = if self.history_valid {
+            self.view_projection
+        } else {
+            view_projection
+        };
+        self.reprojection = previous * camera.view.inverse();
+        self.view_projection = view_projection;
+        self.reset_history = !self.history_valid;
+        self.history_valid = true;
+    }
+
+    // TAA precisa das posições já gravadas (veja Ssao::record)
+    pub fn record(
+        &self,
+        graph: &CompiledGraph,
+        pass: PassId,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) {
+        let taa = self.mode == AntiAliasingMode::Taa;
+        if pass == self.passes.motion && taa {
+            post::fullscreen_draw(
+                builder,
+                graph.extent(pass),
+                &self.motion,
+                &self.motion_set,
+                Some(shaders::taa_motion_fs::Motion {
+                    reprojection: self.reprojection.to_cols_array_2d(),
+                    jitter: self.jitter.to_array(),
+                }),
+            );
+        } else if pass == self.passes.resolve && taa {
+            post::fullscreen_draw(
+                builder,
+                graph.extent(pass),
+                &self.resolve,
+                &self.resolve_sets[self.history().1],
+                Some(shaders::taa_resolve_fs::Resolve {
+                    current_weight: if self.reset_history {
+                        1.0
+                    } else {
+                        self.current_weight
+                    },
+                }),
+            );
+        } else if pass == self.passes.resolve {
+            let [width, height] = graph.extent(pass);
+            post::fullscreen_draw(
+                builder,
+                [width, height],
+                &self.fxaa,
+                &self.fxaa_set,
+                Some(shaders::fxaa_fs::Fxaa {
+                    texel_size: [1.0 / width as f32, 1.0 / height as f32],
+                    enabled: (self.mode == AntiAliasingMode::Fxaa) as i32,
+                }),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn halton_fills_the_unit_interval() {
+        assert_eq!(halton(1, 2), 0.5);
+        assert_eq!(halton(2, 2), 0.25);
+        assert_eq!(halton(3, 2), 0.75);
+        assert!((halton(1, 3) - 1.0 / 3.0).abs() < 1e-6);
+        assert!((halton(2, 3) - 2.0 / 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn jitter_stays_inside_a_pixel() {
+        let extent = [1280, 720];
+        let pixel = Vec2::new(2.0 / 1280.0, 2.0 / 720.0);
+        let mut seen = vec![];
+        for frame in 0..JITTER_SAMPLES {
+            let jitter = jitter(frame, extent);
+            assert!(jitter.abs().cmple(pixel * 0.5).all());
+            assert!(!seen.contains(&jitter));
+            seen.push(jitter);
+        }
+        assert_eq!(jitter(JITTER_SAMPLES, extent), seen[0]);
+    }
+}
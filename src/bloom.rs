@@ -0,0 +1,337 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet},
+    image::sampler::Sampler,
+    pipeline::{graphics::color_blend::AttachmentBlend, GraphicsPipeline},
+    query::{QueryPool, QueryPoolCreateInfo, QueryResultFlags, QueryType},
+    shader::ShaderModule,
+    sync::PipelineStage,
+};
+
+use crate::{
+    device::GPU,
+    post,
+    render_graph::{CompiledGraph, PassId, RenderGraph, ResourceId},
+    shaders,
+};
+
+// o primeiro mip já tem metade do tamanho da tela, mais que isso quase não
+// muda o resultado
+const MAX_LEVELS: u32 = 6;
+
+#[derive(Debug, Clone, Copy)]
+pub struct BloomSettings {
+    pub enabled: bool,
+    // brilho a partir do qual os pixels começam a brilhar, com uma
+    // transição suave abaixo disso
+    pub threshold: f32,
+    // quanto do bloom é somado à cena antes do tone mapping
+    pub intensity: f32,
+    // raio do filtro do upsample em coordenadas de textura
+    pub radius: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold: 1.0,
+            intensity: 0.5,
+            radius: 0.005,
+        }
+    }
+}
+
+// quantidade de mips da cadeia, o primeiro com metade do tamanho da tela e
+// nenhum menor que 1x1
+pub fn level_count(extent: [u32; 2], max_levels: u32) -> u32 {
+    let smallest = (extent[0].min(extent[1]) / 2).max(1);
+    (u32::BITS - smallest.leading_zeros()).min(max_levels)
+}
+
+// passes do bloom no grafo do frame (veja Bloom::declare)
+pub struct BloomPasses {
+    // uma por mip, o upsample soma no que o downsample escreveu
+    down: Vec<PassId>,
+    // do segundo menor mip para o maior
+    up: Vec<PassId>,
+    source: ResourceId,
+    levels: Vec<ResourceId>,
+}
+
+impl BloomPasses {
+    // o mip 0, somado à cena no tone mapping
+    pub fn result(&self) -> ResourceId {
+        self.levels[0]
+    }
+}
+
+// bloom do Jimenez (Next Generation Post Processing in Call of Duty: Advanced
+// Warfare): reduz os pixels claros numa cadeia de mips e soma de volta do
+// menor para o maior, o mip 0 é somado à cena no tone mapping. Cada mip é uma
+// imagem do grafo
+pub struct Bloom {
+    pub settings: BloomSettings,
+    passes: BloomPasses,
+    downsample: Arc<GraphicsPipeline>,
+    upsample: Arc<GraphicsPipeline>,
+    // entrada de cada downsample (a cena no primeiro) e de cada upsample
+    down_sets: Vec<Arc<PersistentDescriptorSet>>,
+    up_sets: Vec<Arc<PersistentDescriptorSet>>,
+    // tamanho da entrada de cada downsample
+    down_extents: Vec<[u32; 2]>,
+    // início e fim de cada imagem da swapchain, sem suporte a timestamps
+    // na queue fica None
+    query_pool: Option<Arc<QueryPool>>,
+    timestamp_period: f32,
+}
+
+impl Bloom {
+    // source é a imagem HDR de onde saem os pixels claros
+    pub fn declare(graph: &mut RenderGraph, source: ResourceId) -> BloomPasses {
+        let [width, height, _] = graph.extent();
+        let level_count = level_count([width, height], MAX_LEVELS);
+        let levels: Vec<ResourceId> = (0..level_count)
+            .map(|level| {
+                let extent = [
+                    (width >> (level + 1)).max(1),
+                    (height >> (level + 1)).max(1),
+                    1,
+                ];
+                graph.transient_sized(&format!("bloom_{level}"), post::HDR_FORMAT, extent)
+            })
+            .collect();
+        let down = levels
+            .iter()
+            .enumerate()
+            .map(|(level, &target)| {
+                let input = match level {
+                    0 => source,
+                    _ => levels[level - 1],
+                };
+                graph
+                    .add_pass(&format!("bloom_down_{level}"))
+                    .color(target, None)
+                    .sample(input)
+                    .id()
+            })
+            .collect();
+        // do menor para o maior, cada mip soma o de baixo ampliado
+        let up = (1..levels.len())
+            .rev()
+            .map(|level| {
+                graph
+                    .add_pass(&format!("bloom_up_{level}"))
+                    .color(levels[level - 1], None)
+                    .sample(levels[level])
+                    .id()
+            })
+            .collect();
+        BloomPasses {
+            down,
+            up,
+            source,
+            levels,
+        }
+    }
+
+    pub fn new(
+        device: &GPU,
+        vs: &Arc<ShaderModule>,
+        graph: &CompiledGraph,
+        passes: BloomPasses,
+        sampler: Arc<Sampler>,
+        image_count: usize,
+    ) -> Bloom {
+        let downsample = post::fullscreen_pipeline(
+            device,
+            vs,
+            &shaders::bloom_down_fs::load(device.clone()).expect("failed to create shader module"),
+            graph.subpass(passes.down[0]),
+            None,
+        );
+        // com um mip só não tem upsample
+        let upsample = passes.up.first().map(|&pass| graph.subpass(pass));
+        let upsample = post::fullscreen_pipeline(
+            device,
+            vs,
+            &shaders::bloom_up_fs::load(device.clone()).expect("failed to create shader module"),
+            upsample.unwrap_or_else(|| graph.subpass(passes.down[0])),
+            Some(AttachmentBlend::additive()),
+        );
+
+        // timestamps precisam de suporte da queue
+        let queue_family = device.graphics_queue.queue_family_index() as usize;
+        let query_pool = device.physical_device.queue_family_properties()[queue_family]
+            .timestamp_valid_bits
+            .map(|_| {
+                QueryPool::new(
+                    device.logical_device.clone(),
+                    QueryPoolCreateInfo {
+                        query_count: 2 * image_count as u32,
+                        ..QueryPoolCreateInfo::query_type(QueryType::Timestamp)
+                    },
+                )
+                .unwrap()
+            });
+
+        let descriptor_set_allocator =
+            StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+        let set = |pipeline: &Arc<GraphicsPipeline>, input: ResourceId| {
+            post::input_set(
+                &descriptor_set_allocator,
+                &sampler,
+                pipeline,
+                vec![graph.image(input).unwrap()],
+            )
+        };
+        let inputs: Vec<ResourceId> = [passes.source]
+            .into_iter()
+            .chain(passes.levels[..passes.levels.len() - 1].iter().copied())
+            .collect();
+
+        Bloom {
+            settings: BloomSettings::default(),
+            down_sets: inputs
+                .iter()
+                .map(|&input| set(&downsample, input))
+                .collect(),
+            up_sets: passes.levels[1..]
+                .iter()
+                .rev()
+                .map(|&level| set(&upsample, level))
+                .collect(),
+            down_extents: inputs
+                .iter()
+                .map(|&input| {
+                    let [width, height, _] = graph.image(input).unwrap().image().extent();
+                    [width, height]
+                })
+                .collect(),
+            passes,
+            downsample,
+            upsample,
+            query_pool,
+            timestamp_period: device.physical_device.properties().timestamp_period,
+        }
+    }
+
+    // fora dos render passes, antes do execute do grafo
+    pub fn reset_queries(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        image_index: usize,
+    ) {
+        let Some(query_pool) = &self.query_pool else {
+            return;
+        };
+        if self.settings.enabled {
+            let queries = 2 * image_index as u32;
+            unsafe {
+                builder
+                    .reset_query_pool(query_pool.clone(), queries..queries + 2)
+                    .unwrap();
+            }
+        }
+    }
+
+    // desligado as passes ficam vazias, o tone mapping não lê o resultado
+    pub fn record(
+        &self,
+        graph: &CompiledGraph,
+        pass: PassId,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        image_index: usize,
+    ) {
+        if !self.settings.enabled {
+            return;
+        }
+        let queries = 2 * image_index as u32;
+        let timestamp = |builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+                         query: u32,
+                         stage: PipelineStage| {
+            if let Some(query_pool) = &self.query_pool {
+                unsafe {
+                    builder
+                        .write_timestamp(query_pool.clone(), query, stage)
+                        .unwrap();
+                }
+            }
+        };
+
+        if let Some(level) = self.passes.down.iter().position(|&other| other == pass) {
+            if level == 0 {
+                timestamp(builder, queries, PipelineStage::TopOfPipe);
+            }
+            // texel da entrada, a cena no primeiro e o mip anterior nos outros
+            let [width, height] = self.down_extents[level];
+            post::fullscreen_draw(
+                builder,
+                graph.extent(pass),
+                &self.downsample,
+                &self.down_sets[level],
+                Some(shaders::bloom_down_fs::Downsample {
+                    texel_size: [1.0 / width as f32, 1.0 / height as f32],
+                    threshold: self.settings.threshold,
+                    first: (level == 0) as i32,
+                }),
+            );
+        } else if let Some(index) = self.passes.up.iter().position(|&other| other == pass) {
+            post::fullscreen_draw(
+                builder,
+                graph.extent(pass),
+                &self.upsample,
+                &self.up_sets[index],
+                Some(shaders::bloom_up_fs::Upsample {
+                    radius: self.settings.radius,
+                }),
+            );
+        } else {
+            return;
+        }
+
+        let last = self.passes.up.last().or(self.passes.down.last());
+        if last == Some(&pass) {
+            timestamp(builder, queries + 1, PipelineStage::BottomOfPipe);
+        }
+    }
+
+    // tempo de GPU da última vez que o command buffer da imagem rodou, None
+    // enquanto não terminou ou com o bloom desligado
+    pub fn gpu_time(&self, image_index: usize) -> Option<Duration> {
+        let query_pool = self.query_pool.as_ref()?;
+        if !self.settings.enabled {
+            return None;
+        }
+        let queries = 2 * image_index as u32;
+        let mut timestamps = [0u64; 2];
+        let available = query_pool
+            .get_results(
+                queries..queries + 2,
+                &mut timestamps,
+                QueryResultFlags::empty(),
+            )
+            .ok()?;
+        available.then(|| {
+            let ticks = timestamps[1].saturating_sub(timestamps[0]);
+            Duration::from_nanos((ticks as f64 * self.timestamp_period as f64) as u64)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_count_stops_at_one_pixel() {
+        assert_eq!(level_count([1920, 1080], MAX_LEVELS), 6);
+        assert_eq!(level_count([1920, 1080], 20), 10);
+        // 2x2 -> 1x1
+        assert_eq!(level_count([4, 8], MAX_LEVELS), 2);
+        assert_eq!(level_count([1, 1], MAX_LEVELS), 1);
+    }
+}
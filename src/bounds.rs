@@ -0,0 +1,236 @@
+use glam::{Mat4, Vec3};
+
+use crate::MyVertex;
+
+// caixa alinhada aos eixos
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Aabb {
+        points.into_iter().fold(
+            Aabb {
+                min: Vec3::splat(f32::INFINITY),
+                max: Vec3::splat(f32::NEG_INFINITY),
+            },
+            |aabb, point| Aabb {
+                min: aabb.min.min(point),
+                max: aabb.max.max(point),
+            },
+        )
+    }
+
+    pub fn from_vertices(vertices: &[MyVertex]) -> Aabb {
+        Self::from_points(vertices.iter().map(|vertex| Vec3::from(vertex.position)))
+    }
+
+    // menor caixa que contém as duas
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    // metade do tamanho em cada eixo
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        [0, 1, 2, 3, 4, 5, 6, 7].map(|i| {
+            Vec3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            )
+        })
+    }
+
+    // caixa que contém esta caixa depois de transformada (ex: pela matriz do
+    // objeto), pode ficar maior que a caixa justa da malha rotacionada
+    pub fn transform(&self, matrix: Mat4) -> Aabb {
+        let center = matrix.transform_point3(self.center());
+        let half_extents = self.half_extents();
+        // cada eixo da caixa nova é a soma dos eixos transformados em módulo
+        let extents = matrix.x_axis.truncate().abs() * half_extents.x
+            + matrix.y_axis.truncate().abs() * half_extents.y
+            + matrix.z_axis.truncate().abs() * half_extents.z;
+        Aabb {
+            min: center - extents,
+            max: center + extents,
+        }
+    }
+
+    // distância até a entrada do raio na caixa (0.0 se começa dentro),
+    // direction não precisa ser normalizado, o resultado é em múltiplos dele
+    pub fn ray_intersection(&self, origin: Vec3, direction: Vec3) -> Option<f32> {
+        // método dos slabs, divisão por 0 gera infinito que funciona aqui
+        let inverse = direction.recip();
+        let t1 = (self.min - origin) * inverse;
+        let t2 = (self.max - origin) * inverse;
+
+        let t_enter = t1.min(t2).max_element();
+        let t_exit = t1.max(t2).min_element();
+
+        if t_exit >= t_enter.max(0.0) {
+            Some(t_enter.max(0.0))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    // centrada na caixa, não é a menor esfera possível mas é rápida e
+    // sempre contém todos os vértices
+    pub fn from_vertices(vertices: &[MyVertex], aabb: &Aabb) -> BoundingSphere {
+        let center = aabb.center();
+        let radius = vertices
+            .iter()
+            .map(|vertex| center.distance_squared(Vec3::from(vertex.position)))
+            .fold(0.0, f32::max)
+            .sqrt();
+        BoundingSphere { center, radius }
+    }
+
+    // esfera que passa pelos cantos da caixa
+    pub fn from_aabb(aabb: &Aabb) -> BoundingSphere {
+        BoundingSphere {
+            center: aabb.center(),
+            radius: aabb.half_extents().length(),
+        }
+    }
+
+    // o raio é escalado pelo maior eixo para continuar contendo a malha
+    // com escala não uniforme
+    pub fn transform(&self, matrix: Mat4) -> BoundingSphere {
+        let scale = matrix
+            .x_axis
+            .truncate()
+            .length()
+            .max(matrix.y_axis.truncate().length())
+            .max(matrix.z_axis.truncate().length());
+        BoundingSphere {
+            center: matrix.transform_point3(self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_points_encloses_all_points() {
+        let aabb = Aabb::from_points([
+            Vec3::new(1.0, -2.0, 0.5),
+            Vec3::new(-1.0, 3.0, 0.0),
+            Vec3::new(0.0, 0.0, -4.0),
+        ]);
+        assert_eq!(aabb.min, Vec3::new(-1.0, -2.0, -4.0));
+        assert_eq!(aabb.max, Vec3::new(1.0, 3.0, 0.5));
+    }
+
+    #[test]
+    fn union_encloses_both_boxes() {
+        let a = Aabb {
+            min: Vec3::new(-1.0, 0.0, 0.0),
+            max: Vec3::new(0.0, 1.0, 1.0),
+        };
+        let b = Aabb {
+            min: Vec3::new(2.0, -3.0, 0.5),
+            max: Vec3::new(3.0, 0.5, 0.5),
+        };
+        let union = a.union(&b);
+        assert_eq!(union.min, Vec3::new(-1.0, -3.0, 0.0));
+        assert_eq!(union.max, Vec3::new(3.0, 1.0, 1.0));
+
+        let sphere = BoundingSphere::from_aabb(&union);
+        for corner in union.corners() {
+            assert!(corner.distance(sphere.center) <= sphere.radius + 1e-5);
+        }
+    }
+
+    #[test]
+    fn ray_intersection() {
+        let aabb = Aabb {
+            min: Vec3::splat(-1.0),
+            max: Vec3::splat(1.0),
+        };
+        assert_eq!(aabb.ray_intersection(Vec3::new(0.0, 0.0, -5.0), Vec3::Z), Some(4.0));
+        // direção não normalizada
+        assert_eq!(aabb.ray_intersection(Vec3::new(0.0, 0.0, -5.0), Vec3::Z * 2.0), Some(2.0));
+        // começando dentro
+        assert_eq!(aabb.ray_intersection(Vec3::ZERO, Vec3::X), Some(0.0));
+        // caixa atrás do raio
+        assert_eq!(aabb.ray_intersection(Vec3::new(0.0, 0.0, -5.0), -Vec3::Z), None);
+        // paralelo a um eixo e fora da caixa
+        assert_eq!(aabb.ray_intersection(Vec3::new(2.0, 0.0, -5.0), Vec3::Z), None);
+    }
+
+    #[test]
+    fn transform_encloses_transformed_corners() {
+        let aabb = Aabb {
+            min: Vec3::new(-1.0, -2.0, -0.5),
+            max: Vec3::new(1.0, 2.0, 0.5),
+        };
+        let matrix = Mat4::from_scale_rotation_translation(
+            Vec3::new(2.0, 1.0, 3.0),
+            glam::Quat::from_rotation_y(0.7),
+            Vec3::new(5.0, -1.0, 2.0),
+        );
+
+        let world = aabb.transform(matrix);
+        for corner in aabb.corners() {
+            let point = matrix.transform_point3(corner);
+            assert!(point.cmpge(world.min - 1e-5).all() && point.cmple(world.max + 1e-5).all());
+        }
+
+        // sem rotação a caixa continua justa
+        let translated = aabb.transform(Mat4::from_translation(Vec3::X));
+        assert!(translated.min.abs_diff_eq(aabb.min + Vec3::X, 1e-6));
+        assert!(translated.max.abs_diff_eq(aabb.max + Vec3::X, 1e-6));
+    }
+
+    #[test]
+    fn sphere_contains_vertices_after_transform() {
+        let vertices: Vec<MyVertex> = [[1.0, 0.0, 0.0], [-1.0, 2.0, 0.0], [0.0, 0.0, 3.0]]
+            .into_iter()
+            .map(|position| MyVertex {
+                position,
+                color: [0.0; 3],
+                normal: [0.0; 3],
+                texcoord: [0.0; 2],
+                tangent: [0.0; 4],
+            })
+            .collect();
+        let aabb = Aabb::from_vertices(&vertices);
+        let sphere = BoundingSphere::from_vertices(&vertices, &aabb);
+
+        let matrix = Mat4::from_scale_rotation_translation(
+            Vec3::new(1.0, 4.0, 2.0),
+            glam::Quat::from_rotation_x(1.2),
+            Vec3::new(0.0, 3.0, 0.0),
+        );
+        let world = sphere.transform(matrix);
+        for vertex in &vertices {
+            let point = matrix.transform_point3(Vec3::from(vertex.position));
+            assert!(point.distance(world.center) <= world.radius + 1e-5);
+        }
+    }
+}
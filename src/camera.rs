@@ -1,28 +1,74 @@
-use glam::{Mat4, Vec3};
+use glam::{Mat4, Vec2, Vec3, Vec4, Vec4Swizzles};
 
-use crate::keyboard::{Keyboard, Keys};
+use crate::{
+    bounds::BoundingSphere,
+    keyboard::{Keyboard, Keys},
+};
 
+// usado no lugar do far infinito quando a projeção é ortográfica
+const ORTHOGRAPHIC_MAX_FAR: f32 = 1000.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    // fov vertical em radianos
+    Perspective { fov: f32 },
+    // altura do volume visível em unidades do mundo
+    Orthographic { height: f32 },
+}
+
+// raio no espaço do mundo, direction é sempre normalizado
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+}
+
+impl Ray {
+    pub fn at(&self, distance: f32) -> Vec3 {
+        self.origin + self.direction * distance
+    }
+}
+
+#[derive(Clone)]
 pub struct Camera {
     pub projection: Mat4,
     pub view: Mat4,
     pub translation: Vec3,
     pub rotation: Vec3,
+    projection_mode: Projection,
+    aspect_ratio: f32,
+    z_near: f32,
+    // pode ser f32::INFINITY na perspectiva com reversed-Z
+    z_far: f32,
+    reversed_z: bool,
+    // deslocamento da imagem em NDC, usado pelo TAA (veja set_jitter)
+    jitter: Vec2,
     move_speed: f32,
     look_speed: f32,
 }
 
 impl Camera {
-    pub fn new(aspect_ratio: f32, position: Vec3, rotation: Vec3) -> Camera {
+    pub fn new(aspect_ratio: f32, position: Vec3, rotation: Vec3, reversed_z: bool) -> Camera {
         let mut camera = Camera {
             projection: Mat4::IDENTITY,
             view: Mat4::IDENTITY,
             translation: Vec3::ZERO,
             rotation: Vec3::ZERO,
+            projection_mode: Projection::Perspective { fov: 0.0 },
+            aspect_ratio,
+            z_near: 0.1,
+            z_far: 100.0,
+            reversed_z,
+            jitter: Vec2::ZERO,
             move_speed: 3.0,
             look_speed: 1.5,
         };
-        // 0.87266462599716 = 50 graus
-        camera.perspective_view(0.87266462599716, aspect_ratio, 0.1, 100.0);
+        let fov = 50f32.to_radians();
+        if reversed_z {
+            camera.reversed_perspective_view(fov, aspect_ratio, 0.1);
+        } else {
+            camera.perspective_view(fov, aspect_ratio, 0.1, 100.0);
+        }
 
         camera.set_view_yxz(
                 position,
@@ -61,11 +107,186 @@ impl Camera {
     }
 
     pub fn perspective_view(&mut self, fov: f32, aspect_ratio: f32, z_near: f32, z_far: f32) {
-        self.projection *= Mat4::perspective_lh(fov, aspect_ratio, z_near, z_far);
+        self.projection_mode = Projection::Perspective { fov };
+        self.aspect_ratio = aspect_ratio;
+        self.set_clip_planes(z_near, z_far);
+    }
+
+    // reversed-Z com o far no infinito, precisa do depth compare Greater
+    // e do depth limpo para 0.0 (veja Renderer::scene_graph)
+    pub fn reversed_perspective_view(&mut self, fov: f32, aspect_ratio: f32, z_near: f32) {
+        self.reversed_z = true;
+        self.perspective_view(fov, aspect_ratio, z_near, f32::INFINITY);
+    }
+
+    pub fn projection_mode(&self) -> Projection {
+        self.projection_mode
+    }
+
+    pub fn set_projection_mode(&mut self, projection_mode: Projection) {
+        self.projection_mode = projection_mode;
+        self.update_projection();
+    }
+
+    // só tem efeito na perspectiva
+    pub fn set_fov(&mut self, fov: f32) {
+        if let Projection::Perspective { .. } = self.projection_mode {
+            self.projection_mode = Projection::Perspective { fov };
+            self.update_projection();
+        }
+    }
+
+    // as duas valem para a perspectiva e para a ortográfica
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+        self.aspect_ratio = aspect_ratio;
+        self.update_projection();
+    }
+
+    pub fn set_clip_planes(&mut self, z_near: f32, z_far: f32) {
+        self.z_near = z_near;
+        self.z_far = z_far;
+        self.update_projection();
+    }
+
+    // move a imagem inteira por uma fração de pixel, um ponto que cairia
+    // em ndc passa a cair em ndc + jitter
+    pub fn set_jitter(&mut self, jitter: Vec2) {
+        self.jitter = jitter;
+        self.update_projection();
+    }
+
+    pub fn jitter(&self) -> Vec2 {
+        self.jitter
+    }
+
+    pub fn clip_planes(&self) -> (f32, f32) {
+        (self.z_near, self.z_far)
+    }
+
+    // recalcula a matriz a partir dos parâmetros, sempre substitui a
+    // projeção anterior
+    fn update_projection(&mut self) {
+        // no reversed-Z basta trocar o near com o far
+        let (near, far) = if self.reversed_z {
+            (self.z_far, self.z_near)
+        } else {
+            (self.z_near, self.z_far)
+        };
+
+        self.projection = match self.projection_mode {
+            Projection::Perspective { fov } if self.z_far.is_infinite() => {
+                if self.reversed_z {
+                    Mat4::perspective_infinite_reverse_lh(fov, self.aspect_ratio, self.z_near)
+                } else {
+                    Mat4::perspective_infinite_lh(fov, self.aspect_ratio, self.z_near)
+                }
+            }
+            Projection::Perspective { fov } => {
+                Mat4::perspective_lh(fov, self.aspect_ratio, near, far)
+            }
+            Projection::Orthographic { height } => {
+                // a ortográfica não aceita far infinito
+                let (near, far) = match (near.is_infinite(), far.is_infinite()) {
+                    (true, _) => (ORTHOGRAPHIC_MAX_FAR, far),
+                    (_, true) => (near, ORTHOGRAPHIC_MAX_FAR),
+                    _ => (near, far),
+                };
+                let half_height = height / 2.0;
+                let half_width = half_height * self.aspect_ratio;
+                Mat4::orthographic_lh(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    near,
+                    far,
+                )
+            }
+        };
+        // o x e o y do clip somam jitter * w, na perspectiva e na ortográfica
+        self.projection = Mat4::from_translation(self.jitter.extend(0.0)) * self.projection;
+    }
+
+    // planos do frustum no espaço do mundo na ordem
+    // [esquerda, direita, baixo, cima, near, far], cada plano é (normal, d)
+    // com a normal apontando para dentro, ou seja, um ponto p está dentro
+    // se normal.dot(p) + d >= 0 para todos os planos
+    pub fn frustum_planes(&self) -> [Vec4; 6] {
+        let matrix = (self.projection * self.view).transpose();
+        let (x, y, z, w) = (matrix.x_axis, matrix.y_axis, matrix.z_axis, matrix.w_axis);
+
+        // a profundidade da vulkan vai de 0 a w, com reversed-Z o near é o w
+        let (near, far) = if self.reversed_z {
+            (w - z, z)
+        } else {
+            (z, w - z)
+        };
+
+        [w + x, w - x, w + y, w - y, near, far].map(|plane| {
+            let length = plane.xyz().length();
+            // o far infinito gera um plano sem normal, que deixa tudo passar
+            if length > f32::EPSILON {
+                plane / length
+            } else {
+                Vec4::new(0.0, 0.0, 0.0, 1.0)
+            }
+        })
+    }
+
+    // gera um raio saindo da câmera que passa pelo pixel screen_position,
+    // com (0, 0) no canto superior esquerdo
+    pub fn screen_ray(&self, screen_position: Vec2, screen_size: Vec2) -> Ray {
+        let ndc = screen_position / screen_size * 2.0 - Vec2::ONE;
+        let inverse = (self.projection * self.view).inverse();
+
+        // 0.5 fica a uma distância finita mesmo com o far no infinito
+        let near_depth = if self.reversed_z { 1.0 } else { 0.0 };
+        let unproject = |depth: f32| {
+            let point = inverse * Vec4::new(ndc.x, ndc.y, depth, 1.0);
+            point.xyz() / point.w
+        };
+        let origin = unproject(near_depth);
+        let direction = (unproject(0.5) - origin).normalize();
+
+        Ray { origin, direction }
+    }
+
+    // direção para onde a câmera está olhando
+    pub fn forward(&self) -> Vec3 {
+        let (s1, c1) = self.rotation.y.sin_cos();
+        let (s2, c2) = self.rotation.x.sin_cos();
+        Vec3::new(c2 * s1, -s2, c1 * c2)
+    }
+
+    // distância do ponto até o plano da câmera, usada para ordenar os
+    // objetos transparentes (funciona também na ortográfica)
+    pub fn view_depth(&self, point: Vec3) -> f32 {
+        self.forward().dot(point - self.translation)
+    }
+
+    // "zoom to fit": afasta a câmera na direção em que ela está olhando até
+    // a esfera inteira caber na tela
+    pub fn frame_sphere(&mut self, sphere: &BoundingSphere) {
+        let distance = match self.projection_mode {
+            Projection::Perspective { fov } => {
+                // usa o menor dos fovs (vertical ou horizontal)
+                let half_fov = fov / 2.0;
+                let half_horizontal = (half_fov.tan() * self.aspect_ratio).atan();
+                sphere.radius / half_fov.min(half_horizontal).sin()
+            }
+            Projection::Orthographic { .. } => {
+                let height = 2.0 * sphere.radius * (1.0 / self.aspect_ratio).max(1.0);
+                self.projection_mode = Projection::Orthographic { height };
+                self.update_projection();
+                sphere.radius + self.z_near + 1.0
+            }
+        };
+        self.set_view_yxz(sphere.center - self.forward() * distance, self.rotation);
     }
 
     pub fn move_camera(&mut self, delta_time: f32, keys: &Keyboard ) {
         let mut rotate: Vec3 = Vec3::ZERO;
+        let mut zoom = 0.0;
 
         for command in keys.active.clone() {
             match command {
@@ -73,10 +294,24 @@ impl Camera {
                 Keys::RotateDown => rotate.x -= 1.0,
                 Keys::RotateRight => rotate.y += 1.0,
                 Keys::RotateLeft => rotate.y -= 1.0,
+                Keys::ZoomIn => zoom -= 1.0,
+                Keys::ZoomOut => zoom += 1.0,
                 _ => {}
             }
         }
 
+        if zoom != 0.0 {
+            let factor = 1.0 + zoom * delta_time;
+            match self.projection_mode {
+                Projection::Perspective { fov } => self.set_fov((fov * factor).clamp(0.1, 2.5)),
+                Projection::Orthographic { height } => {
+                    self.set_projection_mode(Projection::Orthographic {
+                        height: (height * factor).max(0.1),
+                    })
+                }
+            }
+        }
+
         if rotate.dot(rotate) > std::f32::EPSILON {
             self.rotation += self.look_speed * delta_time * rotate.normalize();
         }
@@ -108,3 +343,178 @@ impl Camera {
         self.set_view_yxz(self.translation, self.rotation);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera(reversed_z: bool) -> Camera {
+        Camera::new(16.0 / 9.0, Vec3::ZERO, Vec3::ZERO, reversed_z)
+    }
+
+    fn depth(camera: &Camera, point: Vec3) -> f32 {
+        let clip = camera.projection * camera.view * point.extend(1.0);
+        clip.z / clip.w
+    }
+
+    fn inside(planes: &[Vec4; 6], point: Vec3) -> bool {
+        planes.iter().all(|plane| plane.dot(point.extend(1.0)) >= 0.0)
+    }
+
+    #[test]
+    fn perspective_view_replaces_projection() {
+        let mut camera = camera(false);
+        camera.perspective_view(1.0, 1.5, 0.1, 50.0);
+        let once = camera.projection;
+        camera.perspective_view(1.0, 1.5, 0.1, 50.0);
+        assert!(camera.projection.abs_diff_eq(once, 1e-6));
+        assert!(camera.projection.abs_diff_eq(Mat4::perspective_lh(1.0, 1.5, 0.1, 50.0), 1e-6));
+    }
+
+    #[test]
+    fn clip_planes_map_to_depth_range() {
+        let mut camera = camera(false);
+        camera.perspective_view(1.0, 1.5, 0.5, 20.0);
+        assert!(depth(&camera, Vec3::new(0.0, 0.0, 0.5)).abs() < 1e-5);
+        assert!((depth(&camera, Vec3::new(0.0, 0.0, 20.0)) - 1.0).abs() < 1e-5);
+
+        // o reversed-Z também aceita um far finito
+        let mut camera = self::camera(true);
+        camera.perspective_view(1.0, 1.5, 0.5, 20.0);
+        assert!((depth(&camera, Vec3::new(0.0, 0.0, 0.5)) - 1.0).abs() < 1e-5);
+        assert!(depth(&camera, Vec3::new(0.0, 0.0, 20.0)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn setters_rebuild_projection() {
+        let mut camera = camera(false);
+        camera.set_fov(1.2);
+        assert_eq!(camera.projection_mode(), Projection::Perspective { fov: 1.2 });
+        assert!(camera
+            .projection
+            .abs_diff_eq(Mat4::perspective_lh(1.2, 16.0 / 9.0, 0.1, 100.0), 1e-6));
+
+        camera.set_aspect_ratio(2.0);
+        camera.set_clip_planes(0.5, 20.0);
+        assert_eq!(camera.projection_mode(), Projection::Perspective { fov: 1.2 });
+        assert!(camera.projection.abs_diff_eq(Mat4::perspective_lh(1.2, 2.0, 0.5, 20.0), 1e-6));
+    }
+
+    #[test]
+    fn setters_keep_orthographic_projection() {
+        let mut camera = camera(false);
+        camera.set_projection_mode(Projection::Orthographic { height: 4.0 });
+        camera.set_aspect_ratio(2.0);
+        camera.set_clip_planes(0.5, 20.0);
+        assert_eq!(camera.projection_mode(), Projection::Orthographic { height: 4.0 });
+        assert!(camera
+            .projection
+            .abs_diff_eq(Mat4::orthographic_lh(-4.0, 4.0, -2.0, 2.0, 0.5, 20.0), 1e-6));
+    }
+
+    #[test]
+    fn jitter_shifts_ndc_at_every_depth() {
+        let mut camera = camera(true);
+        let point = Vec3::new(0.3, -0.2, 5.0);
+        let ndc = |camera: &Camera, point: Vec3| {
+            let clip = camera.projection * camera.view * point.extend(1.0);
+            clip.xy() / clip.w
+        };
+        let jitter = Vec2::new(0.01, -0.02);
+        for far in [point, point * 4.0] {
+            let before = ndc(&camera, far);
+            camera.set_jitter(jitter);
+            assert!((ndc(&camera, far) - before - jitter).length() < 1e-5);
+            camera.set_jitter(Vec2::ZERO);
+            assert!((ndc(&camera, far) - before).length() < 1e-6);
+        }
+
+        // a troca de projeção mantém o jitter
+        camera.set_jitter(jitter);
+        camera.set_projection_mode(Projection::Orthographic { height: 4.0 });
+        let before = Mat4::orthographic_lh(-16.0 / 9.0 * 2.0, 16.0 / 9.0 * 2.0, -2.0, 2.0, 0.1, 1000.0);
+        let clip = before * point.extend(1.0);
+        assert!((ndc(&camera, point) - clip.xy() / clip.w - jitter).length() < 1e-5);
+    }
+
+    #[test]
+    fn orthographic_keeps_size_with_distance() {
+        let mut camera = Camera::new(1.0, Vec3::ZERO, Vec3::ZERO, false);
+        camera.set_projection_mode(Projection::Orthographic { height: 4.0 });
+        // set_fov não muda a ortográfica
+        camera.set_fov(0.3);
+        assert_eq!(camera.projection_mode(), Projection::Orthographic { height: 4.0 });
+
+        let near = camera.projection * Vec4::new(1.0, 2.0, 1.0, 1.0);
+        let far = camera.projection * Vec4::new(1.0, 2.0, 90.0, 1.0);
+        assert!((near.x - 0.5).abs() < 1e-6 && (near.y - 1.0).abs() < 1e-6);
+        assert!((far.x - 0.5).abs() < 1e-6 && (far.y - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn frustum_planes_contain_visible_points() {
+        for reversed_z in [false, true] {
+            let camera = camera(reversed_z);
+            let planes = camera.frustum_planes();
+            assert!(inside(&planes, Vec3::new(0.0, 0.0, 5.0)));
+            assert!(!inside(&planes, Vec3::new(0.0, 0.0, -5.0)));
+            assert!(!inside(&planes, Vec3::new(50.0, 0.0, 5.0)));
+            assert!(!inside(&planes, Vec3::new(0.0, -50.0, 5.0)));
+            assert!(!inside(&planes, Vec3::new(0.0, 0.0, 0.05)));
+        }
+
+        let far_planes = camera(false).frustum_planes();
+        assert!(!inside(&far_planes, Vec3::new(0.0, 0.0, 150.0)));
+    }
+
+    #[test]
+    fn frame_sphere_fits_sphere_on_screen() {
+        let sphere = BoundingSphere {
+            center: Vec3::new(4.0, -1.0, 7.0),
+            radius: 2.5,
+        };
+        for aspect_ratio in [0.5, 2.0] {
+            let mut camera = Camera::new(aspect_ratio, Vec3::ZERO, Vec3::new(0.3, 1.0, 0.0), false);
+            camera.frame_sphere(&sphere);
+
+            let planes = camera.frustum_planes();
+            for plane in &planes[..4] {
+                assert!(plane.dot(sphere.center.extend(1.0)) >= sphere.radius - 1e-3);
+            }
+            // pelo menos um dos lados encosta na esfera
+            assert!(planes[..4]
+                .iter()
+                .any(|plane| (plane.dot(sphere.center.extend(1.0)) - sphere.radius).abs() < 1e-3));
+        }
+    }
+
+    #[test]
+    fn screen_ray_goes_through_pixel() {
+        for reversed_z in [false, true] {
+            let mut camera = camera(reversed_z);
+            camera.set_view_yxz(Vec3::new(1.0, 2.0, -3.0), Vec3::ZERO);
+            let size = Vec2::new(800.0, 600.0);
+
+            let center = camera.screen_ray(size / 2.0, size);
+            assert!(center.direction.abs_diff_eq(Vec3::Z, 1e-4));
+            assert!((center.origin - Vec3::new(1.0, 2.0, -3.0)).length() < 0.2);
+
+            // o ponto no raio tem que projetar de volta no mesmo pixel
+            let pixel = Vec2::new(100.0, 450.0);
+            let ray = camera.screen_ray(pixel, size);
+            let clip = camera.projection * camera.view * ray.at(10.0).extend(1.0);
+            let ndc = clip.xy() / clip.w;
+            assert!(((ndc + Vec2::ONE) / 2.0 * size).abs_diff_eq(pixel, 1e-2));
+        }
+    }
+
+    #[test]
+    fn view_depth_is_measured_along_forward() {
+        let mut camera = camera(true);
+        camera.set_view_yxz(Vec3::new(0.0, 0.0, -3.0), Vec3::ZERO);
+        assert_eq!(camera.view_depth(Vec3::new(0.0, 0.0, 2.0)), 5.0);
+        // de lado fica na mesma profundidade, atrás fica negativo
+        assert_eq!(camera.view_depth(Vec3::new(4.0, 1.0, 2.0)), 5.0);
+        assert!(camera.view_depth(Vec3::new(0.0, 0.0, -4.0)) < 0.0);
+    }
+}
@@ -0,0 +1,180 @@
+use std::sync::Arc;
+
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        PrimaryAutoCommandBuffer,
+    },
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, DescriptorSetsCollection,
+        PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    pipeline::{
+        compute::ComputePipelineCreateInfo, layout::PipelineDescriptorSetLayoutCreateInfo,
+        ComputePipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo,
+    },
+    shader::ShaderModule,
+    sync::Sharing,
+};
+
+use crate::device::GPU;
+
+// um compute shader pronto para ser despachado, tanto num command buffer
+// de gráficos quanto num da queue de compute (veja command_buffer)
+pub struct ComputeKernel {
+    pub pipeline: Arc<ComputePipeline>,
+    // o mesmo local_size do shader, o vulkano não tira isso do SPIR-V
+    pub local_size: [u32; 3],
+}
+
+impl ComputeKernel {
+    // o layout é tirado do próprio shader
+    pub fn new(device: &GPU, shader: Arc<ShaderModule>, local_size: [u32; 3]) -> ComputeKernel {
+        let stage = PipelineShaderStageCreateInfo::new(shader.entry_point("main").unwrap());
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+                .into_pipeline_layout_create_info(device.clone())
+                .unwrap(),
+        )
+        .unwrap();
+        let pipeline = ComputePipeline::new(
+            device.clone(),
+            None,
+            ComputePipelineCreateInfo::stage_layout(stage, layout),
+        )
+        .unwrap();
+        ComputeKernel {
+            pipeline,
+            local_size,
+        }
+    }
+
+    // buffers e imagens de um set do layout
+    pub fn set(
+        &self,
+        descriptor_set_allocator: &StandardDescriptorSetAllocator,
+        index: usize,
+        writes: impl IntoIterator<Item = WriteDescriptorSet>,
+    ) -> Arc<PersistentDescriptorSet> {
+        PersistentDescriptorSet::new(
+            descriptor_set_allocator,
+            self.pipeline.layout().set_layouts()[index].clone(),
+            writes,
+            [],
+        )
+        .unwrap()
+    }
+
+    // liga a pipeline e os sets a partir do 0
+    pub fn bind(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        sets: impl DescriptorSetsCollection,
+    ) {
+        builder
+            .bind_pipeline_compute(self.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.pipeline.layout().clone(),
+                0,
+                sets,
+            )
+            .unwrap();
+    }
+
+    pub fn push_constants(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        push_constants: impl BufferContents,
+    ) {
+        builder
+            .push_constants(self.pipeline.layout().clone(), 0, push_constants)
+            .unwrap();
+    }
+
+    // grupos suficientes para size invocações, o shader ignora o que passa
+    // do tamanho
+    pub fn dispatch(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        size: [u32; 3],
+    ) {
+        builder
+            .dispatch(group_count(size, self.local_size))
+            .unwrap();
+    }
+}
+
+pub fn group_count(size: [u32; 3], local_size: [u32; 3]) -> [u32; 3] {
+    [0, 1, 2].map(|axis| size[axis].div_ceil(local_size[axis]))
+}
+
+// command buffer da queue de compute, a sincronização com os gráficos fica
+// com quem executa (then_execute na compute_queue e then_signal_semaphore
+// antes do command buffer da cena)
+pub fn command_buffer(
+    device: &GPU,
+    command_buffer_allocator: &StandardCommandBufferAllocator,
+    record: impl FnOnce(&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>),
+) -> Arc<PrimaryAutoCommandBuffer> {
+    let mut builder = AutoCommandBufferBuilder::primary(
+        command_buffer_allocator,
+        device.compute_queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+    record(&mut builder);
+    builder.build().unwrap()
+}
+
+// buffer escrito na queue de compute e lido na de gráficos, com async
+// compute as duas famílias precisam ter acesso a ele
+pub fn shared_buffer<T, I>(device: &GPU, usage: BufferUsage, data: I) -> Subbuffer<[T]>
+where
+    T: BufferContents,
+    I: IntoIterator<Item = T>,
+    I::IntoIter: ExactSizeIterator,
+{
+    let sharing = if device.async_compute() {
+        Sharing::Concurrent(
+            [&device.graphics_queue, &device.compute_queue]
+                .map(|queue| queue.queue_family_index())
+                .into_iter()
+                .collect(),
+        )
+    } else {
+        Sharing::Exclusive
+    };
+    Buffer::from_iter(
+        device.memory_allocator.clone(),
+        BufferCreateInfo {
+            usage,
+            sharing,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        data,
+    )
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_count_covers_partial_groups() {
+        assert_eq!(group_count([100, 64, 6], [8, 8, 1]), [13, 8, 6]);
+        assert_eq!(group_count([1000, 1, 1], [64, 1, 1]), [16, 1, 1]);
+        assert_eq!(group_count([0, 1, 1], [64, 1, 1]), [0, 1, 1]);
+    }
+}
@@ -0,0 +1,183 @@
+use vulkano::{
+    format::Format,
+    swapchain::{ColorSpace, CompositeAlpha, PresentMode},
+};
+
+// como a cena é iluminada (veja Renderer::scene_graph)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderPath {
+    // um subpass só, cada material calcula toda a luz
+    Forward,
+    // os opacos escrevem o G-buffer, a luz é calculada uma vez por pixel e
+    // os transparentes são desenhados em forward no final, sem MSAA
+    Deferred,
+}
+
+// configurações que o usuário escolhe antes de criar o Renderer
+pub struct RendererConfig {
+    // modo de apresentação desejado, se a superfície não suportar
+    // é usado o mais próximo (veja select_present_mode)
+    //
+    // Fifo = vsync, Mailbox = sem tearing e sem esperar o vsync,
+    // Immediate = sem vsync (pode ter tearing)
+    pub present_mode: PresentMode,
+    // prefere formatos sRGB para a swapchain
+    pub prefer_srgb: bool,
+    // quantidade de imagens da swapchain, 3 = triple buffering
+    pub image_count: u32,
+    // amostras de MSAA (1, 2, 4 ou 8), limitado pelo que a GPU suporta
+    pub msaa_samples: u32,
+    // formatos de profundidade em ordem de preferência, o primeiro
+    // suportado pela GPU é usado (D16_UNORM sempre é suportado)
+    pub depth_formats: Vec<Format>,
+    // reversed-Z: profundidade 1.0 no near e 0.0 no infinito, distribui
+    // melhor a precisão do float e evita z-fighting em cenas grandes
+    pub reversed_z: bool,
+    pub render_path: RenderPath,
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        Self {
+            present_mode: PresentMode::Fifo,
+            prefer_srgb: true,
+            image_count: 3,
+            msaa_samples: 4,
+            depth_formats: vec![
+                Format::D32_SFLOAT,
+                Format::D24_UNORM_S8_UINT,
+                Format::D16_UNORM,
+            ],
+            reversed_z: true,
+            render_path: RenderPath::Forward,
+        }
+    }
+}
+
+impl RendererConfig {
+    // atalho para ligar/desligar o vsync sem ter que escolher o present mode
+    pub fn with_vsync(mut self, vsync: bool) -> Self {
+        self.present_mode = if vsync {
+            PresentMode::Fifo
+        } else {
+            PresentMode::Immediate
+        };
+        self
+    }
+
+    pub fn select_present_mode(&self, supported: &[PresentMode]) -> PresentMode {
+        // ordem de preferência para cada modo pedido, Fifo é sempre suportado
+        // então fica no final de todas as listas
+        let preference: &[PresentMode] = match self.present_mode {
+            PresentMode::Immediate => &[PresentMode::Immediate, PresentMode::Mailbox],
+            PresentMode::Mailbox => &[PresentMode::Mailbox],
+            PresentMode::FifoRelaxed => &[PresentMode::FifoRelaxed],
+            _ => &[],
+        };
+
+        preference
+            .iter()
+            .copied()
+            .find(|mode| supported.contains(mode))
+            .unwrap_or(PresentMode::Fifo)
+    }
+
+    pub fn select_format(&self, formats: &[(Format, ColorSpace)]) -> (Format, ColorSpace) {
+        let srgb = formats.iter().copied().find(|(format, color_space)| {
+            *color_space == ColorSpace::SrgbNonLinear
+                && matches!(format, Format::B8G8R8A8_SRGB | Format::R8G8B8A8_SRGB)
+        });
+
+        match srgb {
+            Some(format) if self.prefer_srgb => format,
+            _ => formats[0],
+        }
+    }
+
+    pub fn select_image_count(&self, min_image_count: u32, max_image_count: Option<u32>) -> u32 {
+        // max_image_count == None significa que não tem limite
+        let count = self.image_count.max(min_image_count);
+        match max_image_count {
+            Some(max) => count.min(max),
+            None => count,
+        }
+    }
+}
+
+// o que foi realmente escolhido na criação da swapchain
+#[derive(Debug, Clone, Copy)]
+pub struct SwapchainSelection {
+    pub present_mode: PresentMode,
+    pub format: Format,
+    pub color_space: ColorSpace,
+    pub image_count: u32,
+    pub composite_alpha: CompositeAlpha,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(present_mode: PresentMode) -> RendererConfig {
+        RendererConfig {
+            present_mode,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn present_mode_falls_back_to_fifo() {
+        let fifo_only = [PresentMode::Fifo];
+        assert_eq!(
+            config(PresentMode::Mailbox).select_present_mode(&fifo_only),
+            PresentMode::Fifo
+        );
+        assert_eq!(
+            config(PresentMode::Immediate).select_present_mode(&fifo_only),
+            PresentMode::Fifo
+        );
+
+        // sem Immediate o Mailbox também evita esperar o vsync
+        let supported = [PresentMode::Fifo, PresentMode::Mailbox];
+        assert_eq!(
+            config(PresentMode::Immediate).select_present_mode(&supported),
+            PresentMode::Mailbox
+        );
+        assert_eq!(
+            config(PresentMode::Mailbox).select_present_mode(&supported),
+            PresentMode::Mailbox
+        );
+    }
+
+    #[test]
+    fn format_prefers_srgb() {
+        let formats = [
+            (Format::B8G8R8A8_UNORM, ColorSpace::SrgbNonLinear),
+            (Format::B8G8R8A8_SRGB, ColorSpace::SrgbNonLinear),
+        ];
+        let config = RendererConfig::default();
+        assert_eq!(config.select_format(&formats), formats[1]);
+
+        let linear = RendererConfig {
+            prefer_srgb: false,
+            ..Default::default()
+        };
+        assert_eq!(linear.select_format(&formats), formats[0]);
+        // sem sRGB fica com o primeiro da superfície
+        assert_eq!(config.select_format(&formats[..1]), formats[0]);
+    }
+
+    #[test]
+    fn image_count_is_clamped_to_surface() {
+        let config = RendererConfig::default();
+        assert_eq!(config.select_image_count(2, Some(8)), 3);
+        assert_eq!(config.select_image_count(4, Some(8)), 4);
+        assert_eq!(config.select_image_count(1, Some(2)), 2);
+        // max_image_count == 0 no Vulkan chega como None, sem limite
+        let config = RendererConfig {
+            image_count: 16,
+            ..Default::default()
+        };
+        assert_eq!(config.select_image_count(2, None), 16);
+    }
+}
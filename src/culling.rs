@@ -0,0 +1,153 @@
+use glam::{Mat4, Vec4, Vec4Swizzles};
+
+use crate::{
+    bounds::{Aabb, BoundingSphere},
+    camera::Camera,
+    object::{InstancedObject, Model, Object},
+    InstanceData,
+};
+
+// planos extraídos de camera.projection * camera.view
+// (veja Camera::frustum_planes)
+pub struct Frustum {
+    planes: [Vec4; 6],
+}
+
+// contadores do último frame
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CullingStats {
+    pub drawn: usize,
+    pub culled: usize,
+}
+
+impl Frustum {
+    pub fn from_camera(camera: &Camera) -> Frustum {
+        Frustum {
+            planes: camera.frustum_planes(),
+        }
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.dot(sphere.center.extend(1.0)) >= -sphere.radius)
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let center = aabb.center().extend(1.0);
+        let half_extents = aabb.half_extents();
+        // distância do canto da caixa mais para dentro de cada plano
+        self.planes
+            .iter()
+            .all(|plane| plane.dot(center) + plane.xyz().abs().dot(half_extents) >= 0.0)
+    }
+
+    // a esfera é mais barata, só testa a caixa quando a esfera passa
+    pub fn is_model_visible(&self, model: &Model, matrix: Mat4) -> bool {
+        self.intersects_sphere(&model.sphere.transform(matrix))
+            && self.intersects_aabb(&model.aabb.transform(matrix))
+    }
+
+    pub fn is_visible(&self, object: &Object) -> bool {
+        self.intersects_sphere(&object.world_sphere()) && self.intersects_aabb(&object.world_aabb())
+    }
+
+    // índices dos objetos que precisam ser desenhados
+    pub fn cull(&self, objects: &[Object]) -> (Vec<usize>, CullingStats) {
+        let visible: Vec<usize> = (0..objects.len())
+            .filter(|&index| self.is_visible(&objects[index]))
+            .collect();
+        let stats = CullingStats {
+            drawn: visible.len(),
+            culled: objects.len() - visible.len(),
+        };
+        (visible, stats)
+    }
+
+    // dados das instâncias visíveis, prontos para o buffer de instâncias
+    pub fn cull_instances(&self, instanced: &InstancedObject) -> (Vec<InstanceData>, CullingStats) {
+        let visible: Vec<InstanceData> = instanced
+            .instances
+            .iter()
+            .filter(|instance| self.is_model_visible(&instanced.model, instance.matrix()))
+            .map(|instance| instance.instance_data(&instanced.material))
+            .collect();
+        let stats = CullingStats {
+            drawn: visible.len(),
+            culled: instanced.instances.len() - visible.len(),
+        };
+        (visible, stats)
+    }
+}
+
+impl std::ops::AddAssign for CullingStats {
+    fn add_assign(&mut self, other: CullingStats) {
+        self.drawn += other.drawn;
+        self.culled += other.culled;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+    use crate::object::{test_cube, Instance};
+
+    #[test]
+    fn culls_objects_outside_frustum() {
+        let camera = Camera::new(1.0, Vec3::new(0.0, 0.0, -10.0), Vec3::ZERO, true);
+        let frustum = Frustum::from_camera(&camera);
+        let objects = [
+            test_cube(Vec3::ZERO),
+            // atrás da câmera
+            test_cube(Vec3::new(0.0, 0.0, -20.0)),
+            // muito para o lado
+            test_cube(Vec3::new(30.0, 0.0, 0.0)),
+            // só encostando na borda da tela
+            test_cube(Vec3::new(5.0, 0.0, 0.0)),
+        ];
+
+        let (visible, stats) = frustum.cull(&objects);
+        assert_eq!(visible, vec![0, 3]);
+        assert_eq!(stats, CullingStats { drawn: 2, culled: 2 });
+    }
+
+    #[test]
+    fn culls_instances_individually() {
+        let camera = Camera::new(1.0, Vec3::new(0.0, 0.0, -10.0), Vec3::ZERO, true);
+        let frustum = Frustum::from_camera(&camera);
+        let instanced = InstancedObject {
+            model: Model::load("obj/cube.obj"),
+            instances: [0.0, 30.0, -2.0, 100.0]
+                .into_iter()
+                .map(|x| Instance {
+                    translation: Vec3::new(x, 0.0, 0.0),
+                    ..Default::default()
+                })
+                .collect(),
+            material: Default::default(),
+        };
+
+        let (visible, stats) = frustum.cull_instances(&instanced);
+        assert_eq!(stats, CullingStats { drawn: 2, culled: 2 });
+        assert_eq!(visible[1].instance_model[3][0], -2.0);
+    }
+
+    #[test]
+    fn aabb_test_rejects_what_sphere_accepts() {
+        let camera = Camera::new(1.0, Vec3::ZERO, Vec3::ZERO, false);
+        let frustum = Frustum::from_camera(&camera);
+        // caixa comprida fora do frustum cuja esfera alcança o frustum
+        let aabb = Aabb {
+            min: Vec3::new(-50.0, 30.0, 9.0),
+            max: Vec3::new(50.0, 31.0, 11.0),
+        };
+        let sphere = BoundingSphere {
+            center: aabb.center(),
+            radius: aabb.half_extents().length(),
+        };
+        assert!(frustum.intersects_sphere(&sphere));
+        assert!(!frustum.intersects_aabb(&aabb));
+    }
+}
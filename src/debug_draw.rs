@@ -0,0 +1,231 @@
+use std::f32::consts::TAU;
+
+use glam::{Mat4, Vec3};
+use vulkano::{buffer::BufferContents, pipeline::graphics::vertex_input::Vertex};
+
+use crate::{
+    bounds::{Aabb, BoundingSphere},
+    camera::Camera,
+};
+
+// distância até onde o frustum é desenhado quando o far é infinito
+const FRUSTUM_MAX_FAR: f32 = 50.0;
+// segmentos usados nos círculos das esferas
+const CIRCLE_SEGMENTS: u32 = 32;
+
+#[derive(BufferContents, Vertex, Clone, Copy, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct DebugVertex {
+    #[format(R32G32B32_SFLOAT)]
+    position: [f32; 3],
+    #[format(R32G32B32_SFLOAT)]
+    color: [f32; 3],
+}
+
+// desenho de linhas no modo imediato: tudo que é pedido durante o frame é
+// desenhado por cima da cena (veja Renderer::create_command_buffer) e depois
+// apagado com clear
+//
+// as linhas são guardadas em pares de vértices, em coordenadas do mundo
+pub struct DebugDraw {
+    // linhas novas usam o depth test, sem ele ficam por cima de tudo
+    pub depth_test: bool,
+    pub lines: Vec<DebugVertex>,
+    pub overlay: Vec<DebugVertex>,
+}
+
+impl Default for DebugDraw {
+    fn default() -> DebugDraw {
+        DebugDraw {
+            depth_test: true,
+            lines: vec![],
+            overlay: vec![],
+        }
+    }
+}
+
+impl DebugDraw {
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.overlay.clear();
+    }
+
+    pub fn line(&mut self, from: Vec3, to: Vec3, color: Vec3) {
+        let lines = if self.depth_test {
+            &mut self.lines
+        } else {
+            &mut self.overlay
+        };
+        for position in [from, to] {
+            lines.push(DebugVertex {
+                position: position.to_array(),
+                color: color.to_array(),
+            });
+        }
+    }
+
+    // arestas de uma caixa com os cantos na ordem de Aabb::corners, o bit 0
+    // do índice é o x, o bit 1 o y e o bit 2 o z
+    fn box_edges(&mut self, corners: [Vec3; 8], color: Vec3) {
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corners[i], corners[i | bit], color);
+                }
+            }
+        }
+    }
+
+    pub fn aabb(&mut self, aabb: &Aabb, color: Vec3) {
+        self.box_edges(aabb.corners(), color);
+    }
+
+    // caixa no espaço local transformada pela matriz, fica justa mesmo
+    // rotacionada (ao contrário de Aabb::transform)
+    pub fn oriented_box(&mut self, aabb: &Aabb, matrix: Mat4, color: Vec3) {
+        self.box_edges(aabb.corners().map(|corner| matrix.transform_point3(corner)), color);
+    }
+
+    pub fn circle(&mut self, center: Vec3, normal: Vec3, radius: f32, color: Vec3) {
+        let (u, v) = normal.normalize().any_orthonormal_pair();
+        let point = |i: u32| {
+            let (sin, cos) = (i as f32 / CIRCLE_SEGMENTS as f32 * TAU).sin_cos();
+            center + (u * cos + v * sin) * radius
+        };
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    // um círculo em cada plano dos eixos
+    pub fn sphere(&mut self, sphere: &BoundingSphere, color: Vec3) {
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            self.circle(sphere.center, axis, sphere.radius, color);
+        }
+    }
+
+    // eixos x, y e z da matriz em vermelho, verde e azul
+    pub fn axes(&mut self, matrix: Mat4, size: f32) {
+        let origin = matrix.transform_point3(Vec3::ZERO);
+        for (axis, color) in [(Vec3::X, Vec3::X), (Vec3::Y, Vec3::Y), (Vec3::Z, Vec3::Z)] {
+            self.line(origin, matrix.transform_point3(axis * size), color);
+        }
+    }
+
+    pub fn arrow(&mut self, from: Vec3, to: Vec3, color: Vec3) {
+        self.line(from, to, color);
+
+        let direction = to - from;
+        let length = direction.length();
+        if length <= f32::EPSILON {
+            return;
+        }
+        // ponta com quatro riscos, do tamanho de 1/5 da flecha
+        let direction = direction / length;
+        let (u, v) = direction.any_orthonormal_pair();
+        let head = length * 0.2;
+        for side in [u, -u, v, -v] {
+            self.line(to, to - direction * head + side * head * 0.5, color);
+        }
+    }
+
+    // grade no plano xz com divisions x divisions quadrados
+    pub fn grid(&mut self, center: Vec3, size: f32, divisions: u32, color: Vec3) {
+        let divisions = divisions.max(1);
+        let half = size / 2.0;
+        for i in 0..=divisions {
+            let offset = i as f32 / divisions as f32 * size - half;
+            self.line(
+                center + Vec3::new(offset, 0.0, -half),
+                center + Vec3::new(offset, 0.0, half),
+                color,
+            );
+            self.line(
+                center + Vec3::new(-half, 0.0, offset),
+                center + Vec3::new(half, 0.0, offset),
+                color,
+            );
+        }
+    }
+
+    // volume que a câmera enxerga, com far infinito vai até FRUSTUM_MAX_FAR
+    pub fn frustum(&mut self, camera: &Camera, color: Vec3) {
+        self.box_edges(frustum_corners(camera), color);
+    }
+}
+
+// cantos do frustum na mesma ordem de Aabb::corners, com o near no z = 0
+pub fn frustum_corners(camera: &Camera) -> [Vec3; 8] {
+    let (near, far) = camera.clip_planes();
+    let far = far.min(FRUSTUM_MAX_FAR);
+    // profundidade de cada plano depois da projeção, funciona com
+    // reversed-Z e com far infinito
+    let depth = |distance: f32| {
+        camera
+            .projection
+            .project_point3(Vec3::new(0.0, 0.0, distance))
+            .z
+    };
+    let depths = [depth(near), depth(far)];
+    let inverse = (camera.projection * camera.view).inverse();
+
+    [0, 1, 2, 3, 4, 5, 6, 7].map(|i| {
+        let ndc = Vec3::new(
+            if i & 1 == 0 { -1.0 } else { 1.0 },
+            if i & 2 == 0 { -1.0 } else { 1.0 },
+            depths[i >> 2],
+        );
+        inverse.project_point3(ndc)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn box_has_twelve_edges() {
+        let mut debug = DebugDraw::default();
+        debug.aabb(
+            &Aabb {
+                min: Vec3::ZERO,
+                max: Vec3::ONE,
+            },
+            Vec3::ONE,
+        );
+        assert_eq!(debug.lines.len(), 24);
+        // cada aresta tem o tamanho de um lado
+        for line in debug.lines.chunks_exact(2) {
+            let length = Vec3::from(line[0].position).distance(Vec3::from(line[1].position));
+            assert_eq!(length, 1.0);
+        }
+    }
+
+    #[test]
+    fn depth_test_chooses_the_list() {
+        let mut debug = DebugDraw::default();
+        debug.line(Vec3::ZERO, Vec3::X, Vec3::ONE);
+        debug.depth_test = false;
+        debug.arrow(Vec3::ZERO, Vec3::Y, Vec3::ONE);
+        assert_eq!(debug.lines.len(), 2);
+        assert_eq!(debug.overlay.len(), 10);
+
+        debug.clear();
+        assert!(debug.lines.is_empty() && debug.overlay.is_empty());
+    }
+
+    #[test]
+    fn frustum_corners_sit_on_the_clip_planes() {
+        for reversed_z in [false, true] {
+            let camera = Camera::new(1.0, Vec3::new(0.0, 0.0, -3.0), Vec3::ZERO, reversed_z);
+            let (near, far) = camera.clip_planes();
+            let far = far.min(FRUSTUM_MAX_FAR);
+            let corners = frustum_corners(&camera);
+            for (i, corner) in corners.iter().enumerate() {
+                let distance = corner.z + 3.0;
+                let expected = if i < 4 { near } else { far };
+                assert!((distance - expected).abs() < expected * 1e-3, "{corner}");
+            }
+        }
+    }
+}
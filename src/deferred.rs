@@ -0,0 +1,225 @@
+use std::sync::Arc;
+
+use glam::Vec2;
+use vulkano::{
+    buffer::Subbuffer,
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    image::view::ImageView,
+    pipeline::{
+        graphics::{
+            color_blend::{AttachmentBlend, ColorBlendAttachmentState, ColorBlendState},
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::{CullMode, FrontFace, RasterizationState},
+            vertex_input::{Vertex, VertexDefinition, VertexInputState},
+            viewport::{Viewport, ViewportState},
+            GraphicsPipelineCreateInfo,
+        },
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+        GraphicsPipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo,
+    },
+    render_pass::Subpass,
+    shader::ShaderModule,
+};
+
+use crate::{
+    camera::Camera, device::GPU, light::LightData, prerender::MeshBuffers, primitives, shaders,
+    MyVertex,
+};
+
+// luz do deferred, gravada entre o G-buffer e os transparentes: uma passada
+// em tela cheia com a luz direcional e a ambiente e um volume por luz
+// pontual somado por cima
+pub struct Deferred {
+    pub lighting: Arc<GraphicsPipeline>,
+    pub volumes: Arc<GraphicsPipeline>,
+    // o mesmo para as duas pipelines, o set 0 é o do fs sem as luzes
+    pub layout: Arc<PipelineLayout>,
+    sphere: MeshBuffers,
+    // G-buffer e profundidade como input attachments, no set 1
+    inputs: Arc<PersistentDescriptorSet>,
+}
+
+impl Deferred {
+    // subpass é o da pass de luz do grafo (veja Renderer::scene_graph),
+    // gbuffer é albedo, normal e material e depth a view só com a
+    // profundidade
+    pub fn new(
+        device: &GPU,
+        subpass: Subpass,
+        gbuffer: [Arc<ImageView>; 3],
+        depth: Arc<ImageView>,
+        viewport: &Viewport,
+        descriptor_set_allocator: &StandardDescriptorSetAllocator,
+    ) -> Deferred {
+        let entry_point = |module: Arc<ShaderModule>| module.entry_point("main").unwrap();
+        let light_vs = entry_point(
+            shaders::post_vs::load(device.clone()).expect("failed to create shader module"),
+        );
+        let light_fs = entry_point(
+            shaders::deferred_light_fs::load(device.clone())
+                .expect("failed to create shader module"),
+        );
+        let volume_vs = entry_point(
+            shaders::deferred_volume_vs::load(device.clone())
+                .expect("failed to create shader module"),
+        );
+        let volume_fs = entry_point(
+            shaders::deferred_volume_fs::load(device.clone())
+                .expect("failed to create shader module"),
+        );
+
+        let lighting_stages = [
+            PipelineShaderStageCreateInfo::new(light_vs),
+            PipelineShaderStageCreateInfo::new(light_fs),
+        ];
+        let volume_stages = [
+            PipelineShaderStageCreateInfo::new(volume_vs.clone()),
+            PipelineShaderStageCreateInfo::new(volume_fs),
+        ];
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages(
+                lighting_stages.iter().chain(&volume_stages),
+            )
+            .into_pipeline_layout_create_info(device.clone())
+            .unwrap(),
+        )
+        .unwrap();
+
+        let pipeline = |stages: [PipelineShaderStageCreateInfo; 2],
+                        vertex_input_state: VertexInputState,
+                        rasterization_state: RasterizationState,
+                        blend: Option<AttachmentBlend>| {
+            GraphicsPipeline::new(
+                device.clone(),
+                None,
+                GraphicsPipelineCreateInfo {
+                    stages: stages.into_iter().collect(),
+                    vertex_input_state: Some(vertex_input_state),
+                    input_assembly_state: Some(InputAssemblyState::default()),
+                    viewport_state: Some(ViewportState {
+                        viewports: [viewport.clone()].into_iter().collect(),
+                        ..Default::default()
+                    }),
+                    rasterization_state: Some(rasterization_state),
+                    multisample_state: Some(MultisampleState::default()),
+                    color_blend_state: Some(ColorBlendState::with_attachment_states(
+                        subpass.num_color_attachments(),
+                        ColorBlendAttachmentState {
+                            blend,
+                            ..Default::default()
+                        },
+                    )),
+                    subpass: Some(subpass.clone().into()),
+                    ..GraphicsPipelineCreateInfo::layout(layout.clone())
+                },
+            )
+            .unwrap()
+        };
+
+        let lighting = pipeline(
+            lighting_stages,
+            VertexInputState::default(),
+            RasterizationState::default(),
+            None,
+        );
+        // só as faces de trás, assim o volume continua desenhado com a câmera
+        // dentro dele, sem depth test porque o depth buffer é uma entrada
+        let volume_input = [MyVertex::per_vertex(), LightData::per_instance()]
+            .definition(&volume_vs.info().input_interface)
+            .unwrap();
+        let volumes = pipeline(
+            volume_stages,
+            volume_input,
+            RasterizationState {
+                cull_mode: CullMode::Front,
+                front_face: FrontFace::CounterClockwise,
+                ..Default::default()
+            },
+            Some(AttachmentBlend::additive()),
+        );
+
+        let inputs = PersistentDescriptorSet::new(
+            descriptor_set_allocator,
+            layout.set_layouts()[1].clone(),
+            gbuffer
+                .into_iter()
+                .chain([depth])
+                .enumerate()
+                .map(|(binding, view)| WriteDescriptorSet::image_view(binding as u32, view)),
+            [],
+        )
+        .unwrap();
+
+        Deferred {
+            lighting,
+            volumes,
+            layout,
+            sphere: MeshBuffers::new(device, &primitives::icosphere(1.0, 1)),
+            inputs,
+        }
+    }
+
+    // reconstrói a posição de cada pixel a partir da profundidade
+    pub fn push_constants(
+        camera: &Camera,
+        reversed_z: bool,
+        extent: [f32; 2],
+    ) -> shaders::deferred_light_fs::Lighting {
+        shaders::deferred_light_fs::Lighting {
+            inverse_view_projection: (camera.projection * camera.view)
+                .inverse()
+                .to_cols_array_2d(),
+            inverse_screen_size: (Vec2::ONE / Vec2::from(extent)).to_array(),
+            far_depth: if reversed_z { 0.0 } else { 1.0 },
+        }
+    }
+
+    // set é o set 0 do layout, o céu precisa ter sido desenhado antes
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        set: Arc<PersistentDescriptorSet>,
+        push_constants: shaders::deferred_light_fs::Lighting,
+        lights: Option<&Subbuffer<[LightData]>>,
+    ) {
+        builder
+            .bind_pipeline_graphics(self.lighting.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.layout.clone(),
+                0,
+                (set, self.inputs.clone()),
+            )
+            .unwrap()
+            .push_constants(self.layout.clone(), 0, push_constants)
+            .unwrap()
+            .draw(3, 1, 0, 0)
+            .unwrap();
+
+        // o layout é o mesmo, os sets e as push constants continuam valendo
+        let Some(lights) = lights else {
+            return;
+        };
+        builder
+            .bind_pipeline_graphics(self.volumes.clone())
+            .unwrap()
+            .bind_vertex_buffers(0, (self.sphere.vertex_buffer.clone(), lights.clone()))
+            .unwrap()
+            .bind_index_buffer(self.sphere.indices_buffer.clone())
+            .unwrap()
+            .draw_indexed(
+                self.sphere.indices_buffer.len() as u32,
+                lights.len() as u32,
+                0,
+                0,
+                0,
+            )
+            .unwrap();
+    }
+}
@@ -3,7 +3,7 @@ use std::sync::Arc;
 use vulkano::{
     device::{
         physical::{PhysicalDevice, PhysicalDeviceType},
-        Device, DeviceCreateInfo, DeviceExtensions, Queue, QueueCreateInfo, QueueFlags,
+        Device, DeviceCreateInfo, DeviceExtensions, Features, Queue, QueueCreateInfo, QueueFlags,
     },
     instance::Instance,
     memory::allocator::StandardMemoryAllocator,
@@ -14,6 +14,9 @@ pub struct GPU {
     pub physical_device: Arc<PhysicalDevice>,
     pub logical_device: Arc<Device>,
     pub graphics_queue: Arc<Queue>,
+    // numa família só de compute quando a GPU tem uma (async compute),
+    // senão é a mesma queue dos gráficos
+    pub compute_queue: Arc<Queue>,
     pub memory_allocator: Arc<StandardMemoryAllocator>,
 }
 
@@ -28,6 +31,14 @@ impl GPU {
         // physical device
         let (pd, queue_family_index) =
             Self::select_physical_device(&instance, &surface, &device_extensions);
+        let compute_family_index = pd
+            .queue_family_properties()
+            .iter()
+            .position(|q| {
+                q.queue_flags.contains(QueueFlags::COMPUTE)
+                    && !q.queue_flags.intersects(QueueFlags::GRAPHICS)
+            })
+            .map(|q| q as u32);
 
         // cria o logical device e extrai a queue
         //
@@ -36,18 +47,28 @@ impl GPU {
         let (device, mut queues) = Device::new(
             pd.clone(),
             DeviceCreateInfo {
-                queue_create_infos: vec![QueueCreateInfo {
-                    queue_family_index,
-                    ..Default::default()
-                }],
+                queue_create_infos: [Some(queue_family_index), compute_family_index]
+                    .into_iter()
+                    .flatten()
+                    .map(|queue_family_index| QueueCreateInfo {
+                        queue_family_index,
+                        ..Default::default()
+                    })
+                    .collect(),
                 enabled_extensions: device_extensions,
+                // wireframe, só liga se a GPU suportar (veja ViewMode)
+                enabled_features: Features {
+                    fill_mode_non_solid: pd.supported_features().fill_mode_non_solid,
+                    ..Features::empty()
+                },
                 ..Default::default()
             },
         )
         .expect("failed to create device");
 
-        // como só temos uma queue pega a primeira do vetor
+        // as queues vêm na ordem dos queue_create_infos
         let queue = queues.next().unwrap();
+        let compute_queue = queues.next().unwrap_or_else(|| queue.clone());
 
         let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
 
@@ -55,6 +76,7 @@ impl GPU {
             physical_device: pd,
             logical_device: device,
             graphics_queue: queue,
+            compute_queue,
             memory_allocator,
         }
     }
@@ -64,6 +86,12 @@ impl GPU {
         self.logical_device.clone()
     }
 
+    // compute e gráficos rodam em paralelo, os recursos usados pelos dois
+    // precisam ser compartilhados (veja compute::shared_buffer)
+    pub fn async_compute(&self) -> bool {
+        self.compute_queue.queue_family_index() != self.graphics_queue.queue_family_index()
+    }
+
     fn select_physical_device(
         instance: &Arc<Instance>,
         surface: &Arc<Surface>,
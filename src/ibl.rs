@@ -0,0 +1,237 @@
+use std::sync::Arc;
+
+use vulkano::{
+    descriptor_set::{allocator::StandardDescriptorSetAllocator, WriteDescriptorSet},
+    format::Format,
+    image::{
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo, SamplerMipmapMode},
+        view::{ImageView, ImageViewCreateInfo, ImageViewType},
+        Image, ImageAspects, ImageCreateFlags, ImageCreateInfo, ImageSubresourceRange, ImageType,
+        ImageUsage,
+    },
+    memory::allocator::AllocationCreateInfo,
+};
+
+use crate::{
+    compute::ComputeKernel,
+    device::GPU,
+    shaders,
+    texture::{self, Texture},
+};
+
+// as faces da irradiância quase não têm detalhe
+const IRRADIANCE_SIZE: u32 = 32;
+// mip 0 = roughness 0, o último mip = roughness 1
+const SPECULAR_SIZE: u32 = 128;
+const SPECULAR_LEVELS: u32 = 5;
+// x = n·v, y = roughness
+const BRDF_LUT_SIZE: u32 = 256;
+
+// luz ambiente tirada do ambiente do skybox (veja shaders::fs), as três
+// texturas vão no set 0 da cena
+pub struct Ibl {
+    // luz difusa vinda de cada direção, já dividida por pi
+    pub irradiance: Texture,
+    // reflexo do ambiente borrado pela roughness, um mip para cada nível
+    pub specular: Texture,
+    // escala e soma do fresnel da aproximação split-sum
+    pub brdf_lut: Texture,
+}
+
+impl Ibl {
+    // gera tudo na GPU com compute shaders, usado só no carregamento
+    pub fn new(device: &GPU, environment: &Texture) -> Ibl {
+        let irradiance = cube_image(device, IRRADIANCE_SIZE, 1);
+        let kernel = ComputeKernel::new(
+            device,
+            shaders::irradiance_cs::load(device.clone()).expect("failed to create shader module"),
+            [8, 8, 1],
+        );
+        dispatch(
+            device,
+            &kernel,
+            [
+                WriteDescriptorSet::image_view_sampler(
+                    0,
+                    environment.view.clone(),
+                    environment.sampler.clone(),
+                ),
+                WriteDescriptorSet::image_view(1, faces_view(&irradiance, 0)),
+            ],
+            [IRRADIANCE_SIZE, IRRADIANCE_SIZE, 6],
+            None,
+        );
+
+        let specular = cube_image(device, SPECULAR_SIZE, SPECULAR_LEVELS);
+        let kernel = ComputeKernel::new(
+            device,
+            shaders::prefilter_cs::load(device.clone()).expect("failed to create shader module"),
+            [8, 8, 1],
+        );
+        for level in 0..SPECULAR_LEVELS {
+            let size = (SPECULAR_SIZE >> level).max(1);
+            dispatch(
+                device,
+                &kernel,
+                [
+                    WriteDescriptorSet::image_view_sampler(
+                        0,
+                        environment.view.clone(),
+                        environment.sampler.clone(),
+                    ),
+                    WriteDescriptorSet::image_view(1, faces_view(&specular, level)),
+                ],
+                [size, size, 6],
+                Some(shaders::prefilter_cs::Prefilter {
+                    roughness: level_roughness(level, SPECULAR_LEVELS),
+                }),
+            );
+        }
+
+        let brdf_lut = Image::new(
+            device.memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: Format::R16G16B16A16_SFLOAT,
+                extent: [BRDF_LUT_SIZE, BRDF_LUT_SIZE, 1],
+                usage: ImageUsage::STORAGE | ImageUsage::SAMPLED,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap();
+        let brdf_lut = ImageView::new_default(brdf_lut).unwrap();
+        let kernel = ComputeKernel::new(
+            device,
+            shaders::brdf_lut_cs::load(device.clone()).expect("failed to create shader module"),
+            [8, 8, 1],
+        );
+        dispatch(
+            device,
+            &kernel,
+            [WriteDescriptorSet::image_view(0, brdf_lut.clone())],
+            [BRDF_LUT_SIZE, BRDF_LUT_SIZE, 1],
+            None,
+        );
+
+        Ibl {
+            irradiance: Texture::from_cube_image(device, irradiance),
+            specular: Texture {
+                view: cube_view(&specular),
+                sampler: Sampler::new(
+                    device.clone(),
+                    SamplerCreateInfo {
+                        mag_filter: Filter::Linear,
+                        min_filter: Filter::Linear,
+                        mipmap_mode: SamplerMipmapMode::Linear,
+                        address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                        ..Default::default()
+                    },
+                )
+                .unwrap(),
+            },
+            brdf_lut: Texture {
+                view: brdf_lut,
+                sampler: Sampler::new(
+                    device.clone(),
+                    SamplerCreateInfo {
+                        mag_filter: Filter::Linear,
+                        min_filter: Filter::Linear,
+                        address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                        ..Default::default()
+                    },
+                )
+                .unwrap(),
+            },
+        }
+    }
+}
+
+// roughness usada para gerar cada mip do specular, o fragment shader
+// escolhe o mip com roughness * (levels - 1)
+pub fn level_roughness(level: u32, levels: u32) -> f32 {
+    if levels <= 1 {
+        return 0.0;
+    }
+    level as f32 / (levels - 1) as f32
+}
+
+fn cube_image(device: &GPU, size: u32, mip_levels: u32) -> Arc<Image> {
+    Image::new(
+        device.memory_allocator.clone(),
+        ImageCreateInfo {
+            flags: ImageCreateFlags::CUBE_COMPATIBLE,
+            image_type: ImageType::Dim2d,
+            format: Format::R16G16B16A16_SFLOAT,
+            extent: [size, size, 1],
+            array_layers: 6,
+            mip_levels,
+            usage: ImageUsage::STORAGE | ImageUsage::SAMPLED,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )
+    .unwrap()
+}
+
+// as seis faces de um mip como array de imagens 2D, para o compute shader
+// escrever (veja shaders::irradiance_cs)
+fn faces_view(image: &Arc<Image>, level: u32) -> Arc<ImageView> {
+    ImageView::new(
+        image.clone(),
+        ImageViewCreateInfo {
+            view_type: ImageViewType::Dim2dArray,
+            subresource_range: ImageSubresourceRange {
+                aspects: ImageAspects::COLOR,
+                mip_levels: level..level + 1,
+                array_layers: 0..6,
+            },
+            ..ImageViewCreateInfo::from_image(image)
+        },
+    )
+    .unwrap()
+}
+
+fn cube_view(image: &Arc<Image>) -> Arc<ImageView> {
+    ImageView::new(
+        image.clone(),
+        ImageViewCreateInfo {
+            view_type: ImageViewType::Cube,
+            ..ImageViewCreateInfo::from_image(image)
+        },
+    )
+    .unwrap()
+}
+
+fn dispatch<const N: usize>(
+    device: &GPU,
+    kernel: &ComputeKernel,
+    writes: [WriteDescriptorSet; N],
+    size: [u32; 3],
+    push_constants: Option<shaders::prefilter_cs::Prefilter>,
+) {
+    let descriptor_set_allocator =
+        StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+    let set = kernel.set(&descriptor_set_allocator, 0, writes);
+
+    texture::submit_and_wait(device, |builder| {
+        kernel.bind(builder, set);
+        if let Some(push_constants) = push_constants {
+            kernel.push_constants(builder, push_constants);
+        }
+        kernel.dispatch(builder, size);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn specular_levels_cover_all_roughness() {
+        assert_eq!(level_roughness(0, SPECULAR_LEVELS), 0.0);
+        assert_eq!(level_roughness(SPECULAR_LEVELS - 1, SPECULAR_LEVELS), 1.0);
+        assert_eq!(level_roughness(2, 5), 0.5);
+        assert_eq!(level_roughness(0, 1), 0.0);
+    }
+}
@@ -15,11 +15,28 @@ pub enum Keys {
     MoveLeft,
     MoveForward,
     MoveBackward,
+    ToggleMsaa,
+    ToggleProjection,
+    ZoomIn,
+    ZoomOut,
+    FrameObject,
+    ToggleDebug,
+    NextViewMode,
+    NextToneMap,
+    ExposureUp,
+    ExposureDown,
+    ToggleBloom,
+    ToggleSsao,
+    NextAntiAliasing,
+    ToggleAdditive,
 }
 
 pub struct Keyboard {
     key_map: HashMap<VirtualKeyCode, Keys>,
     pub active: Vec<Keys>,
+    // teclas que foram apertadas desde a última chamada de take_pressed,
+    // usado para ações que acontecem uma vez só (ex: trocar o MSAA)
+    pressed: Vec<Keys>,
 }
 
 // TODO: Transformar numa classe onde o usuário pode mudar o input da manéira que quiser
@@ -37,10 +54,25 @@ impl Default for Keyboard {
         default_key_map.insert(VirtualKeyCode::A, Keys::MoveLeft);
         default_key_map.insert(VirtualKeyCode::E, Keys::MoveUp);
         default_key_map.insert(VirtualKeyCode::Q, Keys::MoveDown);
+        default_key_map.insert(VirtualKeyCode::M, Keys::ToggleMsaa);
+        default_key_map.insert(VirtualKeyCode::P, Keys::ToggleProjection);
+        default_key_map.insert(VirtualKeyCode::Z, Keys::ZoomIn);
+        default_key_map.insert(VirtualKeyCode::X, Keys::ZoomOut);
+        default_key_map.insert(VirtualKeyCode::F, Keys::FrameObject);
+        default_key_map.insert(VirtualKeyCode::G, Keys::ToggleDebug);
+        default_key_map.insert(VirtualKeyCode::V, Keys::NextViewMode);
+        default_key_map.insert(VirtualKeyCode::T, Keys::NextToneMap);
+        default_key_map.insert(VirtualKeyCode::RBracket, Keys::ExposureUp);
+        default_key_map.insert(VirtualKeyCode::LBracket, Keys::ExposureDown);
+        default_key_map.insert(VirtualKeyCode::B, Keys::ToggleBloom);
+        default_key_map.insert(VirtualKeyCode::O, Keys::ToggleSsao);
+        default_key_map.insert(VirtualKeyCode::N, Keys::NextAntiAliasing);
+        default_key_map.insert(VirtualKeyCode::K, Keys::ToggleAdditive);
         let active = vec![];
         Keyboard {
             key_map: default_key_map,
             active,
+            pressed: vec![],
         }
     }
 }
@@ -53,6 +85,7 @@ impl Keyboard {
                 if input.state == ElementState::Pressed {
                     if !self.active.contains(input_match.unwrap()) {
                         self.active.push(input_match.unwrap().clone());
+                        self.pressed.push(*input_match.unwrap());
                     }
                 } else if input.state == ElementState::Released {
                     // keep all items that are not input_match
@@ -62,4 +95,31 @@ impl Keyboard {
             _ => {}
         }
     }
+
+    pub fn take_pressed(&mut self) -> Vec<Keys> {
+        std::mem::take(&mut self.pressed)
+    }
+}
+
+// valor seguinte de all, depois do último volta para o primeiro. Usado pelas
+// teclas que trocam entre os valores de um enum (ex: ViewMode::next)
+pub fn cycle<T: Copy + PartialEq>(all: &[T], current: T) -> T {
+    let index = all.iter().position(|&value| value == current).unwrap();
+    all[(index + 1) % all.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycle_visits_every_value_and_wraps() {
+        let all = [3, 1, 2];
+        let mut value = all[0];
+        for expected in all.iter().skip(1) {
+            value = cycle(&all, value);
+            assert_eq!(value, *expected);
+        }
+        assert_eq!(cycle(&all, value), all[0]);
+    }
 }
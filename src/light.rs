@@ -0,0 +1,110 @@
+use glam::Vec3;
+use vulkano::{buffer::BufferContents, pipeline::graphics::vertex_input::Vertex};
+
+use crate::{bounds::BoundingSphere, culling::Frustum};
+
+// luz pontual que chega a zero no raio, além da luz direcional fixa dos
+// shaders
+#[derive(Debug, Clone, Copy)]
+pub struct PointLight {
+    pub position: Vec3,
+    // cor linear, multiplicada pela intensidade
+    pub color: Vec3,
+    pub intensity: f32,
+    pub radius: f32,
+}
+
+// a mesma luz no storage buffer do forward (veja shaders::fs) e nas
+// instâncias dos volumes do deferred (veja shaders::deferred_volume_vs)
+#[derive(BufferContents, Vertex, Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct LightData {
+    // xyz = posição, w = raio
+    #[format(R32G32B32A32_SFLOAT)]
+    pub light_position_radius: [f32; 4],
+    #[format(R32G32B32A32_SFLOAT)]
+    pub light_color: [f32; 4],
+}
+
+impl LightData {
+    // o storage buffer não pode ser vazio, raio 0 é ignorado pelos shaders
+    pub const NONE: LightData = LightData {
+        light_position_radius: [0.0; 4],
+        light_color: [0.0; 4],
+    };
+}
+
+impl PointLight {
+    pub fn sphere(&self) -> BoundingSphere {
+        BoundingSphere {
+            center: self.position,
+            radius: self.radius,
+        }
+    }
+
+    pub fn data(&self) -> LightData {
+        LightData {
+            light_position_radius: self.position.extend(self.radius).to_array(),
+            light_color: (self.color * self.intensity).extend(1.0).to_array(),
+        }
+    }
+}
+
+// só as luzes que alcançam alguma coisa dentro do frustum
+pub fn visible(frustum: &Frustum, lights: &[PointLight]) -> Vec<LightData> {
+    lights
+        .iter()
+        .filter(|light| light.radius > 0.0 && frustum.intersects_sphere(&light.sphere()))
+        .map(PointLight::data)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+
+    fn light(position: Vec3, radius: f32) -> PointLight {
+        PointLight {
+            position,
+            color: Vec3::ONE,
+            intensity: 1.0,
+            radius,
+        }
+    }
+
+    #[test]
+    fn data_packs_radius_and_intensity() {
+        let light = PointLight {
+            intensity: 2.0,
+            color: Vec3::new(1.0, 0.5, 0.0),
+            ..light(Vec3::new(1.0, 2.0, 3.0), 4.0)
+        };
+        assert_eq!(
+            light.data(),
+            LightData {
+                light_position_radius: [1.0, 2.0, 3.0, 4.0],
+                light_color: [2.0, 1.0, 0.0, 1.0],
+            }
+        );
+    }
+
+    #[test]
+    fn visible_drops_lights_that_cannot_reach_the_screen() {
+        let camera = Camera::new(1.0, Vec3::new(0.0, 0.0, -10.0), Vec3::ZERO, true);
+        let frustum = Frustum::from_camera(&camera);
+        let lights = [
+            light(Vec3::ZERO, 1.0),
+            // atrás da câmera
+            light(Vec3::new(0.0, 0.0, -20.0), 2.0),
+            // atrás da câmera, mas o raio alcança a frente dela
+            light(Vec3::new(0.0, 0.0, -12.0), 5.0),
+            // sem raio não ilumina nada
+            light(Vec3::ZERO, 0.0),
+        ];
+
+        let visible = visible(&frustum, &lights);
+        assert_eq!(visible, vec![lights[0].data(), lights[2].data()]);
+        assert_eq!(visible[1].light_position_radius, [0.0, 0.0, -12.0, 5.0]);
+    }
+}
@@ -0,0 +1,155 @@
+// escolha do nível de detalhe (LOD) de um objeto a cada frame, o nível 0 é
+// o mais detalhado
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LodMetric {
+    // fração da altura da tela coberta pela esfera do objeto
+    ScreenSize,
+    // distância da câmera até o centro da esfera
+    Distance,
+}
+
+pub struct LodGroup {
+    pub metric: LodMetric,
+    // thresholds[i] é a borda entre o nível i e o i + 1, em ScreenSize os
+    // valores diminuem e em Distance aumentam
+    pub thresholds: Vec<f32>,
+    // folga relativa em volta da borda para o nível não ficar trocando
+    // quando o valor está bem em cima dela (0.1 = 10%)
+    pub hysteresis: f32,
+    // tempo em segundos da transição entre níveis, 0.0 troca direto
+    pub fade_duration: f32,
+    current: usize,
+    // nível anterior e progresso (0.0 a 1.0) da transição
+    fade: Option<(usize, f32)>,
+}
+
+impl Default for LodGroup {
+    fn default() -> LodGroup {
+        LodGroup::new(LodMetric::ScreenSize, vec![])
+    }
+}
+
+impl LodGroup {
+    pub fn new(metric: LodMetric, thresholds: Vec<f32>) -> LodGroup {
+        LodGroup {
+            metric,
+            thresholds,
+            hysteresis: 0.1,
+            fade_duration: 0.0,
+            current: 0,
+            fade: None,
+        }
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    // Some((nível anterior, progresso)) enquanto está na transição
+    pub fn fading(&self) -> Option<(usize, f32)> {
+        self.fade
+    }
+
+    // levels é a quantidade de modelos disponíveis, a escolha nunca passa dele
+    pub fn update(&mut self, value: f32, levels: usize, delta_time: f32) {
+        let max_level = self.thresholds.len().min(levels.saturating_sub(1));
+        let mut level = self.current.min(max_level);
+
+        while level < max_level && self.is_coarser(value, self.thresholds[level]) {
+            level += 1;
+        }
+        while level > 0 && self.is_finer(value, self.thresholds[level - 1]) {
+            level -= 1;
+        }
+
+        if level != self.current {
+            if self.fade_duration > 0.0 {
+                self.fade = Some((self.current, 0.0));
+            }
+            self.current = level;
+        } else if let Some((previous, progress)) = self.fade {
+            let progress = progress + delta_time / self.fade_duration;
+            self.fade = (progress < 1.0).then_some((previous, progress));
+        }
+    }
+
+    fn is_coarser(&self, value: f32, threshold: f32) -> bool {
+        match self.metric {
+            LodMetric::ScreenSize => value < threshold * (1.0 - self.hysteresis),
+            LodMetric::Distance => value > threshold * (1.0 + self.hysteresis),
+        }
+    }
+
+    fn is_finer(&self, value: f32, threshold: f32) -> bool {
+        match self.metric {
+            LodMetric::ScreenSize => value > threshold * (1.0 + self.hysteresis),
+            LodMetric::Distance => value < threshold * (1.0 - self.hysteresis),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance_selects_levels_with_hysteresis() {
+        let mut lod = LodGroup::new(LodMetric::Distance, vec![10.0, 20.0]);
+
+        lod.update(5.0, 3, 0.0);
+        assert_eq!(lod.current(), 0);
+        // dentro da folga não troca
+        lod.update(10.5, 3, 0.0);
+        assert_eq!(lod.current(), 0);
+        lod.update(11.5, 3, 0.0);
+        assert_eq!(lod.current(), 1);
+        // voltar um pouco para trás da borda também não troca
+        lod.update(9.5, 3, 0.0);
+        assert_eq!(lod.current(), 1);
+        lod.update(8.5, 3, 0.0);
+        assert_eq!(lod.current(), 0);
+        // pula mais de um nível de uma vez
+        lod.update(100.0, 3, 0.0);
+        assert_eq!(lod.current(), 2);
+    }
+
+    #[test]
+    fn screen_size_selects_coarser_levels_when_small() {
+        let mut lod = LodGroup::new(LodMetric::ScreenSize, vec![0.5, 0.1]);
+
+        lod.update(0.8, 3, 0.0);
+        assert_eq!(lod.current(), 0);
+        lod.update(0.3, 3, 0.0);
+        assert_eq!(lod.current(), 1);
+        lod.update(0.01, 3, 0.0);
+        assert_eq!(lod.current(), 2);
+        lod.update(0.52, 3, 0.0);
+        assert_eq!(lod.current(), 1);
+    }
+
+    #[test]
+    fn never_selects_missing_levels() {
+        let mut lod = LodGroup::new(LodMetric::Distance, vec![10.0, 20.0]);
+        lod.update(100.0, 2, 0.0);
+        assert_eq!(lod.current(), 1);
+        lod.update(100.0, 1, 0.0);
+        assert_eq!(lod.current(), 0);
+    }
+
+    #[test]
+    fn cross_fade_progresses_over_time() {
+        let mut lod = LodGroup::new(LodMetric::Distance, vec![10.0]);
+        lod.fade_duration = 0.5;
+
+        lod.update(50.0, 2, 0.1);
+        assert_eq!(lod.current(), 1);
+        assert_eq!(lod.fading(), Some((0, 0.0)));
+
+        lod.update(50.0, 2, 0.25);
+        assert_eq!(lod.fading(), Some((0, 0.5)));
+
+        lod.update(50.0, 2, 0.3);
+        assert_eq!(lod.fading(), None);
+    }
+}
@@ -1,15 +1,38 @@
+mod antialiasing;
+mod bloom;
+mod bounds;
 mod camera;
+mod compute;
+mod config;
+mod culling;
+mod debug_draw;
+mod deferred;
 mod device;
+mod ibl;
 mod keyboard;
+mod light;
+mod lod;
+mod material;
 mod object;
+mod particles;
+mod picking;
+mod post;
 mod prerender;
+mod primitives;
+mod render_graph;
 mod renderer;
 mod shaders;
+mod simplify;
+mod skybox;
+mod ssao;
+mod tangent;
+mod texture;
+mod view_mode;
 
 use std::sync::Arc;
 use std::time::Instant;
 
-use glam::Vec3;
+use glam::{Vec2, Vec3};
 use vulkano::buffer::BufferContents;
 use vulkano::device::DeviceExtensions;
 use vulkano::instance::{Instance, InstanceCreateInfo};
@@ -18,7 +41,7 @@ use vulkano::swapchain::{self, Surface, SwapchainPresentInfo};
 use vulkano::sync::future::FenceSignalFuture;
 use vulkano::sync::{self, GpuFuture};
 use vulkano::{Validated, VulkanError};
-use winit::event::{Event, WindowEvent};
+use winit::event::{ElementState, Event, MouseButton, WindowEvent};
 use winit::event_loop::EventLoop;
 use winit::window::WindowBuilder;
 
@@ -47,9 +70,60 @@ pub struct MyVertex {
     normal: [f32; 3],
     #[format(R32G32_SFLOAT)]
     texcoord: [f32; 2],
+    // xyz = tangente, w = sinal da bitangente (MikkTSpace)
+    #[format(R32G32B32A32_SFLOAT)]
+    tangent: [f32; 4],
+}
+
+// dados que mudam por instância, segundo binding de vértices
+#[derive(BufferContents, Vertex, Clone, Copy)]
+#[repr(C)]
+pub struct InstanceData {
+    #[format(R32G32B32A32_SFLOAT)]
+    instance_model: [[f32; 4]; 4],
+    #[format(R32G32B32_SFLOAT)]
+    instance_tint: [f32; 3],
+    // transição entre LODs: 1.0 = desenha tudo, (0, 1) = nível que está
+    // entrando, [-1, 0) = nível que está saindo (veja shaders::fs)
+    #[format(R32_SFLOAT)]
+    instance_fade: f32,
+    // veja Material
+    #[format(R32_SFLOAT)]
+    instance_opacity: f32,
+    #[format(R32_SFLOAT)]
+    instance_alpha_cutoff: f32,
+    #[format(R32_SFLOAT)]
+    instance_reflectivity: f32,
+    #[format(R32G32_SFLOAT)]
+    instance_roughness_metallic: [f32; 2],
+}
+
+// uso offline do simplificador:
+// rust_engine simplify <entrada.obj> <saída.obj> <fração de triângulos>
+fn simplify_command(args: &[String]) {
+    let [input, output, ratio] = args else {
+        eprintln!("uso: rust_engine simplify <entrada.obj> <saída.obj> <fração>");
+        std::process::exit(1);
+    };
+    let ratio: f32 = ratio.parse().expect("fração inválida");
+
+    let model = object::Model::load(input);
+    let simplified = simplify::simplify(&model, ratio);
+    simplified.save_obj(output).expect("failed to write obj");
+    println!(
+        "{} -> {} triângulos",
+        model.indices.len() / 3,
+        simplified.indices.len() / 3
+    );
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("simplify") {
+        simplify_command(&args[2..]);
+        return;
+    }
+
     let library = vulkano::VulkanLibrary::new()
         .expect("no local Vulkan library/DLL. Did you install Vulkan?");
     let event_loop = EventLoop::new();
@@ -88,19 +162,169 @@ fn main() {
     );
 
     // Renderer { swapchain, RenderPass, Framebuffers, viewport, command buffers}
-    let renderer = renderer::Renderer::new(&device, surface.clone(), window.inner_size());
+    // --no-vsync para benchmarks, --deferred troca o forward pelo G-buffer
+    let vsync = !args.iter().any(|arg| arg == "--no-vsync");
+    let render_path = if args.iter().any(|arg| arg == "--deferred") {
+        config::RenderPath::Deferred
+    } else {
+        config::RenderPath::Forward
+    };
+    let renderer_config = config::RendererConfig {
+        render_path,
+        ..config::RendererConfig::default().with_vsync(vsync)
+    };
+    let mut renderer = renderer::Renderer::new(
+        &device,
+        surface.clone(),
+        window.inner_size(),
+        &renderer_config,
+    );
+    let selection = &renderer.selection;
+    println!(
+        "swapchain: {:?}, {:?} {:?}, {} imagens, alpha {:?}",
+        selection.present_mode,
+        selection.format,
+        selection.color_space,
+        selection.image_count,
+        selection.composite_alpha,
+    );
+    println!("profundidade: {:?}", renderer.depth_format);
+    println!("caminho: {:?}", renderer.render_path);
+    println!("async compute: {}", device.async_compute());
+    // --vignette escurece os cantos numa pass extra do pós-processamento
+    if args.iter().any(|arg| arg == "--vignette") {
+        let vignette =
+            shaders::vignette_fs::load(device.clone()).expect("failed to create shader module");
+        renderer.add_post_pass(&device, vignette);
+    }
+    // --dump-graph grafo.dot salva as passes do frame (veja RenderGraph::to_dot)
+    if let Some(path) = args
+        .iter()
+        .position(|arg| arg == "--dump-graph")
+        .and_then(|index| args.get(index + 1))
+    {
+        std::fs::write(path, renderer.graph_dot()).expect("failed to write render graph");
+    }
+
+    let mut vase = object::Object::new("obj/vase.obj");
+
+    vase.translation = Vec3::from_array([0.0, 0.5, 0.0]);
+    vase.scale = Vec3::from_array([1.5, 1.5, 1.5]);
+    // o vaso não vem com LODs, então são gerados aqui
+    vase.lod_models = simplify::generate_lods(&vase.model, &[0.5, 0.25, 0.1]);
+    vase.lod = lod::LodGroup::new(lod::LodMetric::ScreenSize, vec![0.6, 0.3, 0.15]);
+    vase.lod.fade_duration = 0.3;
+
+    let mut cube = object::Object::new("obj/cube.obj");
+
+    cube.translation = Vec3::from_array([2.5, 0.0, 1.0]);
+    cube.scale = Vec3::from_array([0.4, 0.4, 0.4]);
 
-    let mut object = object::Object::new("obj/vase.obj");
+    let mut torus = object::Object::from_model(primitives::torus(0.5, 0.15, 32, 16));
 
-    object.translation = Vec3::from_array([0.0, 0.5, 0.0]);
-    object.scale = Vec3::from_array([1.5, 1.5, 1.5]);
+    torus.translation = Vec3::from_array([-2.5, 0.0, 1.0]);
+    torus.rotation = Vec3::from_array([1.2, 0.0, 0.0]);
+    // metal polido, reflete o céu pela luz ambiente
+    torus.material.metallic = 1.0;
+    torus.material.roughness = 0.25;
+    // primitivas com menos segmentos no lugar do simplify, trocadas pela
+    // distância até a câmera
+    torus.lod_models = vec![
+        primitives::torus(0.5, 0.15, 16, 8),
+        primitives::torus(0.5, 0.15, 8, 4),
+    ];
+    torus.lod = lod::LodGroup::new(lod::LodMetric::Distance, vec![6.0, 12.0]);
 
-    let prerender = prerender::PreRenderer::new(
+    // vidro na frente do vaso
+    let mut glass = object::Object::from_model(primitives::uv_sphere(0.35, 32, 16));
+
+    glass.translation = Vec3::from_array([0.8, 0.0, -1.0]);
+    glass.tint = Vec3::from_array([0.6, 0.8, 2.0]);
+    glass.material = material::Material::transparent(0.35);
+
+    // grade recortada pelo alpha da textura
+    let mut fence = object::Object::from_model(primitives::plane(1.5, 1.5, 1, 1));
+
+    fence.translation = Vec3::from_array([-1.2, 0.0, -0.5]);
+    fence.rotation = Vec3::from_array([std::f32::consts::FRAC_PI_2, 0.0, 0.0]);
+    fence.material = material::Material::cutout(
+        texture::TextureData::checker(64, 8, [255, 255, 255, 255], [0, 0, 0, 0]),
+        0.5,
+    );
+
+    let mut objects = vec![vase, cube, torus];
+    // a tecla ToggleAdditive troca o blend do vidro
+    let glass_index = objects.len();
+    objects.push(glass);
+    objects.push(fence);
+
+    // primitivas com tampas enfileiradas atrás do vaso
+    let solids = [
+        primitives::cylinder(0.3, 0.8, 24, 1),
+        primitives::cone(0.35, 0.8, 24, 1),
+        primitives::capsule(0.25, 0.9, 24, 8),
+    ];
+    for (x, model) in [-2.5, 2.5, 4.0].into_iter().zip(solids) {
+        let mut solid = object::Object::from_model(model);
+        solid.translation = Vec3::new(x, 0.0, 2.5);
+        objects.push(solid);
+    }
+
+    // chão de cubos desenhados com uma chamada só
+    let floor = object::InstancedObject {
+        model: primitives::cube(2.0, 1),
+        instances: (0..400)
+            .map(|i| {
+                let (x, z) = ((i % 20) as f32, (i / 20) as f32);
+                object::Instance {
+                    translation: Vec3::new(x - 9.5, 2.0, z - 9.5),
+                    scale: Vec3::splat(0.45),
+                    tint: Vec3::new(x / 20.0, 0.6, z / 20.0) * 2.0,
+                    ..Default::default()
+                }
+            })
+            .collect(),
+        material: Default::default(),
+    };
+    let instanced = vec![floor];
+
+    // luzes coloridas um pouco acima do chão
+    let lights: Vec<light::PointLight> = (0..16)
+        .map(|i| {
+            let (x, z) = ((i % 4) as f32, (i / 4) as f32);
+            let hue = i as f32 / 16.0 * std::f32::consts::TAU;
+            light::PointLight {
+                position: Vec3::new(x * 5.0 - 7.5, 1.2, z * 5.0 - 7.5),
+                color: Vec3::new(hue.cos(), (hue + 2.1).cos(), (hue + 4.2).cos()) * 0.5 + 0.5,
+                intensity: 3.0,
+                radius: 3.0,
+            }
+        })
+        .collect();
+
+    // faíscas saindo do chão atrás do vaso
+    let mut particles = particles::ParticleSystem::new(&device, 4096, Vec3::new(0.0, 1.5, 3.0));
+
+    let mut prerender = prerender::PreRenderer::new(
         &device,
-        &object,
-        &renderer.render_pass,
+        &objects,
+        &instanced,
+        &renderer.subpasses,
         &renderer.viewport,
+        renderer.samples,
+        renderer.reversed_z,
     );
+    // --sky céu.hdr ou --sky seis imagens separadas por vírgula, sem isso
+    // fica o gradiente
+    if let Some(sky) = args
+        .iter()
+        .position(|arg| arg == "--sky")
+        .and_then(|index| args.get(index + 1))
+    {
+        prerender
+            .skybox
+            .set_environment(&device, &skybox::Environment::from_arg(sky));
+    }
 
     let frames_in_flight = usize::try_from(renderer.swapchain.image_count()).unwrap();
     let mut fences: Vec<Option<Arc<FenceSignalFuture<_>>>> = vec![None; frames_in_flight];
@@ -110,14 +334,49 @@ fn main() {
     let mut camera = camera::Camera::new(
             renderer.get_aspect_ratio(),
             Vec3::from_array([0.0, 0.0, -3.0]),
-            Vec3::from_array([0.0, 0.0, 0.0])
+            Vec3::from_array([0.0, 0.0, 0.0]),
+            renderer.reversed_z,
         );
 
+    let mut cursor = Vec2::ZERO;
+    // último objeto clicado, enquadrado pelo zoom to fit
+    let mut picked: Option<usize> = None;
+    let mut culling_stats = culling::CullingStats::default();
+    let mut debug = debug_draw::DebugDraw::default();
+    // câmera de quando o debug foi ligado, o frustum dela fica desenhado
+    // para ver o culling de fora
+    let mut debug_camera: Option<camera::Camera> = None;
+
+    let mut bloom_report = 0.0;
+
     let mut delta_time = 0.0;
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent { event, .. } => match event {
             WindowEvent::CloseRequested => *control_flow = winit::event_loop::ControlFlow::Exit,
             WindowEvent::KeyboardInput { input, .. } => inputs.keyboard_events(input),
+            WindowEvent::CursorMoved { position, .. } => {
+                cursor = Vec2::new(position.x as f32, position.y as f32);
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                let hit = picking::pick(
+                    &camera,
+                    cursor,
+                    Vec2::from(renderer.viewport.extent),
+                    &objects,
+                );
+                picked = hit.as_ref().map(|hit| hit.object);
+                match hit {
+                    Some(hit) => println!(
+                        "objeto {} em {:?}, normal {:?}, distância {}",
+                        hit.object, hit.position, hit.normal, hit.distance
+                    ),
+                    None => println!("nenhum objeto"),
+                }
+            }
             _ => (),
         },
         Event::MainEventsCleared => {
@@ -127,16 +386,165 @@ fn main() {
                 camera.move_camera(delta_time, &inputs);
             }
 
-            let uniform = shaders::vs::Data {
-                camera: (camera.projection * camera.view).to_cols_array_2d(),
-                modelMatrix: object.calculate_matrix(),
-            };
+            for object in objects.iter_mut() {
+                object.update_lod(&camera, delta_time);
+            }
 
-            let command_buffer = renderer.create_command_buffer(
+            for key in inputs.take_pressed() {
+                if key == keyboard::Keys::NextViewMode {
+                    prerender.view_mode = prerender.view_mode.next();
+                    println!("modo de visualização: {:?}", prerender.view_mode);
+                }
+                // o tone mapping faz parte do pós-processamento (veja PostChain)
+                let tone_mapping = &mut renderer.post.tone_mapping;
+                if key == keyboard::Keys::NextToneMap {
+                    tone_mapping.operator = tone_mapping.operator.next();
+                    println!("tone mapping: {:?}", tone_mapping.operator);
+                }
+                if key == keyboard::Keys::ExposureUp || key == keyboard::Keys::ExposureDown {
+                    // meio stop por tecla
+                    let step = if key == keyboard::Keys::ExposureUp {
+                        std::f32::consts::SQRT_2
+                    } else {
+                        std::f32::consts::FRAC_1_SQRT_2
+                    };
+                    tone_mapping.exposure *= step;
+                    println!("exposição: {}", tone_mapping.exposure);
+                }
+                if key == keyboard::Keys::ToggleBloom {
+                    let bloom = &mut renderer.post.bloom.settings;
+                    bloom.enabled = !bloom.enabled;
+                    println!("bloom: {}", bloom.enabled);
+                }
+                if key == keyboard::Keys::ToggleSsao {
+                    let ssao = &mut renderer.post.ssao.settings;
+                    ssao.enabled = !ssao.enabled;
+                    println!("SSAO: {}", ssao.enabled);
+                }
+                if key == keyboard::Keys::NextAntiAliasing {
+                    let anti_aliasing = &mut renderer.post.anti_aliasing;
+                    anti_aliasing.mode = anti_aliasing.mode.next();
+                    println!("anti-aliasing: {:?}", anti_aliasing.mode);
+                }
+                // o vidro vira brilho, a pipeline nova é criada no prepare
+                if key == keyboard::Keys::ToggleAdditive {
+                    let blend_mode = &mut objects[glass_index].material.state.blend_mode;
+                    *blend_mode = match blend_mode {
+                        material::BlendMode::Additive => material::BlendMode::Alpha,
+                        _ => material::BlendMode::Additive,
+                    };
+                    println!("vidro: {:?}", blend_mode);
+                }
+                if key == keyboard::Keys::ToggleDebug {
+                    debug_camera = match debug_camera {
+                        Some(_) => None,
+                        None => Some(camera.clone()),
+                    };
+                }
+                // o objeto clicado ou, sem nenhum, a cena toda
+                if key == keyboard::Keys::FrameObject {
+                    let sphere = match picked {
+                        Some(index) => objects[index].world_sphere(),
+                        None => bounds::BoundingSphere::from_aabb(
+                            &objects
+                                .iter()
+                                .map(|object| object.world_aabb())
+                                .reduce(|bounds, aabb| bounds.union(&aabb))
+                                .unwrap(),
+                        ),
+                    };
+                    camera.frame_sphere(&sphere);
+                }
+                if key == keyboard::Keys::ToggleProjection {
+                    let default_fov = 50f32.to_radians();
+                    camera.set_projection_mode(match camera.projection_mode() {
+                        // altura que a perspectiva enxerga a 3 unidades da câmera
+                        camera::Projection::Perspective { fov } => {
+                            camera::Projection::Orthographic {
+                                height: 2.0 * 3.0 * (fov / 2.0).tan(),
+                            }
+                        }
+                        camera::Projection::Orthographic { .. } => {
+                            camera::Projection::Perspective { fov: default_fov }
+                        }
+                    });
+                }
+                if key == keyboard::Keys::ToggleMsaa {
+                    // o deferred não tem MSAA (veja Renderer::set_msaa)
+                    if renderer.render_path == config::RenderPath::Deferred {
+                        println!("MSAA: indisponível no deferred");
+                        continue;
+                    }
+                    // espera a GPU terminar antes de trocar os framebuffers
+                    for fence in fences.iter_mut() {
+                        if let Some(fence) = fence.take() {
+                            fence.wait(None).unwrap();
+                        }
+                    }
+
+                    // 1 -> 2 -> 4 -> 8 -> 1, só as que a GPU suporta
+                    let samples = renderer.next_sample_count(&device);
+                    renderer.set_msaa(&device, samples);
+                    prerender.rebuild_pipeline(
+                        &device,
+                        &renderer.subpasses,
+                        &renderer.viewport,
+                        renderer.samples,
+                    );
+                    println!("MSAA: {:?}", renderer.samples);
+                }
+            }
+
+            debug.clear();
+            if let Some(debug_camera) = &debug_camera {
+                debug.grid(Vec3::ZERO, 20.0, 20, Vec3::splat(0.4));
+                debug.axes(glam::Mat4::IDENTITY, 1.0);
+                for object in &objects {
+                    debug.aabb(&object.world_aabb(), Vec3::new(0.0, 1.0, 0.0));
+                    // a caixa justa, rodada junto com o objeto
+                    debug.oriented_box(
+                        &object.model.aabb,
+                        object.matrix(),
+                        Vec3::new(1.0, 0.0, 1.0),
+                    );
+                    debug.sphere(&object.world_sphere(), Vec3::new(0.0, 0.6, 1.0));
+                    debug.axes(object.matrix(), 0.5);
+                }
+                for light in &lights {
+                    debug.sphere(&light.sphere(), light.color);
+                }
+                debug.frustum(debug_camera, Vec3::new(1.0, 0.5, 0.0));
+                // direção da luz (veja shaders::fs), sempre visível
+                debug.depth_test = false;
+                let to_light = Vec3::new(1.0, -3.0, -1.0).normalize();
+                debug.arrow(to_light * 3.0, Vec3::ZERO, Vec3::new(1.0, 1.0, 0.0));
+                debug.depth_test = true;
+            }
+
+            // jitter do TAA, precisa vir antes de gravar a cena
+            renderer.post.anti_aliasing.begin_frame(&mut camera);
+
+            prerender.prepare(
+                &device,
+                objects
+                    .iter()
+                    .map(|object| &object.material)
+                    .chain(instanced.iter().map(|instanced| &instanced.material)),
+            );
+            let (command_buffer, stats) = renderer.create_command_buffer(
                 &device.graphics_queue,
                 &prerender,
-                &uniform,
+                &camera,
+                &objects,
+                &instanced,
+                &lights,
+                &particles.lines,
+                &debug,
             );
+            if stats != culling_stats {
+                println!("objetos desenhados: {}, descartados: {}", stats.drawn, stats.culled);
+                culling_stats = stats;
+            }
 
             // aqui começamos a renderizar a próxima imagem
             let (image_i, _suboptimal, acquire_future) =
@@ -156,6 +564,15 @@ fn main() {
                 image_fence.wait(None).unwrap();
             }
 
+            // custo do bloom na GPU, uma vez por segundo
+            bloom_report += delta_time;
+            if bloom_report >= 1.0 {
+                if let Some(time) = renderer.post.bloom.gpu_time(image_i as usize) {
+                    println!("bloom: {:.3} ms", time.as_secs_f64() * 1000.0);
+                }
+                bloom_report = 0.0;
+            }
+
             let previous_future = match fences[previous_fence_i as usize].clone() {
                 // Create a NowFuture
                 None => {
@@ -167,8 +584,25 @@ fn main() {
                 // Use the existing FenceSignalFuture
                 Some(fence) => fence.boxed(),
             };
+            // com async compute o frame anterior pode ainda estar na queue de
+            // gráficos, o semáforo faz a simulação esperar ele terminar
+            let previous_future = if previous_future
+                .queue()
+                .is_some_and(|queue| queue != device.compute_queue)
+            {
+                previous_future.then_signal_semaphore().boxed()
+            } else {
+                previous_future
+            };
 
+            // a cena desenha as faíscas simuladas neste frame
             let future = previous_future
+                .then_execute(
+                    device.compute_queue.clone(),
+                    particles.simulate(&device, delta_time),
+                )
+                .unwrap()
+                .then_signal_semaphore()
                 .join(acquire_future)
                 .then_execute(
                     device.graphics_queue.clone(),
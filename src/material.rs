@@ -0,0 +1,161 @@
+use vulkano::pipeline::graphics::{
+    color_blend::AttachmentBlend,
+    rasterization::{CullMode, FrontFace, PolygonMode},
+};
+
+use crate::texture::TextureData;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    Opaque,
+    // cor * alpha + fundo * (1 - alpha)
+    Alpha,
+    // cor * alpha + fundo
+    Additive,
+}
+
+impl BlendMode {
+    // desenhado depois dos opacos, de trás para frente
+    pub fn is_transparent(self) -> bool {
+        self != BlendMode::Opaque
+    }
+
+    pub fn attachment_blend(self) -> Option<AttachmentBlend> {
+        match self {
+            BlendMode::Opaque => None,
+            BlendMode::Alpha => Some(AttachmentBlend::alpha()),
+            BlendMode::Additive => Some(AttachmentBlend::additive()),
+        }
+    }
+}
+
+// parte da pipeline que cada material escolhe, materiais com o mesmo estado
+// usam a mesma pipeline (veja PreRenderer::pipeline)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PipelineState {
+    pub cull_mode: CullMode,
+    // os modelos são anti-horários vistos de fora (veja primitives.rs)
+    pub front_face: FrontFace,
+    pub blend_mode: BlendMode,
+    pub depth_test: bool,
+    // só tem efeito com o depth_test ligado
+    pub depth_write: bool,
+    // o modo de visualização Wireframe substitui o polygon mode
+    pub polygon_mode: PolygonMode,
+}
+
+impl Default for PipelineState {
+    fn default() -> Self {
+        Self {
+            cull_mode: CullMode::Back,
+            front_face: FrontFace::CounterClockwise,
+            blend_mode: BlendMode::Opaque,
+            depth_test: true,
+            depth_write: true,
+            polygon_mode: PolygonMode::Fill,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Material {
+    pub state: PipelineState,
+    // multiplicado pela cor dos vértices, o alpha é a transparência
+    pub albedo: Option<TextureData>,
+    // sem normal map usa as normais dos vértices
+    pub normal_map: Option<TextureData>,
+    // multiplicado pelo alpha do albedo
+    pub opacity: f32,
+    // pixels com alpha menor que isso são descartados (folhas, grades),
+    // 0.0 desliga
+    pub alpha_cutoff: f32,
+    // quanto do ambiente (skybox) aparece refletido, 0.0 desliga
+    pub reflectivity: f32,
+    // usados na luz ambiente (veja ibl.rs), 0.0 = espelho, 1.0 = fosco
+    pub roughness: f32,
+    // 0.0 = dielétrico, 1.0 = metal (sem difuso, reflexo com a cor do albedo)
+    pub metallic: f32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            state: PipelineState::default(),
+            albedo: None,
+            normal_map: None,
+            opacity: 1.0,
+            alpha_cutoff: 0.0,
+            reflectivity: 0.0,
+            roughness: 0.5,
+            metallic: 0.0,
+        }
+    }
+}
+
+impl Material {
+    // misturado com o que está atrás, não escreve profundidade para não
+    // esconder os outros transparentes
+    pub fn transparent(opacity: f32) -> Self {
+        Self {
+            state: PipelineState {
+                blend_mode: BlendMode::Alpha,
+                depth_write: false,
+                ..Default::default()
+            },
+            opacity,
+            ..Default::default()
+        }
+    }
+
+    // opaco com buracos, os dois lados aparecem
+    pub fn cutout(albedo: TextureData, alpha_cutoff: f32) -> Self {
+        Self {
+            state: PipelineState {
+                cull_mode: CullMode::None,
+                ..Default::default()
+            },
+            albedo: Some(albedo),
+            alpha_cutoff,
+            ..Default::default()
+        }
+    }
+}
+
+// estados diferentes usados pelos materiais, na ordem em que aparecem
+pub fn unique_states<'a>(materials: impl IntoIterator<Item = &'a Material>) -> Vec<PipelineState> {
+    let mut states = vec![];
+    for material in materials {
+        if !states.contains(&material.state) {
+            states.push(material.state);
+        }
+    }
+    states
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_states_are_shared() {
+        let double_sided = PipelineState {
+            cull_mode: CullMode::None,
+            ..Default::default()
+        };
+        let materials = [
+            Material::default(),
+            Material {
+                state: double_sided,
+                ..Default::default()
+            },
+            Material {
+                normal_map: Some(TextureData::flat_normal()),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(
+            unique_states(&materials),
+            vec![PipelineState::default(), double_sided]
+        );
+    }
+}
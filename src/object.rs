@@ -1,85 +1,246 @@
 use glam::f32::{Mat4, Vec3};
 
-use crate::MyVertex;
+use crate::{
+    bounds::{Aabb, BoundingSphere},
+    camera::{Camera, Projection},
+    lod::{LodGroup, LodMetric},
+    material::Material,
+    tangent,
+    InstanceData, MyVertex,
+};
 
 pub struct Object {
     //pub model: Vec<MyVertex>,
     pub translation: Vec3,
     pub scale: Vec3,
     pub rotation: Vec3,
+    // multiplicado pela cor dos vértices
+    pub tint: Vec3,
     pub model: Model,
+    // níveis de detalhe mais simples que o model, do mais para o menos
+    // detalhado (o model é o nível 0)
+    pub lod_models: Vec<Model>,
+    pub lod: LodGroup,
+    pub material: Material,
 }
 
 impl Object {
     pub fn new(file_name: &str) -> Object {
-        let (models, _materials) = tobj::load_obj(file_name, &tobj::GPU_LOAD_OPTIONS).expect("Failed to load obj!");
+        Self::from_model(Model::load(file_name))
+    }
 
-        // suporte para apenas um modelo no arquivo
-        let mesh = &models[0].mesh;
+    pub fn from_model(model: Model) -> Object {
+        Self {
+            translation: Vec3::ZERO,
+            scale: Vec3::ONE,
+            rotation: Vec3::ZERO,
+            tint: Vec3::ONE,
+            model,
+            lod_models: vec![],
+            lod: LodGroup::default(),
+            material: Material::default(),
+        }
+    }
+
+    pub fn levels(&self) -> usize {
+        self.lod_models.len() + 1
+    }
 
-        let mut vertices: Vec<MyVertex> = vec![];
-        let mut indices: Vec<u32> = vec![];
-        for i in 0..mesh.indices.len() { 
-            let index = mesh.indices[i] as usize;
-            let vertex = MyVertex {
-                position: [mesh.positions[index * 3], mesh.positions[index * 3 + 1], mesh.positions[index * 3 + 2]],
-                color: [0.5, 0.5, 0.5],
-                normal: [mesh.normals[index * 3], mesh.normals[index * 3 + 1], mesh.normals[index * 3 + 2]],
-                texcoord: [mesh.texcoords[index * 2], mesh.texcoords[index * 2 + 1]],
-            };
-
-            if vertices.contains(&vertex) {
-                indices.push(vertices.iter().position(|r| *r == vertex).unwrap().try_into().unwrap());
-            } else {
-                vertices.push(vertex.clone());
-                indices.push(vertices.iter().position(|r| *r == vertex).unwrap().try_into().unwrap());
-            }
+    pub fn model_for_level(&self, level: usize) -> &Model {
+        match level {
+            0 => &self.model,
+            level => &self.lod_models[level - 1],
         }
+    }
 
-        let model = Model {
-            vertices,
-            indices,
+    // escolhe o nível de detalhe deste frame
+    pub fn update_lod(&mut self, camera: &Camera, delta_time: f32) {
+        let sphere = self.world_sphere();
+        let distance = camera.translation.distance(sphere.center);
+        let value = match self.lod.metric {
+            LodMetric::Distance => distance,
+            LodMetric::ScreenSize => match camera.projection_mode() {
+                Projection::Perspective { fov } => {
+                    sphere.radius / (distance.max(f32::EPSILON) * (fov / 2.0).tan())
+                }
+                Projection::Orthographic { height } => 2.0 * sphere.radius / height,
+            },
         };
-        Self {
+        let levels = self.levels();
+        self.lod.update(value, levels, delta_time);
+    }
+
+    pub fn instance_data(&self) -> InstanceData {
+        InstanceData {
+            instance_model: self.matrix().to_cols_array_2d(),
+            instance_tint: self.tint.to_array(),
+            instance_fade: 1.0,
+            instance_opacity: self.material.opacity,
+            instance_alpha_cutoff: self.material.alpha_cutoff,
+            instance_reflectivity: self.material.reflectivity,
+            instance_roughness_metallic: [self.material.roughness, self.material.metallic],
+        }
+    }
+
+    // volumes do modelo no espaço do mundo
+    pub fn world_aabb(&self) -> Aabb {
+        self.model.aabb.transform(self.matrix())
+    }
+
+    pub fn world_sphere(&self) -> BoundingSphere {
+        self.model.sphere.transform(self.matrix())
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        model_matrix(self.translation, self.rotation, self.scale)
+    }
+}
+
+// vários desenhos do mesmo modelo com uma chamada só de draw_indexed,
+// cada instância tem a própria transformação e cor
+pub struct InstancedObject {
+    pub model: Model,
+    pub instances: Vec<Instance>,
+    pub material: Material,
+}
+
+#[derive(Clone, Copy)]
+pub struct Instance {
+    pub translation: Vec3,
+    pub scale: Vec3,
+    pub rotation: Vec3,
+    pub tint: Vec3,
+}
+
+impl Default for Instance {
+    fn default() -> Instance {
+        Instance {
             translation: Vec3::ZERO,
             scale: Vec3::ONE,
             rotation: Vec3::ZERO,
-            model,
+            tint: Vec3::ONE,
         }
     }
+}
 
-    pub fn calculate_matrix(&mut self) -> [[f32; 4]; 4] {
-        let c3 = f32::cos(self.rotation.z);
-        let s3 = f32::sin(self.rotation.z);
-        let c2 = f32::cos(self.rotation.x);
-        let s2 = f32::sin(self.rotation.x);
-        let c1 = f32::cos(self.rotation.y);
-        let s1 = f32::sin(self.rotation.y);
-        Mat4::from_cols_array(&[
-            self.scale.x * (c1 * c3 + s1 * s2 * s3),
-            self.scale.x * (c2 * s3),
-            self.scale.x * (c1 * s2 * s3 - c3 * s1),
-            0.0,
-            self.scale.y * (c3 * s1 * s2 - c1 * s3),
-            self.scale.y * (c2 * c3),
-            self.scale.y * (c1 * c3 * s2 + s1 * s3),
-            0.0,
-            self.scale.z * (c2 * s1),
-            self.scale.z * (-s2),
-            self.scale.z * (c1 * c2),
-            0.0,
-            self.translation.x,
-            self.translation.y,
-            self.translation.z,
-            1.0,
-        ])
-        .to_cols_array_2d()
+impl Instance {
+    pub fn matrix(&self) -> Mat4 {
+        model_matrix(self.translation, self.rotation, self.scale)
+    }
+
+    // a transparência vem do material do InstancedObject
+    pub fn instance_data(&self, material: &Material) -> InstanceData {
+        InstanceData {
+            instance_model: self.matrix().to_cols_array_2d(),
+            instance_tint: self.tint.to_array(),
+            instance_fade: 1.0,
+            instance_opacity: material.opacity,
+            instance_alpha_cutoff: material.alpha_cutoff,
+            instance_reflectivity: material.reflectivity,
+            instance_roughness_metallic: [material.roughness, material.metallic],
+        }
     }
 }
 
+pub fn model_matrix(translation: Vec3, rotation: Vec3, scale: Vec3) -> Mat4 {
+    let c3 = f32::cos(rotation.z);
+    let s3 = f32::sin(rotation.z);
+    let c2 = f32::cos(rotation.x);
+    let s2 = f32::sin(rotation.x);
+    let c1 = f32::cos(rotation.y);
+    let s1 = f32::sin(rotation.y);
+    Mat4::from_cols_array(&[
+        scale.x * (c1 * c3 + s1 * s2 * s3),
+        scale.x * (c2 * s3),
+        scale.x * (c1 * s2 * s3 - c3 * s1),
+        0.0,
+        scale.y * (c3 * s1 * s2 - c1 * s3),
+        scale.y * (c2 * c3),
+        scale.y * (c1 * c3 * s2 + s1 * s3),
+        0.0,
+        scale.z * (c2 * s1),
+        scale.z * (-s2),
+        scale.z * (c1 * c2),
+        0.0,
+        translation.x,
+        translation.y,
+        translation.z,
+        1.0,
+    ])
+}
+
 pub struct Model {
     pub vertices: Vec<MyVertex>,
     pub indices: Vec<u32>,
+    // volumes no espaço local, calculados quando o modelo é criado
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere,
+}
+
+impl Model {
+    pub fn load(file_name: &str) -> Model {
+        let (models, _materials) = tobj::load_obj(file_name, &tobj::GPU_LOAD_OPTIONS).expect("Failed to load obj!");
+
+        // suporte para apenas um modelo no arquivo
+        let mesh = &models[0].mesh;
+
+        // um vértice por canto de triângulo, depois de gerar as tangentes os
+        // vértices repetidos são juntados (veja tangent::with_tangents)
+        let corners: Vec<MyVertex> = mesh
+            .indices
+            .iter()
+            .map(|&index| {
+                let index = index as usize;
+                MyVertex {
+                    position: [mesh.positions[index * 3], mesh.positions[index * 3 + 1], mesh.positions[index * 3 + 2]],
+                    color: [0.5, 0.5, 0.5],
+                    normal: [mesh.normals[index * 3], mesh.normals[index * 3 + 1], mesh.normals[index * 3 + 2]],
+                    texcoord: [mesh.texcoords[index * 2], mesh.texcoords[index * 2 + 1]],
+                    tangent: [0.0; 4],
+                }
+            })
+            .collect();
+        let indices: Vec<u32> = (0..corners.len() as u32).collect();
+        let (vertices, indices) = tangent::with_tangents(&corners, &indices);
+
+        Model::new(vertices, indices)
+    }
+
+    // salva no formato OBJ, um índice só para posição, textura e normal
+    pub fn save_obj(&self, file_name: &str) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let mut file = std::io::BufWriter::new(std::fs::File::create(file_name)?);
+        for vertex in &self.vertices {
+            let [x, y, z] = vertex.position;
+            writeln!(file, "v {x} {y} {z}")?;
+        }
+        for vertex in &self.vertices {
+            let [u, v] = vertex.texcoord;
+            writeln!(file, "vt {u} {v}")?;
+        }
+        for vertex in &self.vertices {
+            let [x, y, z] = vertex.normal;
+            writeln!(file, "vn {x} {y} {z}")?;
+        }
+        // o OBJ não guarda tangentes, elas são geradas de novo no Model::load
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| triangle[i] + 1);
+            writeln!(file, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+        }
+        file.flush()
+    }
+
+    pub fn new(vertices: Vec<MyVertex>, indices: Vec<u32>) -> Model {
+        let aabb = Aabb::from_vertices(&vertices);
+        let sphere = BoundingSphere::from_vertices(&vertices, &aabb);
+        Model {
+            vertices,
+            indices,
+            aabb,
+            sphere,
+        }
+    }
 }
 
 impl Clone for Model {
@@ -87,6 +248,16 @@ impl Clone for Model {
         Model {
             vertices: self.vertices.clone(),
             indices: self.indices.clone(),
+            aabb: self.aabb,
+            sphere: self.sphere,
         }
     }
 }
+
+// cubo de obj/cube.obj na posição dada, usado nos testes
+#[cfg(test)]
+pub fn test_cube(translation: Vec3) -> Object {
+    let mut object = Object::new("obj/cube.obj");
+    object.translation = translation;
+    object
+}
@@ -0,0 +1,125 @@
+use std::sync::Arc;
+
+use glam::Vec3;
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{allocator::StandardCommandBufferAllocator, PrimaryAutoCommandBuffer},
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+};
+
+use crate::{
+    compute::{self, ComputeKernel},
+    debug_draw::DebugVertex,
+    device::GPU,
+    shaders,
+};
+
+// o mundo tem +y para baixo
+const GRAVITY: Vec3 = Vec3::new(0.0, 9.8, 0.0);
+
+// o mesmo struct do shaders::particles_cs
+#[derive(BufferContents, Clone, Copy, Default)]
+#[repr(C)]
+struct Particle {
+    // w = vida restante, começa em 0 e todas nascem no primeiro frame
+    position_life: [f32; 4],
+    velocity: [f32; 4],
+}
+
+// fonte de faíscas simulada num compute shader na queue de compute (veja
+// GPU::compute_queue), que escreve direto num buffer de linhas desenhado
+// com a pipeline de debug
+pub struct ParticleSystem {
+    kernel: ComputeKernel,
+    particles: Subbuffer<[Particle]>,
+    // dois vértices por partícula, lido pela cena
+    pub lines: Subbuffer<[DebugVertex]>,
+    set: Arc<PersistentDescriptorSet>,
+    pub emitter: Vec3,
+    // em segundos, cada faísca vive entre metade disso e o total
+    pub lifetime: f32,
+    pub speed: f32,
+    time: f32,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+}
+
+impl ParticleSystem {
+    pub fn new(device: &GPU, count: u32, emitter: Vec3) -> ParticleSystem {
+        let kernel = ComputeKernel::new(
+            device,
+            shaders::particles_cs::load(device.clone()).expect("failed to create shader module"),
+            [64, 1, 1],
+        );
+
+        // só a queue de compute usa o estado das partículas
+        let particles = Buffer::from_iter(
+            device.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            (0..count).map(|_| Particle {
+                position_life: emitter.extend(0.0).to_array(),
+                ..Default::default()
+            }),
+        )
+        .unwrap();
+        let lines = compute::shared_buffer(
+            device,
+            BufferUsage::STORAGE_BUFFER | BufferUsage::VERTEX_BUFFER,
+            (0..count * 2).map(|_| DebugVertex::default()),
+        );
+
+        let descriptor_set_allocator =
+            StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+        let set = kernel.set(
+            &descriptor_set_allocator,
+            0,
+            [
+                WriteDescriptorSet::buffer(0, particles.clone()),
+                WriteDescriptorSet::buffer(1, lines.clone()),
+            ],
+        );
+
+        ParticleSystem {
+            kernel,
+            particles,
+            lines,
+            set,
+            emitter,
+            lifetime: 1.5,
+            speed: 5.0,
+            time: 0.0,
+            command_buffer_allocator: StandardCommandBufferAllocator::new(
+                device.clone(),
+                Default::default(),
+            ),
+        }
+    }
+
+    // um passo da simulação, executado na compute_queue antes do command
+    // buffer da cena
+    pub fn simulate(&mut self, device: &GPU, delta_time: f32) -> Arc<PrimaryAutoCommandBuffer> {
+        self.time += delta_time;
+        let push_constants = shaders::particles_cs::Simulation {
+            emitter: self.emitter.extend(self.time).to_array(),
+            gravity: GRAVITY.extend(delta_time).to_array(),
+            lifetime: self.lifetime,
+            speed: self.speed,
+        };
+        compute::command_buffer(device, &self.command_buffer_allocator, |builder| {
+            self.kernel.bind(builder, self.set.clone());
+            self.kernel.push_constants(builder, push_constants);
+            self.kernel
+                .dispatch(builder, [self.particles.len() as u32, 1, 1]);
+        })
+    }
+}
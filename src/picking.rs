@@ -0,0 +1,161 @@
+use glam::{Vec2, Vec3};
+
+use crate::{
+    camera::{Camera, Ray},
+    object::Object,
+};
+
+// resultado do picking, tudo no espaço do mundo
+#[derive(Clone, Copy, Debug)]
+pub struct PickHit {
+    // índice do objeto no slice passado para pick
+    pub object: usize,
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub distance: f32,
+}
+
+// objeto mais próximo embaixo do cursor, cursor em pixels com (0, 0) no
+// canto superior esquerdo
+pub fn pick(
+    camera: &Camera,
+    cursor: Vec2,
+    screen_size: Vec2,
+    objects: &[Object],
+) -> Option<PickHit> {
+    pick_ray(&camera.screen_ray(cursor, screen_size), objects)
+}
+
+pub fn pick_ray(ray: &Ray, objects: &[Object]) -> Option<PickHit> {
+    let mut closest: Option<PickHit> = None;
+
+    for (index, object) in objects.iter().enumerate() {
+        let matrix = object.matrix();
+        let inverse = matrix.inverse();
+
+        // o raio vai para o espaço local do objeto sem normalizar a
+        // direção, assim a distância continua sendo a do mundo
+        let origin = inverse.transform_point3(ray.origin);
+        let direction = inverse.transform_vector3(ray.direction);
+
+        let max_distance = closest.map_or(f32::INFINITY, |hit| hit.distance);
+
+        // testa a caixa primeiro para não passar por todos os triângulos
+        match object.model.aabb.ray_intersection(origin, direction) {
+            Some(distance) if distance < max_distance => {}
+            _ => continue,
+        }
+
+        let model = &object.model;
+        let mut best: Option<(f32, Vec3)> = None;
+        for triangle in model.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(model.vertices[triangle[i] as usize].position));
+            if let Some(distance) = ray_triangle(origin, direction, a, b, c) {
+                if distance < best.map_or(max_distance, |(best, _)| best) {
+                    best = Some((distance, (b - a).cross(c - a)));
+                }
+            }
+        }
+
+        if let Some((distance, local_normal)) = best {
+            // normais são transformadas pela inversa transposta
+            let mut normal = inverse.transpose().transform_vector3(local_normal).normalize();
+            if normal.dot(ray.direction) > 0.0 {
+                normal = -normal;
+            }
+            closest = Some(PickHit {
+                object: index,
+                position: ray.at(distance),
+                normal,
+                distance,
+            });
+        }
+    }
+
+    closest
+}
+
+// Möller–Trumbore, aceita os dois lados do triângulo
+fn ray_triangle(origin: Vec3, direction: Vec3, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    if determinant.abs() < f32::EPSILON {
+        return None;
+    }
+
+    let inverse_determinant = 1.0 / determinant;
+    let s = origin - a;
+    let u = s.dot(p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(edge1);
+    let v = direction.dot(q) * inverse_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let distance = edge2.dot(q) * inverse_determinant;
+    (distance > 0.0).then_some(distance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::test_cube;
+
+    #[test]
+    fn picks_closest_object() {
+        let objects = [
+            test_cube(Vec3::new(0.0, 0.0, 10.0)),
+            test_cube(Vec3::new(0.0, 0.0, 5.0)),
+        ];
+        let ray = Ray {
+            origin: Vec3::ZERO,
+            direction: Vec3::Z,
+        };
+
+        let hit = pick_ray(&ray, &objects).unwrap();
+        assert_eq!(hit.object, 1);
+        assert!((hit.distance - 4.0).abs() < 1e-5);
+        assert!(hit.position.abs_diff_eq(Vec3::new(0.0, 0.0, 4.0), 1e-5));
+        assert!(hit.normal.abs_diff_eq(-Vec3::Z, 1e-5));
+    }
+
+    #[test]
+    fn respects_object_transform() {
+        let mut object = test_cube(Vec3::new(3.0, 0.0, 5.0));
+        object.scale = Vec3::splat(2.0);
+        let objects = [object];
+
+        let miss = Ray {
+            origin: Vec3::ZERO,
+            direction: Vec3::Z,
+        };
+        assert!(pick_ray(&miss, &objects).is_none());
+
+        let hit = pick_ray(
+            &Ray {
+                origin: Vec3::new(1.5, 0.0, 0.0),
+                direction: Vec3::Z,
+            },
+            &objects,
+        )
+        .unwrap();
+        assert!((hit.distance - 3.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn picks_through_camera() {
+        let camera = Camera::new(1.0, Vec3::new(0.0, 0.0, -10.0), Vec3::ZERO, true);
+        let objects = [test_cube(Vec3::ZERO)];
+        let size = Vec2::new(600.0, 600.0);
+
+        let hit = pick(&camera, size / 2.0, size, &objects).unwrap();
+        assert!(hit.position.abs_diff_eq(Vec3::new(0.0, 0.0, -1.0), 1e-3));
+        assert!(pick(&camera, Vec2::new(5.0, 5.0), size, &objects).is_none());
+    }
+}
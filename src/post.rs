@@ -0,0 +1,522 @@
+use std::sync::Arc;
+
+use vulkano::{
+    buffer::BufferContents,
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    format::{Format, NumericFormat},
+    image::{
+        sampler::{Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::{ImageView, ImageViewCreateInfo},
+        Image, ImageAspects, ImageCreateInfo, ImageSubresourceRange, ImageType, ImageUsage,
+        SampleCount,
+    },
+    memory::allocator::AllocationCreateInfo,
+    pipeline::{
+        graphics::{
+            color_blend::{AttachmentBlend, ColorBlendAttachmentState, ColorBlendState},
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            vertex_input::VertexInputState,
+            viewport::{Viewport, ViewportState},
+            GraphicsPipelineCreateInfo,
+        },
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+        DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo,
+    },
+    render_pass::Subpass,
+    shader::ShaderModule,
+};
+
+use crate::{
+    antialiasing::{AntiAliasing, AntiAliasingMode, AntiAliasingPasses},
+    bloom::{Bloom, BloomPasses},
+    camera::Camera,
+    device::GPU,
+    keyboard,
+    render_graph::{CompiledGraph, PassId, RenderGraph, ResourceId},
+    shaders,
+    ssao::{Ssao, SsaoPasses},
+};
+
+// formato da imagem onde a cena é desenhada, as cores podem passar de 1.0
+pub const HDR_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    // só corta o que passa de 1.0
+    None,
+    Reinhard,
+    // aproximação do ACES filmic
+    Aces,
+    AgX,
+}
+
+impl ToneMapOperator {
+    // na ordem em que a tecla troca os operadores
+    pub const ALL: [ToneMapOperator; 4] = [
+        ToneMapOperator::None,
+        ToneMapOperator::Reinhard,
+        ToneMapOperator::Aces,
+        ToneMapOperator::AgX,
+    ];
+
+    pub fn next(self) -> ToneMapOperator {
+        keyboard::cycle(&Self::ALL, self)
+    }
+
+    // valor de operator no shaders::tone_map_fs
+    pub fn shader_index(self) -> i32 {
+        match self {
+            ToneMapOperator::None => 0,
+            ToneMapOperator::Reinhard => 1,
+            ToneMapOperator::Aces => 2,
+            ToneMapOperator::AgX => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ToneMapping {
+    // multiplica a cor antes do operador, em escala linear
+    pub exposure: f32,
+    pub operator: ToneMapOperator,
+    // só é aplicado quando a swapchain não é sRGB (veja output_gamma)
+    pub gamma: f32,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self {
+            exposure: 1.0,
+            operator: ToneMapOperator::Aces,
+            gamma: 2.2,
+        }
+    }
+}
+
+// imagens sRGB já fazem a conversão ao escrever, aplicar o gamma de novo
+// deixaria tudo claro demais
+pub fn output_gamma(format: Format, gamma: f32) -> f32 {
+    if format.numeric_format_color() == Some(NumericFormat::SRGB) {
+        1.0
+    } else {
+        gamma
+    }
+}
+
+// imagens da cena lidas pelo PostChain (veja Renderer::scene_graph)
+#[derive(Debug, Clone, Copy)]
+pub struct SceneImages {
+    pub color: ResourceId,
+    // a parte ambiente de color, escurecida pelo SSAO
+    pub ambient: ResourceId,
+    pub depth: ResourceId,
+}
+
+// passes do PostChain no grafo do frame (veja PostChain::declare)
+pub struct PostPasses {
+    ssao: SsaoPasses,
+    anti_aliasing: AntiAliasingPasses,
+    // cada pass extra com a imagem que ela lê
+    extra: Vec<(PassId, ResourceId)>,
+    bloom: BloomPasses,
+    tone_map: PassId,
+    // entrada do bloom e do tone mapping
+    source: ResourceId,
+    // as imagens da swapchain
+    output: ResourceId,
+    image_count: usize,
+}
+
+// passes de tela cheia depois da cena: o SSAO e o anti-aliasing primeiro, as
+// que foram adicionadas com add_pass em ordem de HDR para HDR, depois o bloom
+// e o tone mapping que escreve na swapchain no final. As passes e as imagens
+// intermediárias ficam no grafo do frame junto com a cena
+pub struct PostChain {
+    pub tone_mapping: ToneMapping,
+    pub ssao: Ssao,
+    pub anti_aliasing: AntiAliasing,
+    pub bloom: Bloom,
+    // fragment shaders das passes extras (veja add_pass)
+    shaders: Vec<Arc<ShaderModule>>,
+    // pass, pipeline e entrada de cada pass extra
+    passes: Vec<(PassId, Arc<GraphicsPipeline>, Arc<PersistentDescriptorSet>)>,
+    tone_map_pass: PassId,
+    tone_map: Arc<GraphicsPipeline>,
+    // a cena já com os outros efeitos e o bloom
+    tone_map_set: Arc<PersistentDescriptorSet>,
+    output: ResourceId,
+    output_format: Format,
+}
+
+impl PostChain {
+    // declara as passes no grafo do frame, depois das da cena. extra_passes
+    // é a quantidade de passes de add_pass
+    pub fn declare(
+        device: &GPU,
+        graph: &mut RenderGraph,
+        scene: SceneImages,
+        images: &[Arc<Image>],
+        extra_passes: usize,
+    ) -> PostPasses {
+        let ssao = Ssao::declare(graph, scene);
+        let anti_aliasing = AntiAliasing::declare(device, graph, ssao.output, ssao.positions);
+
+        let mut source = anti_aliasing.output;
+        let extra = (0..extra_passes)
+            .map(|index| {
+                let name = format!("post_{index}");
+                let target = graph.transient(&name, HDR_FORMAT, SampleCount::Sample1);
+                let pass = graph
+                    .add_pass(&name)
+                    .color(target, None)
+                    .sample(source)
+                    .id();
+                (pass, std::mem::replace(&mut source, target))
+            })
+            .collect();
+
+        let bloom = Bloom::declare(graph, source);
+        let output = graph.output(
+            "swapchain",
+            images
+                .iter()
+                .map(|image| ImageView::new_default(image.clone()).unwrap())
+                .collect(),
+        );
+        let tone_map = graph
+            .add_pass("tone_map")
+            .color(output, None)
+            .sample(source)
+            .sample(bloom.result())
+            .id();
+
+        PostPasses {
+            ssao,
+            anti_aliasing,
+            extra,
+            bloom,
+            tone_map,
+            source,
+            output,
+            image_count: images.len(),
+        }
+    }
+
+    // pipelines e entradas das passes declaradas, depois do compile.
+    // pass_shaders são os das passes extras, na ordem de add_pass
+    pub fn new(
+        device: &GPU,
+        graph: &CompiledGraph,
+        passes: PostPasses,
+        pass_shaders: Vec<Arc<ShaderModule>>,
+    ) -> Self {
+        let vs = shaders::post_vs::load(device.clone()).expect("failed to create shader module");
+        let tone_map_fs =
+            shaders::tone_map_fs::load(device.clone()).expect("failed to create shader module");
+        let sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..SamplerCreateInfo::simple_repeat_linear_no_mipmap()
+            },
+        )
+        .unwrap();
+        let descriptor_set_allocator =
+            StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+
+        let extra = passes
+            .extra
+            .iter()
+            .zip(&pass_shaders)
+            .map(|(&(pass, source), fs)| {
+                let pipeline = fullscreen_pipeline(device, &vs, fs, graph.subpass(pass), None);
+                let set = input_set(
+                    &descriptor_set_allocator,
+                    &sampler,
+                    &pipeline,
+                    vec![graph.image(source).unwrap()],
+                );
+                (pass, pipeline, set)
+            })
+            .collect();
+        let tone_map = fullscreen_pipeline(
+            device,
+            &vs,
+            &tone_map_fs,
+            graph.subpass(passes.tone_map),
+            None,
+        );
+        let tone_map_set = input_set(
+            &descriptor_set_allocator,
+            &sampler,
+            &tone_map,
+            vec![
+                graph.image(passes.source).unwrap(),
+                graph.image(passes.bloom.result()).unwrap(),
+            ],
+        );
+
+        Self {
+            tone_mapping: ToneMapping::default(),
+            ssao: Ssao::new(device, &vs, graph, passes.ssao),
+            anti_aliasing: AntiAliasing::new(
+                device,
+                &vs,
+                graph,
+                passes.anti_aliasing,
+                sampler.clone(),
+            ),
+            bloom: Bloom::new(
+                device,
+                &vs,
+                graph,
+                passes.bloom,
+                sampler,
+                passes.image_count,
+            ),
+            shaders: pass_shaders,
+            passes: extra,
+            tone_map_pass: passes.tone_map,
+            tone_map,
+            tone_map_set,
+            output: passes.output,
+            output_format: graph.image(passes.output).unwrap().format(),
+        }
+    }
+
+    // depois que o grafo do frame é recriado (veja Renderer::rebuild), as
+    // configurações dos efeitos continuam
+    pub fn rebuild(&mut self, device: &GPU, graph: &CompiledGraph, passes: PostPasses) {
+        let mut chain = PostChain::new(device, graph, passes, self.shaders.clone());
+        chain.tone_mapping = self.tone_mapping;
+        chain.ssao.settings = self.ssao.settings;
+        chain.anti_aliasing.mode = self.anti_aliasing.mode;
+        chain.anti_aliasing.current_weight = self.anti_aliasing.current_weight;
+        chain.bloom.settings = self.bloom.settings;
+        *self = chain;
+    }
+
+    // adiciona uma pass que roda depois das outras e antes do bloom, o
+    // fragment shader lê a imagem no set 0 binding 0 e escreve a cor na
+    // location 0. Só entra no grafo quando ele é recriado (veja
+    // Renderer::add_post_pass)
+    pub fn add_pass(&mut self, fs: Arc<ShaderModule>) {
+        self.shaders.push(fs);
+    }
+
+    pub fn pass_count(&self) -> usize {
+        self.shaders.len()
+    }
+
+    // imagens dos outputs do grafo usadas neste command buffer (veja
+    // CompiledGraph::execute)
+    pub fn outputs(&self, image_index: usize) -> [(ResourceId, usize); 2] {
+        [(self.output, image_index), self.anti_aliasing.history()]
+    }
+
+    // antes do execute do grafo, fora dos render passes
+    pub fn begin(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        image_index: usize,
+    ) {
+        self.bloom.reset_queries(builder, image_index);
+    }
+
+    // grava uma das passes do PostChain, chamado pelo execute do grafo do
+    // frame com as passes que não são da cena
+    pub fn record(
+        &self,
+        graph: &CompiledGraph,
+        pass: PassId,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        image_index: usize,
+        camera: &Camera,
+        reversed_z: bool,
+    ) {
+        // as posições também dão os vetores de movimento do TAA
+        let taa = self.anti_aliasing.mode == AntiAliasingMode::Taa;
+        self.ssao
+            .record(graph, pass, builder, camera, reversed_z, taa);
+        self.anti_aliasing.record(graph, pass, builder);
+        self.bloom.record(graph, pass, builder, image_index);
+
+        // as passes extras não têm push constants
+        if let Some((_, pipeline, set)) = self.passes.iter().find(|(other, ..)| *other == pass) {
+            fullscreen_draw::<u32>(builder, graph.extent(pass), pipeline, set, None);
+        }
+
+        if pass == self.tone_map_pass {
+            let bloom = &self.bloom.settings;
+            let push_constants = shaders::tone_map_fs::ToneMap {
+                exposure: self.tone_mapping.exposure,
+                operator: self.tone_mapping.operator.shader_index(),
+                gamma: output_gamma(self.output_format, self.tone_mapping.gamma),
+                bloom_intensity: if bloom.enabled { bloom.intensity } else { 0.0 },
+            };
+            fullscreen_draw(
+                builder,
+                graph.extent(pass),
+                &self.tone_map,
+                &self.tone_map_set,
+                Some(push_constants),
+            );
+        }
+    }
+}
+
+// imagem de fora do grafo onde uma pass desenha e que outra lê
+pub fn target(device: &GPU, extent: [u32; 3], format: Format) -> Arc<ImageView> {
+    ImageView::new_default(
+        Image::new(
+            device.memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format,
+                extent,
+                usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap(),
+    )
+    .unwrap()
+}
+
+// só a parte de profundidade do depth buffer, que é o que um shader pode
+// ler
+pub fn depth_view(depth_buffer: &Arc<ImageView>) -> Arc<ImageView> {
+    let depth_image = depth_buffer.image();
+    ImageView::new(
+        depth_image.clone(),
+        ImageViewCreateInfo {
+            subresource_range: ImageSubresourceRange {
+                aspects: ImageAspects::DEPTH,
+                mip_levels: 0..1,
+                array_layers: 0..1,
+            },
+            ..ImageViewCreateInfo::from_image(depth_image)
+        },
+    )
+    .unwrap()
+}
+
+// as entradas vão nos bindings 0, 1, 2... do set 0
+pub fn input_set(
+    allocator: &StandardDescriptorSetAllocator,
+    sampler: &Arc<Sampler>,
+    pipeline: &Arc<GraphicsPipeline>,
+    inputs: Vec<Arc<ImageView>>,
+) -> Arc<PersistentDescriptorSet> {
+    PersistentDescriptorSet::new(
+        allocator,
+        pipeline.layout().set_layouts()[0].clone(),
+        inputs.into_iter().enumerate().map(|(binding, input)| {
+            WriteDescriptorSet::image_view_sampler(binding as u32, input, sampler.clone())
+        }),
+        [],
+    )
+    .unwrap()
+}
+
+// triângulo que cobre a tela (veja shaders::post_vs), a viewport é dinâmica
+// para a mesma pipeline desenhar em imagens de tamanhos diferentes
+pub fn fullscreen_pipeline(
+    device: &GPU,
+    vs: &Arc<ShaderModule>,
+    fs: &Arc<ShaderModule>,
+    subpass: Subpass,
+    blend: Option<AttachmentBlend>,
+) -> Arc<GraphicsPipeline> {
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs.entry_point("main").unwrap()),
+        PipelineShaderStageCreateInfo::new(fs.entry_point("main").unwrap()),
+    ];
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(device.clone())
+            .unwrap(),
+    )
+    .unwrap();
+
+    GraphicsPipeline::new(
+        device.clone(),
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(VertexInputState::default()),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState::default()),
+            multisample_state: Some(MultisampleState::default()),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState {
+                    blend,
+                    ..Default::default()
+                },
+            )),
+            dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )
+    .unwrap()
+}
+
+// um triângulo só cobrindo a imagem inteira, dentro do subpass da pass
+pub fn fullscreen_draw<T: BufferContents>(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    extent: [u32; 2],
+    pipeline: &Arc<GraphicsPipeline>,
+    set: &Arc<PersistentDescriptorSet>,
+    push_constants: Option<T>,
+) {
+    let [width, height] = extent;
+    builder
+        .bind_pipeline_graphics(pipeline.clone())
+        .unwrap()
+        .set_viewport(
+            0,
+            [Viewport {
+                offset: [0.0, 0.0],
+                extent: [width as f32, height as f32],
+                depth_range: 0.0..=1.0,
+            }]
+            .into_iter()
+            .collect(),
+        )
+        .unwrap()
+        .bind_descriptor_sets(
+            PipelineBindPoint::Graphics,
+            pipeline.layout().clone(),
+            0,
+            set.clone(),
+        )
+        .unwrap();
+    if let Some(push_constants) = push_constants {
+        builder
+            .push_constants(pipeline.layout().clone(), 0, push_constants)
+            .unwrap();
+    }
+    builder.draw(3, 1, 0, 0).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gamma_is_skipped_on_srgb_output() {
+        assert_eq!(output_gamma(Format::B8G8R8A8_SRGB, 2.2), 1.0);
+        assert_eq!(output_gamma(Format::B8G8R8A8_UNORM, 2.2), 2.2);
+    }
+}
@@ -1,15 +1,21 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use vulkano::{
     buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    format::Format,
+    image::SampleCount,
     memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
     pipeline::{
         graphics::{
-            color_blend::{ColorBlendAttachmentState, ColorBlendState},
-            depth_stencil::{DepthState, DepthStencilState},
-            input_assembly::InputAssemblyState,
+            color_blend::{ColorBlendAttachmentState, ColorBlendState, ColorComponents},
+            depth_stencil::{CompareOp, DepthState, DepthStencilState},
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
             multisample::MultisampleState,
-            rasterization::RasterizationState,
+            rasterization::{PolygonMode, RasterizationState},
             vertex_input::{Vertex, VertexDefinition},
             viewport::{Viewport, ViewportState},
             GraphicsPipelineCreateInfo,
@@ -17,29 +23,32 @@ use vulkano::{
         layout::PipelineDescriptorSetLayoutCreateInfo,
         GraphicsPipeline, PipelineLayout, PipelineShaderStageCreateInfo,
     },
-    render_pass::{RenderPass, Subpass},
-    shader::ShaderModule,
+    render_pass::Subpass,
+    shader::{ShaderModule, SpecializationConstant},
 };
 
-use crate::{device::GPU, object::Object, shaders, MyVertex};
+use crate::{
+    config::RenderPath,
+    debug_draw::DebugVertex,
+    device::GPU,
+    material::{self, Material, PipelineState},
+    object::{InstancedObject, Model, Object},
+    renderer::SceneSubpasses,
+    shaders,
+    skybox::Skybox,
+    texture::{Texture, TextureData},
+    view_mode::ViewMode,
+    InstanceData, MyVertex,
+};
 
-pub struct PreRenderer {
-    //memory_allocator: Arc<StandardMemoryAllocator>,
+// buffers de um modelo na GPU
+pub struct MeshBuffers {
     pub vertex_buffer: Subbuffer<[MyVertex]>,
     pub indices_buffer: Subbuffer<[u32]>,
-    //vs: Arc<ShaderModule>,
-    //fs: Arc<ShaderModule>,
-    pub pipeline: Arc<GraphicsPipeline>,
-    pub layout: Arc<PipelineLayout>,
 }
 
-impl PreRenderer {
-    pub fn new(
-        device: &GPU,
-        objects: &Object,
-        render_pass: &Arc<RenderPass>,
-        viewport: &Viewport,
-    ) -> Self {
+impl MeshBuffers {
+    pub fn new(device: &GPU, model: &Model) -> Self {
         let vertex_buffer = Buffer::from_iter(
             device.memory_allocator.clone(),
             BufferCreateInfo {
@@ -51,10 +60,10 @@ impl PreRenderer {
                     | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
                 ..Default::default()
             },
-            objects.model.vertices.clone(),
+            model.vertices.clone(),
         )
         .unwrap();
-        
+
         let indices_buffer = Buffer::from_iter(
             device.memory_allocator.clone(),
             BufferCreateInfo {
@@ -66,42 +75,363 @@ impl PreRenderer {
                     | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
                 ..Default::default()
             },
-            objects.model.indices.clone(),
+            model.indices.clone(),
         )
         .unwrap();
 
+        Self {
+            vertex_buffer,
+            indices_buffer,
+        }
+    }
+}
+
+pub struct PreRenderer {
+    //memory_allocator: Arc<StandardMemoryAllocator>,
+    // um por objeto, na mesma ordem do slice passado para new, com um
+    // MeshBuffers para cada nível de detalhe
+    pub meshes: Vec<Vec<MeshBuffers>>,
+    // um por InstancedObject
+    pub instanced_meshes: Vec<MeshBuffers>,
+    // descriptor set 1 (normal map e albedo) de cada objeto e de cada
+    // InstancedObject
+    pub texture_sets: Vec<Arc<PersistentDescriptorSet>>,
+    pub instanced_texture_sets: Vec<Arc<PersistentDescriptorSet>>,
+    vs: Arc<ShaderModule>,
+    fs: Arc<ShaderModule>,
+    // opacos no deferred (veja SceneSubpasses)
+    gbuffer_fs: Arc<ShaderModule>,
+    // estados dos materiais da cena, cada um tem uma pipeline para cada modo
+    // de visualização, criadas no new e depois no prepare
+    states: Vec<PipelineState>,
+    pipelines: HashMap<(PipelineState, ViewMode), Arc<GraphicsPipeline>>,
+    // onde as pipelines são criadas, trocados no rebuild_pipeline
+    subpasses: SceneSubpasses,
+    viewport: Viewport,
+    samples: SampleCount,
+    // o mesmo para todas as pipelines
+    pub layout: Arc<PipelineLayout>,
+    pub view_mode: ViewMode,
+    // linhas de debug com e sem depth test (veja DebugDraw)
+    debug_vs: Arc<ShaderModule>,
+    debug_fs: Arc<ShaderModule>,
+    pub debug_pipeline: Arc<GraphicsPipeline>,
+    pub debug_overlay_pipeline: Arc<GraphicsPipeline>,
+    // céu desenhado antes da cena, o cubemap também vai no set 0 da cena
+    pub skybox: Skybox,
+    reversed_z: bool,
+}
+
+impl PreRenderer {
+    pub fn new(
+        device: &GPU,
+        objects: &[Object],
+        instanced: &[InstancedObject],
+        subpasses: &SceneSubpasses,
+        viewport: &Viewport,
+        samples: SampleCount,
+        reversed_z: bool,
+    ) -> Self {
+        let meshes = objects
+            .iter()
+            .map(|object| {
+                (0..object.levels())
+                    .map(|level| MeshBuffers::new(device, object.model_for_level(level)))
+                    .collect()
+            })
+            .collect();
+        let instanced_meshes = instanced
+            .iter()
+            .map(|instanced| MeshBuffers::new(device, &instanced.model))
+            .collect();
+
         let vs = shaders::vs::load(device.clone()).expect("failed to create shader module");
         let fs = shaders::fs::load(device.clone()).expect("failed to create shader module");
+        let gbuffer_fs =
+            shaders::gbuffer_fs::load(device.clone()).expect("failed to create shader module");
+        let debug_vs =
+            shaders::debug_vs::load(device.clone()).expect("failed to create shader module");
+        let debug_fs =
+            shaders::debug_fs::load(device.clone()).expect("failed to create shader module");
+
+        // o layout não muda quando as pipelines são recriadas, então os sets
+        // continuam valendo depois de rebuild_pipeline
+        let layout = Self::get_layout(device, &vs, &fs);
+        let descriptor_set_allocator =
+            StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+        let flat_normal = TextureData::flat_normal();
+        let white = TextureData::white();
+        let texture_set = |material: &Material| {
+            let normal_map = Texture::new(
+                device,
+                material.normal_map.as_ref().unwrap_or(&flat_normal),
+                Format::R8G8B8A8_UNORM,
+            );
+            let albedo = Texture::new(
+                device,
+                material.albedo.as_ref().unwrap_or(&white),
+                Format::R8G8B8A8_SRGB,
+            );
+            PersistentDescriptorSet::new(
+                &descriptor_set_allocator,
+                layout.set_layouts()[1].clone(),
+                [
+                    WriteDescriptorSet::image_view_sampler(
+                        0,
+                        normal_map.view,
+                        normal_map.sampler,
+                    ),
+                    WriteDescriptorSet::image_view_sampler(1, albedo.view, albedo.sampler),
+                ],
+                [],
+            )
+            .unwrap()
+        };
+        let texture_sets = objects
+            .iter()
+            .map(|object| texture_set(&object.material))
+            .collect();
+        let instanced_texture_sets = instanced
+            .iter()
+            .map(|instanced| texture_set(&instanced.material))
+            .collect();
 
-        let (pipeline, layout) = Self::get_pipeline(
+        let states = material::unique_states(
+            objects
+                .iter()
+                .map(|object| &object.material)
+                .chain(instanced.iter().map(|instanced| &instanced.material)),
+        );
+
+        let (debug_pipeline, debug_overlay_pipeline) = Self::get_debug_pipelines(
             device,
-            vs.clone(),
-            fs.clone(),
-            render_pass.clone(),
-            viewport.clone(),
+            &debug_vs,
+            &debug_fs,
+            &subpasses.overlay,
+            viewport,
+            samples,
+            reversed_z,
         );
-        Self {
+
+        let mut prerender = Self {
             //memory_allocator,
-            vertex_buffer,
-            indices_buffer,
-            //vs,
-            //fs,
-            pipeline,
+            meshes,
+            instanced_meshes,
+            texture_sets,
+            instanced_texture_sets,
+            vs,
+            fs,
+            gbuffer_fs,
+            states,
+            pipelines: HashMap::new(),
+            subpasses: subpasses.clone(),
+            viewport: viewport.clone(),
+            samples,
             layout,
+            view_mode: ViewMode::Shaded,
+            debug_vs,
+            debug_fs,
+            debug_pipeline,
+            debug_overlay_pipeline,
+            skybox: Skybox::new(device, &subpasses.sky, viewport, samples),
+            reversed_z,
+        };
+        prerender.pipelines = prerender.get_pipelines(device, &prerender.states);
+        prerender
+    }
+
+    // usado quando o grafo da cena muda (ex: troca do MSAA)
+    pub fn rebuild_pipeline(
+        &mut self,
+        device: &GPU,
+        subpasses: &SceneSubpasses,
+        viewport: &Viewport,
+        samples: SampleCount,
+    ) {
+        self.subpasses = subpasses.clone();
+        self.viewport = viewport.clone();
+        self.samples = samples;
+        self.pipelines = self.get_pipelines(device, &self.states);
+
+        (self.debug_pipeline, self.debug_overlay_pipeline) = Self::get_debug_pipelines(
+            device,
+            &self.debug_vs,
+            &self.debug_fs,
+            &subpasses.overlay,
+            viewport,
+            samples,
+            self.reversed_z,
+        );
+        self.skybox
+            .rebuild_pipeline(device, &subpasses.sky, viewport, samples);
+    }
+
+    // cria as pipelines dos estados que ainda não têm, para materiais
+    // trocados depois do new (chamado antes de gravar a cena)
+    pub fn prepare<'a>(&mut self, device: &GPU, materials: impl IntoIterator<Item = &'a Material>) {
+        let missing: Vec<PipelineState> = material::unique_states(materials)
+            .into_iter()
+            .filter(|state| !self.states.contains(state))
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+        let pipelines = self.get_pipelines(device, &missing);
+        self.pipelines.extend(pipelines);
+        self.states.extend(missing);
+    }
+
+    // pipeline do material no modo de visualização atual, o estado precisa
+    // ser de um dos materiais passados para new ou prepare
+    pub fn pipeline(&self, state: &PipelineState) -> &Arc<GraphicsPipeline> {
+        self.pipelines
+            .get(&(*state, self.view_mode))
+            .expect("material sem pipeline, falta chamar PreRenderer::prepare")
+    }
+
+    // com reversed-Z o que está mais perto tem profundidade maior
+    fn depth_compare(reversed_z: bool) -> CompareOp {
+        if reversed_z {
+            CompareOp::Greater
+        } else {
+            CompareOp::Less
         }
     }
 
-    fn get_pipeline(
+    // o modo só muda constantes do fragment shader, então o layout é o mesmo
+    // para todas as pipelines, o gbuffer_fs usa uma parte do layout do fs
+    fn get_layout(
         device: &GPU,
-        vs: Arc<ShaderModule>,
-        fs: Arc<ShaderModule>,
-        render_pass: Arc<RenderPass>,
-        viewport: Viewport,
-    ) -> (Arc<GraphicsPipeline>, Arc<PipelineLayout>) {
+        vs: &Arc<ShaderModule>,
+        fs: &Arc<ShaderModule>,
+    ) -> Arc<PipelineLayout> {
+        let stages = [
+            PipelineShaderStageCreateInfo::new(vs.entry_point("main").unwrap()),
+            PipelineShaderStageCreateInfo::new(fs.entry_point("main").unwrap()),
+        ];
+        PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+                .into_pipeline_layout_create_info(device.clone())
+                .unwrap(),
+        )
+        .unwrap()
+    }
+
+    fn get_pipelines(
+        &self,
+        device: &GPU,
+        states: &[PipelineState],
+    ) -> HashMap<(PipelineState, ViewMode), Arc<GraphicsPipeline>> {
+        let (viewport, samples) = (&self.viewport, self.samples);
+        let vs = self.vs.entry_point("main").unwrap();
+        let fs_for_mode = |fs: &Arc<ShaderModule>, mode: ViewMode| {
+            fs.specialize(
+                [
+                    (0, SpecializationConstant::I32(mode.shader_mode())),
+                    (1, SpecializationConstant::Bool(self.reversed_z)),
+                ]
+                .into_iter()
+                .collect(),
+            )
+            .unwrap()
+            .entry_point("main")
+            .unwrap()
+        };
+
+        // binding 0 = vértices, binding 1 = instâncias
+        let vertex_input_state = [MyVertex::per_vertex(), InstanceData::per_instance()]
+            .definition(&vs.info().input_interface)
+            .unwrap();
+
+        // wireframe precisa de uma feature que nem toda GPU tem, sem ela tudo
+        // fica preenchido
+        let non_solid = device.logical_device.enabled_features().fill_mode_non_solid;
+
+        let mut pipelines = HashMap::new();
+        for &state in states {
+            let transparent = state.blend_mode.is_transparent();
+            let subpass = self.subpasses.material(transparent);
+            let fs = match self.subpasses.render_path {
+                RenderPath::Deferred if !transparent => &self.gbuffer_fs,
+                _ => &self.fs,
+            };
+            for mode in ViewMode::ALL {
+                let stages = [
+                    PipelineShaderStageCreateInfo::new(vs.clone()),
+                    PipelineShaderStageCreateInfo::new(fs_for_mode(fs, mode)),
+                ];
+                let polygon_mode = match mode {
+                    _ if !non_solid => PolygonMode::Fill,
+                    ViewMode::Wireframe => mode.polygon_mode(),
+                    _ => state.polygon_mode,
+                };
+                let compare_op = if state.depth_test {
+                    Self::depth_compare(self.reversed_z)
+                } else {
+                    CompareOp::Always
+                };
+
+                let pipeline = GraphicsPipeline::new(
+                    device.clone(),
+                    None,
+                    GraphicsPipelineCreateInfo {
+                        stages: stages.into_iter().collect(),
+                        vertex_input_state: Some(vertex_input_state.clone()),
+                        input_assembly_state: Some(InputAssemblyState::default()),
+                        viewport_state: Some(ViewportState {
+                            viewports: [viewport.clone()].into_iter().collect(),
+                            ..Default::default()
+                        }),
+                        rasterization_state: Some(RasterizationState {
+                            polygon_mode,
+                            cull_mode: state.cull_mode,
+                            front_face: state.front_face,
+                            ..Default::default()
+                        }),
+                        depth_stencil_state: Some(DepthStencilState {
+                            depth: Some(DepthState {
+                                write_enable: state.depth_write,
+                                compare_op,
+                            }),
+                            ..Default::default()
+                        }),
+                        multisample_state: Some(MultisampleState {
+                            rasterization_samples: samples,
+                            ..Default::default()
+                        }),
+                        color_blend_state: Some(ColorBlendState::with_attachment_states(
+                            subpass.num_color_attachments(),
+                            ColorBlendAttachmentState {
+                                blend: state.blend_mode.attachment_blend(),
+                                ..Default::default()
+                            },
+                        )),
+                        subpass: Some(subpass.clone().into()),
+                        ..GraphicsPipelineCreateInfo::layout(self.layout.clone())
+                    },
+                )
+                .unwrap();
+                pipelines.insert((state, mode), pipeline);
+            }
+        }
+        pipelines
+    }
+
+    // pipelines das linhas de debug: (com depth test, por cima de tudo)
+    fn get_debug_pipelines(
+        device: &GPU,
+        vs: &Arc<ShaderModule>,
+        fs: &Arc<ShaderModule>,
+        subpass: &Subpass,
+        viewport: &Viewport,
+        samples: SampleCount,
+        reversed_z: bool,
+    ) -> (Arc<GraphicsPipeline>, Arc<GraphicsPipeline>) {
         let vs = vs.entry_point("main").unwrap();
         let fs = fs.entry_point("main").unwrap();
 
-        let vertex_input_state = MyVertex::per_vertex()
+        let vertex_input_state = DebugVertex::per_vertex()
             .definition(&vs.info().input_interface)
             .unwrap();
 
@@ -118,34 +448,56 @@ impl PreRenderer {
         )
         .unwrap();
 
-        let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+        let pipeline = |depth: Option<DepthState>| {
+            GraphicsPipeline::new(
+                device.clone(),
+                None,
+                GraphicsPipelineCreateInfo {
+                    stages: stages.iter().cloned().collect(),
+                    vertex_input_state: Some(vertex_input_state.clone()),
+                    input_assembly_state: Some(InputAssemblyState {
+                        topology: PrimitiveTopology::LineList,
+                        ..Default::default()
+                    }),
+                    viewport_state: Some(ViewportState {
+                        viewports: [viewport.clone()].into_iter().collect(),
+                        ..Default::default()
+                    }),
+                    rasterization_state: Some(RasterizationState::default()),
+                    depth_stencil_state: Some(DepthStencilState {
+                        depth,
+                        ..Default::default()
+                    }),
+                    multisample_state: Some(MultisampleState {
+                        rasterization_samples: samples,
+                        ..Default::default()
+                    }),
+                    color_blend_state: Some(color_only_blend_state(subpass)),
+                    subpass: Some(subpass.clone().into()),
+                    ..GraphicsPipelineCreateInfo::layout(layout.clone())
+                },
+            )
+            .unwrap()
+        };
 
-        let graphics_pipeline = GraphicsPipeline::new(
-            device.clone(),
-            None,
-            GraphicsPipelineCreateInfo {
-                stages: stages.into_iter().collect(),
-                vertex_input_state: Some(vertex_input_state),
-                input_assembly_state: Some(InputAssemblyState::default()),
-                viewport_state: Some(ViewportState {
-                    viewports: [viewport].into_iter().collect(),
-                    ..Default::default()
-                }),
-                rasterization_state: Some(RasterizationState::default()),
-                depth_stencil_state: Some(DepthStencilState {
-                    depth: Some(DepthState::simple()),
-                    ..Default::default()
-                }),
-                multisample_state: Some(MultisampleState::default()),
-                color_blend_state: Some(ColorBlendState::with_attachment_states(
-                    subpass.num_color_attachments(),
-                    ColorBlendAttachmentState::default(),
-                )),
-                subpass: Some(subpass.into()),
-                ..GraphicsPipelineCreateInfo::layout(layout.clone())
-            },
-        )
-        .unwrap();
-        (graphics_pipeline, layout)
+        // as linhas não escrevem profundidade, assim uma não esconde a outra
+        let depth_tested = pipeline(Some(DepthState {
+            write_enable: false,
+            compare_op: Self::depth_compare(reversed_z),
+        }));
+        (depth_tested, pipeline(None))
+    }
+}
+
+// para o que não é material (céu, linhas de debug): escreve só a cor, a
+// imagem da luz ambiente (veja Renderer::scene_graph) fica intacta
+pub fn color_only_blend_state(subpass: &Subpass) -> ColorBlendState {
+    let mut state = ColorBlendState::with_attachment_states(
+        subpass.num_color_attachments(),
+        ColorBlendAttachmentState::default(),
+    );
+    for attachment in &mut state.attachments[1..] {
+        attachment.color_write_mask = ColorComponents::empty();
     }
+    state
 }
@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use glam::{Vec2, Vec3};
+
+use crate::{object::Model, tangent, MyVertex};
+
+// formas geradas por código, para testes e para montar cenas sem arquivos
+//
+// todas ficam centralizadas na origem com +y para cima, as coordenadas de
+// textura seguem o OBJ (v = 0 embaixo) e os triângulos ficam virados para
+// fora (anti-horário visto de fora)
+
+// posição, normal e coordenada de textura de um vértice
+type Corner = (Vec3, Vec3, Vec2);
+
+#[derive(Default)]
+struct MeshBuilder {
+    corners: Vec<MyVertex>,
+}
+
+impl MeshBuilder {
+    fn triangle(&mut self, [a, b, c]: [Corner; 3]) {
+        // triângulos sem área aparecem nos polos, é só não gerar
+        if a.0 == b.0 || b.0 == c.0 || a.0 == c.0 {
+            return;
+        }
+        // vira o triângulo para o lado das normais
+        let face = (b.0 - a.0).cross(c.0 - a.0);
+        let corners = if face.dot(a.1 + b.1 + c.1) < 0.0 {
+            [a, c, b]
+        } else {
+            [a, b, c]
+        };
+        for (position, normal, texcoord) in corners {
+            self.corners.push(MyVertex {
+                position: position.to_array(),
+                color: [0.5, 0.5, 0.5],
+                normal: normal.to_array(),
+                texcoord: texcoord.to_array(),
+                tangent: [0.0; 4],
+            });
+        }
+    }
+
+    // superfície paramétrica, surface(u, v) com u e v de 0 a 1
+    fn surface(&mut self, segments_u: u32, segments_v: u32, surface: impl Fn(f32, f32) -> Corner) {
+        let corner = |i: u32, j: u32| surface(i as f32 / segments_u as f32, j as f32 / segments_v as f32);
+        for j in 0..segments_v {
+            for i in 0..segments_u {
+                let [a, b, c, d] = [corner(i, j), corner(i + 1, j), corner(i, j + 1), corner(i + 1, j + 1)];
+                self.triangle([a, b, d]);
+                self.triangle([a, d, c]);
+            }
+        }
+    }
+
+    // disco no plano xz virado para normal.y, com a textura projetada de cima
+    fn disk(&mut self, radius: f32, y: f32, normal: Vec3, segments: u32) {
+        self.surface(segments, 1, |u, v| {
+            let (sin, cos) = (u * TAU).sin_cos();
+            let position = Vec3::new(cos * v * radius, y, -sin * v * radius);
+            let texcoord = Vec2::new(0.5 + 0.5 * cos * v, 0.5 + 0.5 * sin * v * normal.y);
+            (position, normal, texcoord)
+        });
+    }
+
+    // vértices iguais são juntados junto com a geração das tangentes
+    fn build(self) -> Model {
+        let indices: Vec<u32> = (0..self.corners.len() as u32).collect();
+        let (vertices, indices) = tangent::with_tangents(&self.corners, &indices);
+        Model::new(vertices, indices)
+    }
+}
+
+// ponto da esfera unitária com u em volta do eixo y e v de baixo para cima
+//
+// o raio do anel é calculado a partir do y para os polos ficarem exatos
+fn sphere_point(u: f32, y: f32) -> Vec3 {
+    let ring = (1.0 - y * y).max(0.0).sqrt();
+    let (sin, cos) = (u * TAU).sin_cos();
+    Vec3::new(cos * ring, y, -sin * ring)
+}
+
+// cubo com arestas de tamanho size, cada face dividida em
+// subdivisions x subdivisions quadrados
+pub fn cube(size: f32, subdivisions: u32) -> Model {
+    let subdivisions = subdivisions.max(1);
+    let mut builder = MeshBuilder::default();
+    // normal, direita e cima de cada face
+    let faces = [
+        (Vec3::X, Vec3::NEG_Z, Vec3::Y),
+        (Vec3::NEG_X, Vec3::Z, Vec3::Y),
+        (Vec3::Y, Vec3::X, Vec3::NEG_Z),
+        (Vec3::NEG_Y, Vec3::X, Vec3::Z),
+        (Vec3::Z, Vec3::X, Vec3::Y),
+        (Vec3::NEG_Z, Vec3::NEG_X, Vec3::Y),
+    ];
+    for (normal, right, up) in faces {
+        builder.surface(subdivisions, subdivisions, |u, v| {
+            let position = (normal + right * (2.0 * u - 1.0) + up * (2.0 * v - 1.0)) * size / 2.0;
+            (position, normal, Vec2::new(u, v))
+        });
+    }
+    builder.build()
+}
+
+// plano no xz virado para +y
+pub fn plane(width: f32, depth: f32, segments_x: u32, segments_z: u32) -> Model {
+    let mut builder = MeshBuilder::default();
+    builder.surface(segments_x.max(1), segments_z.max(1), |u, v| {
+        let position = Vec3::new((u - 0.5) * width, 0.0, (0.5 - v) * depth);
+        (position, Vec3::Y, Vec2::new(u, v))
+    });
+    builder.build()
+}
+
+// esfera por latitude e longitude, a textura é equiretangular
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> Model {
+    let mut builder = MeshBuilder::default();
+    builder.surface(segments.max(3), rings.max(2), |u, v| {
+        let normal = sphere_point(u, -(v * PI).cos());
+        (normal * radius, normal, Vec2::new(u, v))
+    });
+    builder.build()
+}
+
+// icosaedro subdividido, os triângulos ficam quase do mesmo tamanho
+pub fn icosphere(radius: f32, subdivisions: u32) -> Model {
+    let t = (1.0 + 5.0_f32.sqrt()) / 2.0;
+    let mut points: Vec<Vec3> = [
+        [-1.0, t, 0.0],
+        [1.0, t, 0.0],
+        [-1.0, -t, 0.0],
+        [1.0, -t, 0.0],
+        [0.0, -1.0, t],
+        [0.0, 1.0, t],
+        [0.0, -1.0, -t],
+        [0.0, 1.0, -t],
+        [t, 0.0, -1.0],
+        [t, 0.0, 1.0],
+        [-t, 0.0, -1.0],
+        [-t, 0.0, 1.0],
+    ]
+    .map(|point| Vec3::from_array(point).normalize())
+    .to_vec();
+    let mut triangles: Vec<[usize; 3]> = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+
+    // cada triângulo vira quatro, o ponto do meio de cada aresta é
+    // compartilhado pelos dois triângulos da aresta
+    for _ in 0..subdivisions {
+        let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut midpoint = |a: usize, b: usize| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                points.push(((points[a] + points[b]) / 2.0).normalize());
+                points.len() - 1
+            })
+        };
+        triangles = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    let mut builder = MeshBuilder::default();
+    for triangle in triangles {
+        let normals = triangle.map(|index| points[index]);
+        let mut texcoords = normals.map(|normal| {
+            let u = (-normal.z).atan2(normal.x) / TAU;
+            Vec2::new(u.rem_euclid(1.0), 1.0 - normal.y.clamp(-1.0, 1.0).acos() / PI)
+        });
+
+        // no polo o u não existe, fica com a média dos outros dois cantos
+        let poles = normals.map(|normal| normal.x == 0.0 && normal.z == 0.0);
+        let us: Vec<f32> = (0..3).filter(|&i| !poles[i]).map(|i| texcoords[i].x).collect();
+
+        // triângulos que cruzam a costura ficam com u passando de 1
+        let span = us.iter().copied().fold(f32::MIN, f32::max) - us.iter().copied().fold(f32::MAX, f32::min);
+        if span > 0.5 {
+            for texcoord in texcoords.iter_mut().filter(|texcoord| texcoord.x < 0.5) {
+                texcoord.x += 1.0;
+            }
+        }
+        for i in (0..3).filter(|&i| poles[i]) {
+            texcoords[i].x = (texcoords[(i + 1) % 3].x + texcoords[(i + 2) % 3].x) / 2.0;
+        }
+
+        builder.triangle([0, 1, 2].map(|i| (normals[i] * radius, normals[i], texcoords[i])));
+    }
+    builder.build()
+}
+
+// cilindro em pé com as tampas
+pub fn cylinder(radius: f32, height: f32, segments: u32, height_segments: u32) -> Model {
+    let segments = segments.max(3);
+    let mut builder = MeshBuilder::default();
+    builder.surface(segments, height_segments.max(1), |u, v| {
+        let normal = sphere_point(u, 0.0);
+        let position = normal * radius + Vec3::Y * (v - 0.5) * height;
+        (position, normal, Vec2::new(u, v))
+    });
+    builder.disk(radius, height / 2.0, Vec3::Y, segments);
+    builder.disk(radius, -height / 2.0, Vec3::NEG_Y, segments);
+    builder.build()
+}
+
+// cone com a ponta para cima e a base fechada
+pub fn cone(radius: f32, height: f32, segments: u32, height_segments: u32) -> Model {
+    let segments = segments.max(3);
+    let mut builder = MeshBuilder::default();
+    builder.surface(segments, height_segments.max(1), |u, v| {
+        let direction = sphere_point(u, 0.0);
+        // raio do anel exato na ponta, para todos os vértices dela serem iguais
+        let position = direction * radius * (1.0 - v) + Vec3::Y * (v - 0.5) * height;
+        let normal = (direction * height + Vec3::Y * radius).normalize();
+        (position, normal, Vec2::new(u, v))
+    });
+    builder.disk(radius, -height / 2.0, Vec3::NEG_Y, segments);
+    builder.build()
+}
+
+// cilindro com meias esferas nas pontas, height é a altura total
+//
+// o v da textura é proporcional ao comprimento do perfil, então a textura não
+// estica entre o cilindro e as meias esferas
+pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> Model {
+    let segments = segments.max(3);
+    let rings = rings.max(1);
+    let middle = (height - 2.0 * radius).max(0.0);
+    let cap = FRAC_PI_2 * radius;
+    let length = 2.0 * cap + middle;
+
+    let mut builder = MeshBuilder::default();
+    // meia esfera de baixo, t = 0 no polo
+    builder.surface(segments, rings, |u, t| {
+        let normal = sphere_point(u, -((1.0 - t) * FRAC_PI_2).sin());
+        let position = normal * radius - Vec3::Y * middle / 2.0;
+        (position, normal, Vec2::new(u, t * cap / length))
+    });
+    builder.surface(segments, 1, |u, t| {
+        let normal = sphere_point(u, 0.0);
+        let position = normal * radius + Vec3::Y * (t - 0.5) * middle;
+        (position, normal, Vec2::new(u, (cap + t * middle) / length))
+    });
+    // meia esfera de cima, t = 1 no polo
+    builder.surface(segments, rings, |u, t| {
+        let normal = sphere_point(u, (t * FRAC_PI_2).sin());
+        let position = normal * radius + Vec3::Y * middle / 2.0;
+        (position, normal, Vec2::new(u, (cap + middle + t * cap) / length))
+    });
+    builder.build()
+}
+
+// toro deitado no plano xz, major_radius vai do centro até o meio do tubo
+pub fn torus(major_radius: f32, minor_radius: f32, major_segments: u32, minor_segments: u32) -> Model {
+    let mut builder = MeshBuilder::default();
+    builder.surface(major_segments.max(3), minor_segments.max(3), |u, v| {
+        let direction = sphere_point(u, 0.0);
+        let (sin, cos) = (v * TAU).sin_cos();
+        let normal = direction * cos + Vec3::Y * sin;
+        let position = direction * major_radius + normal * minor_radius;
+        (position, normal, Vec2::new(u, v))
+    });
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all() -> Vec<(&'static str, Model)> {
+        vec![
+            ("cube", cube(2.0, 3)),
+            ("plane", plane(4.0, 2.0, 4, 2)),
+            ("uv_sphere", uv_sphere(1.0, 16, 8)),
+            ("icosphere", icosphere(1.0, 2)),
+            ("cylinder", cylinder(1.0, 2.0, 16, 2)),
+            ("cone", cone(1.0, 2.0, 16, 2)),
+            ("capsule", capsule(0.5, 2.0, 16, 4)),
+            ("torus", torus(1.0, 0.25, 24, 12)),
+        ]
+    }
+
+    #[test]
+    fn triangles_face_their_normals() {
+        for (name, model) in all() {
+            assert!(!model.indices.is_empty(), "{name}");
+            for triangle in model.indices.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| &model.vertices[triangle[i] as usize]);
+                let [pa, pb, pc] = [a, b, c].map(|vertex| Vec3::from(vertex.position));
+                let face = (pb - pa).cross(pc - pa);
+                assert!(face.length() > 0.0, "{name}: triângulo sem área");
+                for vertex in [a, b, c] {
+                    let normal = Vec3::from(vertex.normal);
+                    assert!((normal.length() - 1.0).abs() < 1e-4, "{name}: normal {normal}");
+                    assert!(face.dot(normal) > 0.0, "{name}: triângulo virado");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sizes_match_parameters() {
+        let cube = cube(2.0, 1);
+        assert_eq!(cube.aabb.min, Vec3::splat(-1.0));
+        assert_eq!(cube.aabb.max, Vec3::splat(1.0));
+        // 4 vértices por face, as arestas não são compartilhadas
+        assert_eq!(cube.vertices.len(), 24);
+        assert_eq!(cube.indices.len(), 36);
+
+        let capsule = capsule(0.5, 3.0, 16, 4);
+        assert!((capsule.aabb.max.y - 1.5).abs() < 1e-5);
+        assert!((capsule.aabb.min.y + 1.5).abs() < 1e-5);
+
+        let torus = torus(1.0, 0.25, 24, 12);
+        assert!((torus.aabb.max.x - 1.25).abs() < 1e-5);
+        assert!((torus.aabb.max.y - 0.25).abs() < 1e-5);
+    }
+
+    #[test]
+    fn sphere_normals_point_out_of_the_center() {
+        for model in [uv_sphere(2.0, 16, 8), icosphere(2.0, 2)] {
+            for vertex in &model.vertices {
+                let position = Vec3::from(vertex.position);
+                assert!((position.length() - 2.0).abs() < 1e-5);
+                assert!(position.normalize().dot(Vec3::from(vertex.normal)) > 0.999);
+                // a tangente também é gerada
+                assert!(Vec3::from_slice(&vertex.tangent[..3]).length() > 0.9);
+            }
+        }
+    }
+}
@@ -0,0 +1,1084 @@
+use std::fmt::Write;
+use std::sync::Arc;
+
+use vulkano::{
+    command_buffer::{
+        AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassBeginInfo,
+        SubpassContents,
+    },
+    format::{ClearValue, Format},
+    image::{
+        view::ImageView, Image, ImageCreateInfo, ImageLayout, ImageType, ImageUsage, SampleCount,
+    },
+    memory::allocator::AllocationCreateInfo,
+    render_pass::{
+        AttachmentDescription, AttachmentLoadOp, AttachmentReference, AttachmentStoreOp,
+        Framebuffer, FramebufferCreateInfo, RenderPass, RenderPassCreateInfo, Subpass,
+        SubpassDependency, SubpassDescription,
+    },
+    sync::{AccessFlags, DependencyFlags, PipelineStages},
+};
+
+use crate::device::GPU;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PassId(usize);
+
+// como uma pass usa uma imagem
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Color,
+    // destino do resolve do MSAA, um para cada Color na mesma ordem
+    Resolve,
+    Depth,
+    // lida no mesmo pixel com subpassLoad, junta a pass com quem escreveu
+    // a imagem num render pass só
+    Input,
+    // lida como textura no fragment shader, não é attachment: a pass fica
+    // num render pass próprio depois de quem escreveu a imagem
+    Sampled,
+}
+
+impl Access {
+    fn name(self) -> &'static str {
+        match self {
+            Access::Color => "color",
+            Access::Resolve => "resolve",
+            Access::Depth => "depth",
+            Access::Input => "input",
+            Access::Sampled => "sampled",
+        }
+    }
+
+    // os mesmos layouts que o single_pass_renderpass! usa
+    fn layout(self) -> ImageLayout {
+        match self {
+            Access::Color | Access::Resolve => ImageLayout::ColorAttachmentOptimal,
+            Access::Depth => ImageLayout::DepthStencilAttachmentOptimal,
+            Access::Input | Access::Sampled => ImageLayout::ShaderReadOnlyOptimal,
+        }
+    }
+
+    fn usage(self) -> ImageUsage {
+        match self {
+            Access::Color | Access::Resolve => ImageUsage::COLOR_ATTACHMENT,
+            Access::Depth => ImageUsage::DEPTH_STENCIL_ATTACHMENT,
+            Access::Input => ImageUsage::INPUT_ATTACHMENT,
+            Access::Sampled => ImageUsage::SAMPLED,
+        }
+    }
+
+    fn stages(self) -> PipelineStages {
+        match self {
+            Access::Color | Access::Resolve => PipelineStages::COLOR_ATTACHMENT_OUTPUT,
+            Access::Depth => {
+                PipelineStages::EARLY_FRAGMENT_TESTS | PipelineStages::LATE_FRAGMENT_TESTS
+            }
+            Access::Input | Access::Sampled => PipelineStages::FRAGMENT_SHADER,
+        }
+    }
+
+    fn access(self) -> AccessFlags {
+        match self {
+            Access::Color => {
+                AccessFlags::COLOR_ATTACHMENT_READ | AccessFlags::COLOR_ATTACHMENT_WRITE
+            }
+            Access::Resolve => AccessFlags::COLOR_ATTACHMENT_WRITE,
+            Access::Depth => {
+                AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+            }
+            Access::Input => AccessFlags::INPUT_ATTACHMENT_READ,
+            Access::Sampled => AccessFlags::SHADER_SAMPLED_READ,
+        }
+    }
+
+    fn writes(self) -> bool {
+        !matches!(self, Access::Input | Access::Sampled)
+    }
+}
+
+struct Use {
+    resource: ResourceId,
+    access: Access,
+    // só Color e Depth
+    clear: Option<ClearValue>,
+}
+
+struct Pass {
+    name: String,
+    uses: Vec<Use>,
+}
+
+impl Pass {
+    fn samples(&self) -> bool {
+        self.uses.iter().any(|use_| use_.access == Access::Sampled)
+    }
+}
+
+struct Resource {
+    name: String,
+    format: Format,
+    samples: SampleCount,
+    extent: [u32; 3],
+    // imagens de fora do grafo, as outras são criadas no compile. Com mais
+    // de uma o execute escolhe qual (veja output)
+    views: Vec<Arc<ImageView>>,
+    // o conteúdo precisa continuar existindo depois do grafo, as passes que
+    // escrevem nela nunca são descartadas
+    external: bool,
+}
+
+// uma imagem dentro de um render pass do plano
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Attachment {
+    resource: ResourceId,
+    load_op: AttachmentLoadOp,
+    store_op: AttachmentStoreOp,
+    clear: Option<ClearValue>,
+}
+
+// passes seguidas que viram os subpasses de um render pass
+#[derive(Debug)]
+struct Group {
+    passes: Vec<usize>,
+    attachments: Vec<Attachment>,
+}
+
+// o que o compile faz, sem precisar da GPU
+struct Plan {
+    // passes que contribuem para alguma imagem de fora do grafo
+    live: Vec<bool>,
+    groups: Vec<Group>,
+    // uso de cada imagem criada pelo grafo
+    usage: Vec<ImageUsage>,
+}
+
+// passes e imagens do frame declarados antes de existirem: as passes dizem o
+// que leem e escrevem, o grafo ordena as passes pelas dependências, descarta
+// as que não contribuem para nada, junta as que leem input attachments com
+// quem escreveu num render pass só, cria as imagens intermediárias e escolhe
+// load/store ops, layouts e as dependências entre os subpasses. As barreiras
+// entre render passes ficam com o AutoCommandBufferBuilder
+pub struct RenderGraph {
+    extent: [u32; 3],
+    resources: Vec<Resource>,
+    passes: Vec<Pass>,
+}
+
+pub struct PassBuilder<'a> {
+    graph: &'a mut RenderGraph,
+    pass: usize,
+}
+
+impl PassBuilder<'_> {
+    fn with(self, resource: ResourceId, access: Access, clear: Option<ClearValue>) -> Self {
+        self.graph.passes[self.pass].uses.push(Use {
+            resource,
+            access,
+            clear,
+        });
+        self
+    }
+
+    // clear None mantém o que já estava na imagem
+    pub fn color(self, resource: ResourceId, clear: Option<ClearValue>) -> Self {
+        self.with(resource, Access::Color, clear)
+    }
+
+    pub fn resolve(self, resource: ResourceId) -> Self {
+        self.with(resource, Access::Resolve, None)
+    }
+
+    pub fn depth(self, resource: ResourceId, clear: Option<ClearValue>) -> Self {
+        self.with(resource, Access::Depth, clear)
+    }
+
+    pub fn input(self, resource: ResourceId) -> Self {
+        self.with(resource, Access::Input, None)
+    }
+
+    pub fn sample(self, resource: ResourceId) -> Self {
+        self.with(resource, Access::Sampled, None)
+    }
+
+    pub fn id(self) -> PassId {
+        PassId(self.pass)
+    }
+}
+
+impl RenderGraph {
+    // tamanho das imagens criadas pelo grafo, menos as de transient_sized
+    pub fn new(extent: [u32; 3]) -> RenderGraph {
+        RenderGraph {
+            extent,
+            resources: vec![],
+            passes: vec![],
+        }
+    }
+
+    pub fn extent(&self) -> [u32; 3] {
+        self.extent
+    }
+
+    fn add_resource(
+        &mut self,
+        name: &str,
+        format: Format,
+        samples: SampleCount,
+        views: Vec<Arc<ImageView>>,
+    ) -> ResourceId {
+        let extent = match views.first() {
+            Some(view) => view.image().extent(),
+            None => self.extent,
+        };
+        self.resources.push(Resource {
+            name: name.to_string(),
+            format,
+            samples,
+            extent,
+            external: !views.is_empty(),
+            views,
+        });
+        ResourceId(self.resources.len() - 1)
+    }
+
+    // imagem criada pelo grafo, o uso sai das passes
+    pub fn transient(&mut self, name: &str, format: Format, samples: SampleCount) -> ResourceId {
+        self.add_resource(name, format, samples, vec![])
+    }
+
+    // imagem criada pelo grafo com outro tamanho (ex: os mips do bloom)
+    pub fn transient_sized(&mut self, name: &str, format: Format, extent: [u32; 3]) -> ResourceId {
+        let resource = self.transient(name, format, SampleCount::Sample1);
+        self.resources[resource.0].extent = extent;
+        resource
+    }
+
+    // imagens de fora que o grafo escreve, o que tinha nelas antes é
+    // descartado. Cada execute escolhe uma delas (ex: a imagem da swapchain)
+    pub fn output(&mut self, name: &str, views: Vec<Arc<ImageView>>) -> ResourceId {
+        let format = views[0].format();
+        let samples = views[0].image().samples();
+        self.add_resource(name, format, samples, views)
+    }
+
+    pub fn add_pass(&mut self, name: &str) -> PassBuilder<'_> {
+        self.passes.push(Pass {
+            name: name.to_string(),
+            uses: vec![],
+        });
+        PassBuilder {
+            pass: self.passes.len() - 1,
+            graph: self,
+        }
+    }
+
+    // índices das passes na ordem de execução: quem lê uma imagem roda
+    // depois de quem escreveu nela antes na declaração (ou de quem escreve
+    // por último, quando ninguém escreveu antes) e quem escreve roda depois
+    // de quem escreveu e leu antes. Entre passes sem dependência fica a ordem
+    // de declaração
+    fn order(&self) -> Vec<usize> {
+        let mut after: Vec<Vec<usize>> = vec![vec![]; self.passes.len()];
+        for resource in 0..self.resources.len() {
+            let uses: Vec<(usize, bool)> = self
+                .passes
+                .iter()
+                .enumerate()
+                .flat_map(|(index, pass)| {
+                    pass.uses
+                        .iter()
+                        .filter(|use_| use_.resource.0 == resource)
+                        .map(move |use_| (index, use_.access.writes()))
+                })
+                .collect();
+            let last_writer = uses.iter().rev().find(|(_, writes)| *writes);
+            let mut writer: Option<usize> = None;
+            let mut readers: Vec<usize> = vec![];
+            for &(index, writes) in &uses {
+                if writes {
+                    after[index].extend(writer);
+                    after[index].append(&mut readers);
+                    writer = Some(index);
+                } else if let Some(writer) = writer {
+                    after[index].push(writer);
+                    readers.push(index);
+                } else if let Some(&(last, _)) = last_writer {
+                    after[index].push(last);
+                }
+            }
+        }
+
+        let mut order = vec![];
+        let mut done = vec![false; self.passes.len()];
+        while order.len() < self.passes.len() {
+            let next = (0..self.passes.len())
+                .find(|&index| {
+                    !done[index]
+                        && after[index]
+                            .iter()
+                            .all(|&other| other == index || done[other])
+                })
+                .expect("ciclo entre as passes do grafo");
+            done[next] = true;
+            order.push(next);
+        }
+        order
+    }
+
+    fn plan(&self) -> Plan {
+        let order = self.order();
+
+        // de trás para frente: uma pass é útil se escreve numa imagem de
+        // fora ou numa imagem que uma pass útil depois dela usa
+        let mut live = vec![false; self.passes.len()];
+        let mut needed: Vec<bool> = self
+            .resources
+            .iter()
+            .map(|resource| resource.external)
+            .collect();
+        for &index in order.iter().rev() {
+            let pass = &self.passes[index];
+            live[index] = pass
+                .uses
+                .iter()
+                .any(|use_| use_.access.writes() && needed[use_.resource.0]);
+            if live[index] {
+                for use_ in &pass.uses {
+                    needed[use_.resource.0] = true;
+                }
+            }
+        }
+
+        // uma pass entra no render pass anterior quando lê input attachments
+        // escritos nele ou quando só usa imagens que já estão nele (sem
+        // limpar nenhuma), as outras começam um render pass novo. Passes que
+        // leem texturas ficam sozinhas num render pass
+        let mut groups: Vec<Group> = vec![];
+        let mut writer: Vec<Option<usize>> = vec![None; self.resources.len()];
+        let mut user: Vec<Option<usize>> = vec![None; self.resources.len()];
+        for &index in &order {
+            if !live[index] {
+                continue;
+            }
+            let pass = &self.passes[index];
+            for use_ in &pass.uses {
+                if !use_.access.writes() && writer[use_.resource.0].is_none() {
+                    panic!(
+                        "a pass {} lê {} antes de alguém escrever",
+                        pass.name, self.resources[use_.resource.0].name
+                    );
+                }
+            }
+            let inputs: Vec<ResourceId> = pass
+                .uses
+                .iter()
+                .filter(|use_| use_.access == Access::Input)
+                .map(|use_| use_.resource)
+                .collect();
+            let current = groups.len().checked_sub(1);
+            let closed = current.is_some_and(|group: usize| {
+                groups[group]
+                    .passes
+                    .iter()
+                    .any(|&other| self.passes[other].samples())
+            });
+            let reads_current =
+                !inputs.is_empty() && inputs.iter().all(|input| writer[input.0] == current);
+            let fits_current = pass
+                .uses
+                .iter()
+                .all(|use_| user[use_.resource.0] == current && use_.clear.is_none());
+            if current.is_none() || closed || pass.samples() || !(reads_current || fits_current) {
+                groups.push(Group {
+                    passes: vec![],
+                    attachments: vec![],
+                });
+            }
+            let group_index = groups.len() - 1;
+            groups[group_index].passes.push(index);
+            for use_ in pass
+                .uses
+                .iter()
+                .filter(|use_| use_.access != Access::Sampled)
+            {
+                user[use_.resource.0] = Some(group_index);
+                if use_.access.writes() {
+                    writer[use_.resource.0] = Some(group_index);
+                }
+            }
+        }
+
+        // load op no primeiro uso dentro do render pass: limpa quando pedido,
+        // senão carrega o que já estava lá
+        let mut written = vec![false; self.resources.len()];
+        for group in &mut groups {
+            for &index in &group.passes {
+                for use_ in &self.passes[index].uses {
+                    if use_.access == Access::Sampled
+                        || group
+                            .attachments
+                            .iter()
+                            .any(|attachment| attachment.resource == use_.resource)
+                    {
+                        continue;
+                    }
+                    // o resolve sobrescreve a imagem toda
+                    let load_op = if use_.clear.is_some() {
+                        AttachmentLoadOp::Clear
+                    } else if use_.access == Access::Resolve {
+                        AttachmentLoadOp::DontCare
+                    } else if written[use_.resource.0] {
+                        AttachmentLoadOp::Load
+                    } else {
+                        AttachmentLoadOp::DontCare
+                    };
+                    group.attachments.push(Attachment {
+                        resource: use_.resource,
+                        load_op,
+                        store_op: AttachmentStoreOp::DontCare,
+                        clear: use_.clear,
+                    });
+                }
+            }
+            for attachment in &group.attachments {
+                written[attachment.resource.0] = true;
+            }
+        }
+
+        // store op: guarda o que sai do grafo ou é carregado ou lido como
+        // textura mais para frente
+        for index in (0..groups.len()).rev() {
+            let (current, later) = groups.split_at_mut(index + 1);
+            for attachment in &mut current[index].attachments {
+                let loaded_later = later.iter().any(|group| {
+                    group.attachments.iter().any(|other| {
+                        other.resource == attachment.resource
+                            && other.load_op == AttachmentLoadOp::Load
+                    }) || group.passes.iter().any(|&pass| {
+                        self.passes[pass].uses.iter().any(|use_| {
+                            use_.resource == attachment.resource && use_.access == Access::Sampled
+                        })
+                    })
+                });
+                if loaded_later || self.resources[attachment.resource.0].external {
+                    attachment.store_op = AttachmentStoreOp::Store;
+                }
+            }
+        }
+
+        // imagens só usadas como attachment dentro de render passes podem
+        // ficar só na memória do tile (TRANSIENT_ATTACHMENT)
+        let usage = self
+            .resources
+            .iter()
+            .enumerate()
+            .map(|(index, resource)| {
+                let usage = self
+                    .passes
+                    .iter()
+                    .zip(&live)
+                    .filter(|(_, &live)| live)
+                    .flat_map(|(pass, _)| &pass.uses)
+                    .filter(|use_| use_.resource.0 == index)
+                    .fold(ImageUsage::empty(), |usage, use_| {
+                        usage | use_.access.usage()
+                    });
+                let attachment = ImageUsage::COLOR_ATTACHMENT
+                    | ImageUsage::DEPTH_STENCIL_ATTACHMENT
+                    | ImageUsage::INPUT_ATTACHMENT;
+                if !resource.external && !usage.is_empty() && (usage - attachment).is_empty() {
+                    usage | ImageUsage::TRANSIENT_ATTACHMENT
+                } else {
+                    usage
+                }
+            })
+            .collect();
+
+        Plan {
+            live,
+            groups,
+            usage,
+        }
+    }
+
+    // render pass de um grupo, com os layouts do primeiro e do último uso
+    // de cada imagem e uma dependência entre cada par de subpasses que usa a
+    // mesma imagem
+    fn render_pass_info(&self, group: &Group) -> RenderPassCreateInfo {
+        let attachment_index = |resource: ResourceId| {
+            group
+                .attachments
+                .iter()
+                .position(|attachment| attachment.resource == resource)
+                .unwrap() as u32
+        };
+        let uses: Vec<&[Use]> = group
+            .passes
+            .iter()
+            .map(|&index| self.passes[index].uses.as_slice())
+            .collect();
+
+        let attachments = group
+            .attachments
+            .iter()
+            .map(|attachment| {
+                let mut layouts = uses
+                    .iter()
+                    .flat_map(|uses| uses.iter())
+                    .filter(|use_| {
+                        use_.resource == attachment.resource && use_.access != Access::Sampled
+                    })
+                    .map(|use_| use_.access.layout());
+                let initial_layout = layouts.next().unwrap();
+                let resource = &self.resources[attachment.resource.0];
+                AttachmentDescription {
+                    format: resource.format,
+                    samples: resource.samples,
+                    load_op: attachment.load_op,
+                    store_op: attachment.store_op,
+                    initial_layout,
+                    final_layout: layouts.next_back().unwrap_or(initial_layout),
+                    ..Default::default()
+                }
+            })
+            .collect();
+
+        let subpasses = uses
+            .iter()
+            .enumerate()
+            .map(|(subpass, uses)| {
+                let references = |access: Access| -> Vec<Option<AttachmentReference>> {
+                    uses.iter()
+                        .filter(|use_| use_.access == access)
+                        .map(|use_| {
+                            Some(AttachmentReference {
+                                attachment: attachment_index(use_.resource),
+                                layout: access.layout(),
+                                ..Default::default()
+                            })
+                        })
+                        .collect()
+                };
+                // imagens usadas antes e depois, mas não neste subpass
+                let used =
+                    |uses: &[Use], resource| uses.iter().any(|use_| use_.resource == resource);
+                let preserve_attachments = group
+                    .attachments
+                    .iter()
+                    .filter(|attachment| {
+                        let resource = attachment.resource;
+                        !used(uses, resource)
+                            && self.uses_before(&group.passes[..subpass], resource)
+                            && self.uses_before(&group.passes[subpass + 1..], resource)
+                    })
+                    .map(|attachment| attachment_index(attachment.resource))
+                    .collect();
+                SubpassDescription {
+                    input_attachments: references(Access::Input),
+                    color_attachments: references(Access::Color),
+                    color_resolve_attachments: references(Access::Resolve),
+                    depth_stencil_attachment: references(Access::Depth).pop().flatten(),
+                    preserve_attachments,
+                    ..Default::default()
+                }
+            })
+            .collect();
+
+        let mut dependencies: Vec<SubpassDependency> = vec![];
+        for (dst, dst_uses) in uses.iter().enumerate() {
+            for dst_use in dst_uses.iter() {
+                // último subpass antes deste que usou a imagem
+                let Some((src, src_use)) =
+                    uses[..dst]
+                        .iter()
+                        .enumerate()
+                        .rev()
+                        .find_map(|(src, uses)| {
+                            uses.iter()
+                                .find(|use_| use_.resource == dst_use.resource)
+                                .map(|use_| (src, use_))
+                        })
+                else {
+                    continue;
+                };
+                let (src, dst) = (src as u32, dst as u32);
+                let dependency = match dependencies.iter_mut().find(|dependency| {
+                    dependency.src_subpass == Some(src) && dependency.dst_subpass == Some(dst)
+                }) {
+                    Some(dependency) => dependency,
+                    None => {
+                        dependencies.push(SubpassDependency {
+                            src_subpass: Some(src),
+                            dst_subpass: Some(dst),
+                            dependency_flags: DependencyFlags::BY_REGION,
+                            ..Default::default()
+                        });
+                        dependencies.last_mut().unwrap()
+                    }
+                };
+                dependency.src_stages |= src_use.access.stages();
+                dependency.src_access |= src_use.access.access();
+                dependency.dst_stages |= dst_use.access.stages();
+                dependency.dst_access |= dst_use.access.access();
+            }
+        }
+
+        RenderPassCreateInfo {
+            attachments,
+            subpasses,
+            dependencies,
+            ..Default::default()
+        }
+    }
+
+    fn uses_before(&self, passes: &[usize], resource: ResourceId) -> bool {
+        passes.iter().any(|&index| {
+            self.passes[index]
+                .uses
+                .iter()
+                .any(|use_| use_.resource == resource)
+        })
+    }
+
+    // grafo no formato do graphviz (dot -Tpng grafo.dot -o grafo.png): um
+    // cluster por render pass, passes descartadas tracejadas e imagens de
+    // fora com borda dupla
+    pub fn to_dot(&self) -> String {
+        let plan = self.plan();
+        let mut dot = String::from("digraph render_graph {\n    rankdir=LR;\n");
+        for (index, group) in plan.groups.iter().enumerate() {
+            writeln!(dot, "    subgraph cluster_{index} {{").unwrap();
+            writeln!(dot, "        label=\"render pass {index}\";").unwrap();
+            for &pass in &group.passes {
+                writeln!(
+                    dot,
+                    "        p{pass} [label=\"{}\", shape=box];",
+                    self.passes[pass].name
+                )
+                .unwrap();
+            }
+            writeln!(dot, "    }}").unwrap();
+        }
+        for (index, pass) in self.passes.iter().enumerate() {
+            if !plan.live[index] {
+                writeln!(
+                    dot,
+                    "    p{index} [label=\"{}\", shape=box, style=dashed];",
+                    pass.name
+                )
+                .unwrap();
+            }
+        }
+        for (index, resource) in self.resources.iter().enumerate() {
+            writeln!(
+                dot,
+                "    r{index} [label=\"{}\\n{:?} x{}\", peripheries={}];",
+                resource.name,
+                resource.format,
+                resource.samples as u32,
+                if resource.external { 2 } else { 1 },
+            )
+            .unwrap();
+        }
+        for (index, pass) in self.passes.iter().enumerate() {
+            for use_ in &pass.uses {
+                let (from, to) = match use_.access {
+                    Access::Input | Access::Sampled => {
+                        (format!("r{}", use_.resource.0), format!("p{index}"))
+                    }
+                    _ => (format!("p{index}"), format!("r{}", use_.resource.0)),
+                };
+                writeln!(
+                    dot,
+                    "    {from} -> {to} [label=\"{}\"];",
+                    use_.access.name()
+                )
+                .unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    // cria as imagens, os render passes e os framebuffers
+    pub fn compile(&self, device: &GPU) -> CompiledGraph {
+        let plan = self.plan();
+
+        let views = self
+            .resources
+            .iter()
+            .zip(&plan.usage)
+            .map(|(resource, &usage)| {
+                if !resource.views.is_empty() || usage.is_empty() {
+                    // de fora ou não usada por nenhuma pass que sobrou
+                    return resource.views.clone();
+                }
+                vec![ImageView::new_default(
+                    Image::new(
+                        device.memory_allocator.clone(),
+                        ImageCreateInfo {
+                            image_type: ImageType::Dim2d,
+                            format: resource.format,
+                            extent: resource.extent,
+                            usage,
+                            samples: resource.samples,
+                            ..Default::default()
+                        },
+                        AllocationCreateInfo::default(),
+                    )
+                    .unwrap(),
+                )
+                .unwrap()]
+            })
+            .collect::<Vec<_>>();
+
+        let groups = plan
+            .groups
+            .iter()
+            .map(|group| {
+                let render_pass =
+                    RenderPass::new(device.clone(), self.render_pass_info(group)).unwrap();
+                // um framebuffer para cada imagem do output que o render pass
+                // escreve
+                let mut outputs = group
+                    .attachments
+                    .iter()
+                    .map(|attachment| attachment.resource)
+                    .filter(|resource| views[resource.0].len() > 1);
+                let output = outputs.next();
+                assert!(
+                    outputs.next().is_none(),
+                    "um render pass só pode escrever num output"
+                );
+                let count = output.map_or(1, |output| views[output.0].len());
+                let framebuffers = (0..count)
+                    .map(|index| {
+                        Framebuffer::new(
+                            render_pass.clone(),
+                            FramebufferCreateInfo {
+                                attachments: group
+                                    .attachments
+                                    .iter()
+                                    .map(|attachment| {
+                                        let views = &views[attachment.resource.0];
+                                        views[index.min(views.len() - 1)].clone()
+                                    })
+                                    .collect(),
+                                ..Default::default()
+                            },
+                        )
+                        .unwrap()
+                    })
+                    .collect();
+                CompiledGroup {
+                    passes: group.passes.iter().map(|&index| PassId(index)).collect(),
+                    output,
+                    framebuffers,
+                    clear_values: group
+                        .attachments
+                        .iter()
+                        .map(|attachment| match attachment.load_op {
+                            AttachmentLoadOp::Clear => attachment.clear,
+                            _ => None,
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+
+        CompiledGraph { views, groups }
+    }
+}
+
+struct CompiledGroup {
+    passes: Vec<PassId>,
+    // output com várias imagens, escolhida no execute
+    output: Option<ResourceId>,
+    framebuffers: Vec<Arc<Framebuffer>>,
+    clear_values: Vec<Option<ClearValue>>,
+}
+
+pub struct CompiledGraph {
+    views: Vec<Vec<Arc<ImageView>>>,
+    groups: Vec<CompiledGroup>,
+}
+
+impl CompiledGraph {
+    // None para imagens que nenhuma pass usa, de um output é a primeira
+    pub fn image(&self, resource: ResourceId) -> Option<Arc<ImageView>> {
+        self.views[resource.0].first().cloned()
+    }
+
+    fn group(&self, pass: PassId) -> (&CompiledGroup, u32) {
+        self.groups
+            .iter()
+            .find_map(|group| {
+                let subpass = group.passes.iter().position(|&other| other == pass)?;
+                Some((group, subpass as u32))
+            })
+            .expect("pass descartada pelo grafo")
+    }
+
+    // para criar as pipelines da pass
+    pub fn subpass(&self, pass: PassId) -> Subpass {
+        let (group, subpass) = self.group(pass);
+        Subpass::from(group.framebuffers[0].render_pass().clone(), subpass).unwrap()
+    }
+
+    // tamanho das imagens em que a pass desenha, para a viewport
+    pub fn extent(&self, pass: PassId) -> [u32; 2] {
+        self.group(pass).0.framebuffers[0].extent()
+    }
+
+    // grava as passes na ordem, record é chamado dentro do subpass de cada
+    // uma. outputs diz qual imagem de cada output é usada
+    pub fn execute(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        outputs: &[(ResourceId, usize)],
+        mut record: impl FnMut(PassId, &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>),
+    ) {
+        let subpass_begin_info = || SubpassBeginInfo {
+            contents: SubpassContents::Inline,
+            ..Default::default()
+        };
+        for group in &self.groups {
+            let framebuffer = match group.output {
+                Some(output) => outputs
+                    .iter()
+                    .find(|(resource, _)| *resource == output)
+                    .map(|&(_, index)| &group.framebuffers[index])
+                    .expect("imagem do output não escolhida no execute"),
+                None => &group.framebuffers[0],
+            };
+            builder
+                .begin_render_pass(
+                    RenderPassBeginInfo {
+                        clear_values: group.clear_values.clone(),
+                        ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
+                    },
+                    subpass_begin_info(),
+                )
+                .unwrap();
+            for (index, &pass) in group.passes.iter().enumerate() {
+                if index > 0 {
+                    builder
+                        .next_subpass(Default::default(), subpass_begin_info())
+                        .unwrap();
+                }
+                record(pass, builder);
+            }
+            builder.end_render_pass(Default::default()).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HDR: Format = Format::R16G16B16A16_SFLOAT;
+    const DEPTH: Format = Format::D32_SFLOAT;
+
+    fn clear() -> Option<ClearValue> {
+        Some([0.0; 4].into())
+    }
+
+    // faz o papel de um output sem precisar de imagens de verdade
+    fn external(graph: &mut RenderGraph, resource: ResourceId) {
+        graph.resources[resource.0].external = true;
+    }
+
+    // o mesmo formato do deferred do Renderer
+    fn deferred() -> (RenderGraph, [ResourceId; 4]) {
+        let mut graph = RenderGraph::new([64, 64, 1]);
+        let color = graph.transient("color", HDR, SampleCount::Sample1);
+        let albedo = graph.transient("albedo", HDR, SampleCount::Sample1);
+        let normal = graph.transient("normal", HDR, SampleCount::Sample1);
+        let depth = graph.transient("depth", DEPTH, SampleCount::Sample1);
+        external(&mut graph, color);
+        external(&mut graph, depth);
+        graph
+            .add_pass("gbuffer")
+            .color(albedo, clear())
+            .color(normal, clear())
+            .depth(depth, Some(0f32.into()));
+        graph
+            .add_pass("lighting")
+            .color(color, clear())
+            .input(albedo)
+            .input(normal)
+            .input(depth);
+        graph
+            .add_pass("transparent")
+            .color(color, None)
+            .depth(depth, None);
+        (graph, [color, albedo, normal, depth])
+    }
+
+    #[test]
+    fn input_attachments_merge_passes_into_subpasses() {
+        let (graph, [_, albedo, _, depth]) = deferred();
+        let plan = graph.plan();
+        assert_eq!(plan.groups.len(), 1);
+        assert_eq!(plan.groups[0].passes, vec![0, 1, 2]);
+
+        let info = graph.render_pass_info(&plan.groups[0]);
+        assert_eq!(info.subpasses.len(), 3);
+        assert_eq!(info.subpasses[1].input_attachments.len(), 3);
+        // o G-buffer só existe dentro do render pass
+        let albedo_index = plan.groups[0]
+            .attachments
+            .iter()
+            .position(|attachment| attachment.resource == albedo)
+            .unwrap();
+        assert_eq!(
+            info.attachments[albedo_index].store_op,
+            AttachmentStoreOp::DontCare
+        );
+        assert_eq!(
+            info.attachments[albedo_index].final_layout,
+            ImageLayout::ShaderReadOnlyOptimal
+        );
+        assert_eq!(
+            plan.usage[albedo.0],
+            ImageUsage::COLOR_ATTACHMENT
+                | ImageUsage::INPUT_ATTACHMENT
+                | ImageUsage::TRANSIENT_ATTACHMENT
+        );
+        assert_eq!(
+            plan.usage[depth.0],
+            ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::INPUT_ATTACHMENT
+        );
+    }
+
+    #[test]
+    fn dependencies_follow_the_resources_between_subpasses() {
+        let (graph, _) = deferred();
+        let plan = graph.plan();
+        let info = graph.render_pass_info(&plan.groups[0]);
+        let dependency = |src: u32, dst: u32| {
+            info.dependencies
+                .iter()
+                .find(|dependency| {
+                    dependency.src_subpass == Some(src) && dependency.dst_subpass == Some(dst)
+                })
+                .unwrap()
+        };
+
+        let gbuffer = dependency(0, 1);
+        assert!(gbuffer
+            .dst_access
+            .contains(AccessFlags::INPUT_ATTACHMENT_READ));
+        assert!(gbuffer.dst_stages.contains(PipelineStages::FRAGMENT_SHADER));
+        assert!(gbuffer
+            .src_access
+            .contains(AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE));
+        // a profundidade volta a ser depth buffer depois de ser lida
+        let transparent = dependency(1, 2);
+        assert!(transparent
+            .src_access
+            .contains(AccessFlags::INPUT_ATTACHMENT_READ));
+        assert!(transparent
+            .dst_access
+            .contains(AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE));
+        assert_eq!(info.dependencies.len(), 2);
+    }
+
+    #[test]
+    fn separate_passes_store_what_the_next_one_loads() {
+        let mut graph = RenderGraph::new([64, 64, 1]);
+        let color = graph.transient("color", HDR, SampleCount::Sample1);
+        let overlay = graph.transient("overlay", HDR, SampleCount::Sample1);
+        external(&mut graph, overlay);
+        graph.add_pass("scene").color(color, clear());
+        graph
+            .add_pass("overlay")
+            .color(overlay, clear())
+            .color(color, None);
+
+        let plan = graph.plan();
+        assert_eq!(plan.groups.len(), 2);
+        let scene = plan.groups[0].attachments[0];
+        assert_eq!(scene.load_op, AttachmentLoadOp::Clear);
+        assert_eq!(scene.store_op, AttachmentStoreOp::Store);
+        let loaded = plan.groups[1].attachments[1];
+        assert_eq!(loaded.load_op, AttachmentLoadOp::Load);
+        // ninguém usa color depois do overlay
+        assert_eq!(loaded.store_op, AttachmentStoreOp::DontCare);
+    }
+
+    #[test]
+    fn passes_that_reach_nothing_outside_are_culled() {
+        let (mut graph, [color, ..]) = deferred();
+        let unused = graph.transient("unused", HDR, SampleCount::Sample1);
+        graph.add_pass("debug").color(unused, clear()).input(color);
+
+        let plan = graph.plan();
+        assert_eq!(plan.live, vec![true, true, true, false]);
+        assert!(plan.usage[unused.0].is_empty());
+        let dot = graph.to_dot();
+        assert!(dot.contains("p3 [label=\"debug\", shape=box, style=dashed];"));
+        assert!(dot.contains("subgraph cluster_0"));
+        assert!(dot.contains("r1 -> p1 [label=\"input\"];"));
+    }
+
+    #[test]
+    fn passes_run_after_the_images_they_sample() {
+        let mut graph = RenderGraph::new([64, 64, 1]);
+        let color = graph.transient("color", HDR, SampleCount::Sample1);
+        let bloom = graph.transient_sized("bloom", HDR, [32, 32, 1]);
+        let output = graph.transient("output", HDR, SampleCount::Sample1);
+        external(&mut graph, output);
+        // declarada antes das passes que escrevem o que ela lê
+        graph
+            .add_pass("tone_map")
+            .color(output, None)
+            .sample(color)
+            .sample(bloom);
+        graph.add_pass("scene").color(color, clear());
+        graph.add_pass("bloom").color(bloom, None).sample(color);
+
+        let plan = graph.plan();
+        let order: Vec<usize> = plan
+            .groups
+            .iter()
+            .flat_map(|group| group.passes.clone())
+            .collect();
+        assert_eq!(order, vec![1, 2, 0]);
+        // quem lê textura fica num render pass próprio
+        assert_eq!(plan.groups.len(), 3);
+        assert_eq!(
+            plan.groups[0].attachments[0].store_op,
+            AttachmentStoreOp::Store
+        );
+        assert_eq!(
+            plan.groups[1].attachments[0].load_op,
+            AttachmentLoadOp::DontCare
+        );
+        assert_eq!(
+            plan.usage[color.0],
+            ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED
+        );
+        assert!(graph.to_dot().contains("r1 -> p0 [label=\"sampled\"];"));
+    }
+
+    #[test]
+    #[should_panic(expected = "lê albedo antes")]
+    fn reading_before_any_write_panics() {
+        let mut graph = RenderGraph::new([64, 64, 1]);
+        let color = graph.transient("color", HDR, SampleCount::Sample1);
+        let albedo = graph.transient("albedo", HDR, SampleCount::Sample1);
+        external(&mut graph, color);
+        graph
+            .add_pass("lighting")
+            .color(color, clear())
+            .input(albedo);
+        graph.plan();
+    }
+}
@@ -1,10 +1,23 @@
-use crate::prerender::PreRenderer;
+use crate::camera::Camera;
+use crate::config::{RenderPath, RendererConfig, SwapchainSelection};
+use crate::culling::{CullingStats, Frustum};
+use crate::debug_draw::{DebugDraw, DebugVertex};
+use crate::deferred::Deferred;
+use crate::light::{self, LightData, PointLight};
+use crate::object::{InstancedObject, Object};
+use crate::post::{self, PostChain, PostPasses, SceneImages, HDR_FORMAT};
+use crate::prerender::{MeshBuffers, PreRenderer};
+use crate::render_graph::{CompiledGraph, PassId, RenderGraph, ResourceId};
 use crate::shaders;
+use crate::skybox::Skybox;
+use crate::InstanceData;
 use std::sync::Arc;
 
+use glam::Vec4;
+
 use vulkano::buffer::allocator::SubbufferAllocator;
 use vulkano::buffer::allocator::SubbufferAllocatorCreateInfo;
-use vulkano::buffer::BufferUsage;
+use vulkano::buffer::{BufferContents, BufferUsage};
 use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
 use vulkano::descriptor_set::PersistentDescriptorSet;
 use vulkano::descriptor_set::WriteDescriptorSet;
@@ -13,40 +26,153 @@ use vulkano::{
     buffer::Subbuffer,
     command_buffer::{
         allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
-        PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassBeginInfo, SubpassContents,
+        PrimaryAutoCommandBuffer,
     },
     device::{Device, Queue},
-    format::Format,
-    image::{view::ImageView, Image, ImageCreateInfo, ImageType, ImageUsage},
-    memory::allocator::{AllocationCreateInfo, StandardMemoryAllocator},
-    pipeline::graphics::viewport::Viewport,
-    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass},
-    swapchain::{ColorSpace, Surface, SurfaceCapabilities, Swapchain, SwapchainCreateInfo},
+    format::{ClearValue, Format, FormatFeatures},
+    image::{Image, ImageAspects, ImageUsage, SampleCount, SampleCounts},
+    pipeline::{graphics::viewport::Viewport, GraphicsPipeline, Pipeline},
+    render_pass::Subpass,
+    shader::ShaderModule,
+    swapchain::{
+        ColorSpace, CompositeAlpha, PresentMode, Surface, SurfaceCapabilities, Swapchain,
+        SwapchainCreateInfo,
+    },
 };
 use winit::dpi::PhysicalSize;
 
 use crate::device::GPU;
 
+// um draw_indexed já com os dados prontos
+struct Draw<'a> {
+    pipeline: &'a Arc<GraphicsPipeline>,
+    mesh: &'a MeshBuffers,
+    textures: &'a Arc<PersistentDescriptorSet>,
+    instances: Subbuffer<[InstanceData]>,
+}
+
+// passes do grafo da cena (veja Renderer::scene_graph)
+enum ScenePasses {
+    Forward {
+        scene: PassId,
+    },
+    Deferred {
+        gbuffer: PassId,
+        lighting: PassId,
+        transparent: PassId,
+        // albedo, normal e material, lidos pela luz
+        gbuffer_images: [ResourceId; 3],
+    },
+}
+
+// subpasses do grafo onde cada parte da cena é desenhada, as pipelines dos
+// materiais, do céu e das linhas de debug são criadas para eles
+#[derive(Clone)]
+pub struct SceneSubpasses {
+    pub render_path: RenderPath,
+    // no deferred os opacos escrevem o G-buffer
+    pub opaque: Subpass,
+    pub transparent: Subpass,
+    // no deferred o céu vai antes da luz
+    pub sky: Subpass,
+    pub overlay: Subpass,
+}
+
+impl SceneSubpasses {
+    pub fn material(&self, transparent: bool) -> &Subpass {
+        if transparent {
+            &self.transparent
+        } else {
+            &self.opaque
+        }
+    }
+}
+
+impl ScenePasses {
+    fn subpasses(&self, graph: &CompiledGraph) -> SceneSubpasses {
+        match *self {
+            ScenePasses::Forward { scene } => SceneSubpasses {
+                render_path: RenderPath::Forward,
+                opaque: graph.subpass(scene),
+                transparent: graph.subpass(scene),
+                sky: graph.subpass(scene),
+                overlay: graph.subpass(scene),
+            },
+            ScenePasses::Deferred {
+                gbuffer,
+                lighting,
+                transparent,
+                ..
+            } => SceneSubpasses {
+                render_path: RenderPath::Deferred,
+                opaque: graph.subpass(gbuffer),
+                transparent: graph.subpass(transparent),
+                sky: graph.subpass(lighting),
+                overlay: graph.subpass(transparent),
+            },
+        }
+    }
+
+    fn contains(&self, pass: PassId) -> bool {
+        match *self {
+            ScenePasses::Forward { scene } => pass == scene,
+            ScenePasses::Deferred {
+                gbuffer,
+                lighting,
+                transparent,
+                ..
+            } => [gbuffer, lighting, transparent].contains(&pass),
+        }
+    }
+
+    // as linhas de debug vão por cima de tudo
+    fn last(&self) -> PassId {
+        match *self {
+            ScenePasses::Forward { scene } => scene,
+            ScenePasses::Deferred { transparent, .. } => transparent,
+        }
+    }
+}
+
 pub struct Renderer {
     pub swapchain: Arc<Swapchain>,
-    pub render_pass: Arc<RenderPass>,
-    pub framebuffers: Vec<Arc<Framebuffer>>,
+    pub subpasses: SceneSubpasses,
+    // a cena é desenhada em HDR e o PostChain escreve nas imagens da
+    // swapchain, tudo no mesmo grafo (veja frame_graph)
+    graph: CompiledGraph,
+    // o grafo como foi declarado, guardado para o graph_dot
+    declared: RenderGraph,
+    passes: ScenePasses,
+    pub post: PostChain,
     pub viewport: Viewport,
-    //pub images: Vec<Arc<Image>>,
+    pub selection: SwapchainSelection,
+    pub samples: SampleCount,
+    pub depth_format: Format,
+    pub reversed_z: bool,
+    pub render_path: RenderPath,
+    // só existe no deferred
+    deferred: Option<Deferred>,
+    pub images: Vec<Arc<Image>>,
     pub command_buffer_allocator: StandardCommandBufferAllocator,
     uniform_buffer_allocator: SubbufferAllocator,
+    vertex_buffer_allocator: SubbufferAllocator,
     descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
 }
 
 impl Renderer {
-    pub fn new(device: &GPU, surface: Arc<Surface>, dimensions: PhysicalSize<u32>) -> Self {
+    pub fn new(
+        device: &GPU,
+        surface: Arc<Surface>,
+        dimensions: PhysicalSize<u32>,
+        config: &RendererConfig,
+    ) -> Self {
         // ao inves de utilizarmos direto a superficíe para desenhar as imagens,
         // que não é ideal, pois pode causar efeitos estranhos já que renderizamos
         // a imagem em tempo real, utilizamos um swapchain que garante que a imagen
         // exibida foi renderizada.
         //
         // swapchain
-        let (swapchain, images) = Self::create_swapchain(
+        let (swapchain, images, selection) = Self::create_swapchain(
             device.clone(),
             surface.clone(),
             device
@@ -58,20 +184,38 @@ impl Renderer {
                 .physical_device
                 .surface_formats(&surface, Default::default())
                 .unwrap(),
+            device
+                .physical_device
+                .surface_present_modes(&surface, Default::default())
+                .unwrap()
+                .collect(),
+            config,
         );
 
-        // cria o caminho para o vulkan saber onde que os precisa mostrar
-        // as imagens, especificando cores e saturação (veja a função
-        // get_render_pass)
-        //
-        // render pass
-        let render_pass = Self::create_render_pass(device.clone(), swapchain.clone());
+        // amostras e formato de profundidade dos attachments da cena no grafo
+        // (veja scene_graph), o G-buffer do deferred não usa MSAA
+        let samples = match config.render_path {
+            RenderPath::Forward => Self::select_sample_count(device, config.msaa_samples),
+            RenderPath::Deferred => SampleCount::Sample1,
+        };
+        let depth_format = Self::select_depth_format(device, &config.depth_formats);
 
-        // Cria o buffer onde as imagens serão renderizadas antes de serem
-        // exibidas na tela
+        // As imagens onde a cena é renderizada antes de passar pelo
+        // pós-processamento e ser exibida na tela e os framebuffers ficam com
+        // o grafo
         //
         // framebuffers
-        let framebuffers = Self::create_framebuffers(&images, render_pass.clone(), device);
+        let (declared, passes, post_passes, scene) = Self::frame_graph(
+            device,
+            &images,
+            samples,
+            depth_format,
+            config.reversed_z,
+            config.render_path,
+            0,
+        );
+        let graph = declared.compile(device);
+        let subpasses = passes.subpasses(&graph);
 
         let descriptor_set_allocator = Arc::new(StandardDescriptorSetAllocator::new(
             device.clone(),
@@ -88,26 +232,60 @@ impl Renderer {
             },
         );
 
+        // buffers de instâncias, de linhas de debug e de luzes são recriados
+        // todo frame, as luzes também são lidas como storage buffer
+        let vertex_buffer_allocator = SubbufferAllocator::new(
+            device.memory_allocator.clone(),
+            SubbufferAllocatorCreateInfo {
+                buffer_usage: BufferUsage::VERTEX_BUFFER | BufferUsage::STORAGE_BUFFER,
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+        );
+
+        // A viewport basically describes the region of
+        // the framebuffer that the output will be rendered to.
+        // This will almost always be (0, 0) to (width, height)
+        //
+        // viewport
+        let viewport = Viewport {
+            offset: [0.0, 0.0],
+            extent: dimensions.into(),
+            depth_range: 0.0..=1.0,
+        };
+
+        let deferred = Self::deferred(
+            device,
+            &graph,
+            &passes,
+            scene,
+            &viewport,
+            &descriptor_set_allocator,
+        );
+        let post = PostChain::new(device, &graph, post_passes, vec![]);
+
         Self {
             swapchain,
-            render_pass,
-            framebuffers,
-            //images,
+            subpasses,
+            graph,
+            declared,
+            passes,
+            post,
+            selection,
+            samples,
+            depth_format,
+            reversed_z: config.reversed_z,
+            render_path: config.render_path,
+            deferred,
+            images,
             command_buffer_allocator: StandardCommandBufferAllocator::new(
                 device.clone(),
                 Default::default(),
             ),
-            // A viewport basically describes the region of
-            // the framebuffer that the output will be rendered to.
-            // This will almost always be (0, 0) to (width, height)
-            //
-            // viewport
-            viewport: Viewport {
-                offset: [0.0, 0.0],
-                extent: dimensions.into(),
-                depth_range: 0.0..=1.0,
-            },
+            viewport,
             uniform_buffer_allocator,
+            vertex_buffer_allocator,
             descriptor_set_allocator,
         }
     }
@@ -117,124 +295,525 @@ impl Renderer {
         aspect[0] as f32 / aspect[1] as f32
     }
 
+    // troca a quantidade de amostras do MSAA recriando o grafo, a pipeline
+    // precisa ser recriada depois disso (veja PreRenderer::rebuild_pipeline).
+    // O G-buffer do deferred não tem MSAA, aí nada muda e retorna false
+    pub fn set_msaa(&mut self, device: &GPU, samples: u32) -> bool {
+        if self.render_path == RenderPath::Deferred {
+            return false;
+        }
+        self.samples = Self::select_sample_count(device, samples);
+        self.rebuild(device);
+        true
+    }
+
+    // pass extra do pós-processamento (veja PostChain::add_pass), recria o
+    // grafo. Chamado antes de criar o PreRenderer, senão as pipelines dele
+    // precisam ser recriadas
+    pub fn add_post_pass(&mut self, device: &GPU, fs: Arc<ShaderModule>) {
+        self.post.add_pass(fs);
+        self.rebuild(device);
+    }
+
+    // recria o grafo do frame com as imagens, as passes do PostChain e a luz
+    // do deferred
+    fn rebuild(&mut self, device: &GPU) {
+        let (declared, passes, post_passes, scene) = Self::frame_graph(
+            device,
+            &self.images,
+            self.samples,
+            self.depth_format,
+            self.reversed_z,
+            self.render_path,
+            self.post.pass_count(),
+        );
+        self.graph = declared.compile(device);
+        self.declared = declared;
+        self.subpasses = passes.subpasses(&self.graph);
+        self.deferred = Self::deferred(
+            device,
+            &self.graph,
+            &passes,
+            scene,
+            &self.viewport,
+            &self.descriptor_set_allocator,
+        );
+        self.passes = passes;
+        self.post.rebuild(device, &self.graph, post_passes);
+    }
+
+    // só existe no deferred, lê o G-buffer do grafo
+    fn deferred(
+        device: &GPU,
+        graph: &CompiledGraph,
+        passes: &ScenePasses,
+        scene: SceneImages,
+        viewport: &Viewport,
+        descriptor_set_allocator: &Arc<StandardDescriptorSetAllocator>,
+    ) -> Option<Deferred> {
+        match *passes {
+            ScenePasses::Forward { .. } => None,
+            ScenePasses::Deferred {
+                lighting,
+                gbuffer_images,
+                ..
+            } => Some(Deferred::new(
+                device,
+                graph.subpass(lighting),
+                gbuffer_images.map(|image| graph.image(image).unwrap()),
+                post::depth_view(&graph.image(scene.depth).unwrap()),
+                viewport,
+                descriptor_set_allocator,
+            )),
+        }
+    }
+
+    // próxima quantidade de amostras que a GPU suporta, depois da maior
+    // volta para 1 (sem MSAA)
+    pub fn next_sample_count(&self, device: &GPU) -> u32 {
+        let supported = Self::supported_sample_counts(device);
+        [
+            SampleCount::Sample2,
+            SampleCount::Sample4,
+            SampleCount::Sample8,
+        ]
+        .into_iter()
+        .find(|&count| count as u32 > self.samples as u32 && supported.contains_enum(count))
+        .unwrap_or(SampleCount::Sample1) as u32
+    }
+
+    // o SSAO lê o depth buffer num shader
+    fn select_depth_format(device: &GPU, preferred: &[Format]) -> Format {
+        preferred
+            .iter()
+            .copied()
+            .find(|&format| {
+                device
+                    .physical_device
+                    .format_properties(format)
+                    .map(|properties| {
+                        properties
+                            .optimal_tiling_features
+                            .contains(
+                                FormatFeatures::DEPTH_STENCIL_ATTACHMENT
+                                    | FormatFeatures::SAMPLED_IMAGE,
+                            )
+                    })
+                    .unwrap_or(false)
+            })
+            .unwrap_or(Format::D16_UNORM)
+    }
+
+    fn supported_sample_counts(device: &GPU) -> SampleCounts {
+        let properties = device.physical_device.properties();
+        properties.framebuffer_color_sample_counts & properties.framebuffer_depth_sample_counts
+    }
+
+    // maior quantidade de amostras suportada que não passa do pedido
+    fn select_sample_count(device: &GPU, requested: u32) -> SampleCount {
+        let supported = Self::supported_sample_counts(device);
+
+        [
+            SampleCount::Sample8,
+            SampleCount::Sample4,
+            SampleCount::Sample2,
+        ]
+        .into_iter()
+        .find(|&count| count as u32 <= requested && supported.contains_enum(count))
+        .unwrap_or(SampleCount::Sample1)
+    }
+
     fn create_swapchain(
         logical_device: Arc<Device>,
         surface: Arc<Surface>,
         capabilities: SurfaceCapabilities,
         dimensions: PhysicalSize<u32>,
         formats: Vec<(Format, ColorSpace)>,
-    ) -> (Arc<Swapchain>, Vec<Arc<Image>>) {
-        // Essas duas variáveis podem trocar mas agora não vejo porque
-        let composite_alpha = capabilities
+        present_modes: Vec<PresentMode>,
+        config: &RendererConfig,
+    ) -> (Arc<Swapchain>, Vec<Arc<Image>>, SwapchainSelection) {
+        // opaco quando possível, senão o primeiro que a superfície aceitar
+        let composite_alpha = if capabilities
             .supported_composite_alpha
-            .into_iter()
-            .next()
-            .unwrap();
-        let image_format = formats[0].0;
+            .contains_enum(CompositeAlpha::Opaque)
+        {
+            CompositeAlpha::Opaque
+        } else {
+            capabilities
+                .supported_composite_alpha
+                .into_iter()
+                .next()
+                .unwrap()
+        };
+        let (image_format, image_color_space) = config.select_format(&formats);
+        let present_mode = config.select_present_mode(&present_modes);
+        let min_image_count =
+            config.select_image_count(capabilities.min_image_count, capabilities.max_image_count);
 
-        Swapchain::new(
+        let (swapchain, images) = Swapchain::new(
             logical_device,
             surface,
             SwapchainCreateInfo {
-                min_image_count: capabilities.min_image_count,
+                min_image_count,
                 image_format,
+                image_color_space,
                 image_extent: dimensions.into(),
                 image_usage: ImageUsage::COLOR_ATTACHMENT,
                 composite_alpha,
+                present_mode,
                 ..Default::default()
             },
         )
-        .unwrap()
+        .unwrap();
+
+        // o driver pode criar mais imagens do que o pedido
+        let selection = SwapchainSelection {
+            present_mode,
+            format: image_format,
+            color_space: image_color_space,
+            image_count: swapchain.image_count(),
+            composite_alpha,
+        };
+
+        (swapchain, images, selection)
     }
 
-    fn create_render_pass(device: Arc<Device>, swapchain: Arc<Swapchain>) -> Arc<RenderPass> {
-        // Isso provavelmente vai mudar drasticamente
-        vulkano::single_pass_renderpass!(
-            device.clone(),
-            attachments: {
-                color: {
-                    format: swapchain.image_format(),
-                    samples: 1,
-                    load_op: Clear,
-                    store_op: Store,
-                },
-                depth_stencil: {
-                    format: Format::D16_UNORM,
-                    samples: 1,
-                    load_op: Clear,
-                    store_op: DontCare,
-                },
+    // a cena e depois o PostChain, que escreve nas imagens da swapchain.
+    // extra_passes é a quantidade de passes de PostChain::add_pass
+    fn frame_graph(
+        device: &GPU,
+        images: &[Arc<Image>],
+        samples: SampleCount,
+        depth_format: Format,
+        reversed_z: bool,
+        render_path: RenderPath,
+        extra_passes: usize,
+    ) -> (RenderGraph, ScenePasses, PostPasses, SceneImages) {
+        let mut graph = RenderGraph::new(images[0].extent());
+        let (passes, scene) =
+            Self::scene_graph(&mut graph, samples, depth_format, reversed_z, render_path);
+        let post_passes = PostChain::declare(device, &mut graph, scene, images, extra_passes);
+        (graph, passes, post_passes, scene)
+    }
+
+    // passes e imagens da cena (veja RenderGraph). A segunda imagem de cor
+    // recebe só a luz ambiente (veja shaders::fs), ela e o depth buffer são
+    // lidos pelo SSAO. No deferred o G-buffer dos opacos, a luz lendo o
+    // G-buffer e o depth buffer como input attachments e os transparentes em
+    // forward por cima viram os subpasses 0, 1 e 2 de um render pass só (veja
+    // RenderPath)
+    fn scene_graph(
+        graph: &mut RenderGraph,
+        samples: SampleCount,
+        depth_format: Format,
+        reversed_z: bool,
+        render_path: RenderPath,
+    ) -> (ScenePasses, SceneImages) {
+        let clear_color = Some([0.22, 0.22, 0.22, 1.0].into());
+        // o céu não tem luz ambiente para o SSAO tirar
+        let clear_ambient = Some([0.0, 0.0, 0.0, 0.0].into());
+        // com reversed-Z o mais longe possível é 0.0. Os formatos com stencil
+        // (ex: D24_UNORM_S8_UINT) limpam o stencil junto
+        let far = if reversed_z { 0f32 } else { 1f32 };
+        let clear_depth = if depth_format.aspects().intersects(ImageAspects::STENCIL) {
+            Some(ClearValue::DepthStencil((far, 0)))
+        } else {
+            Some(ClearValue::Depth(far))
+        };
+
+        let color = graph.transient("scene_color", HDR_FORMAT, SampleCount::Sample1);
+        let ambient = graph.transient("ambient", HDR_FORMAT, SampleCount::Sample1);
+        let depth = graph.transient("depth", depth_format, samples);
+
+        let passes = match render_path {
+            RenderPath::Forward if samples == SampleCount::Sample1 => ScenePasses::Forward {
+                scene: graph
+                    .add_pass("scene")
+                    .color(color, clear_color)
+                    .color(ambient, clear_ambient)
+                    .depth(depth, clear_depth)
+                    .id(),
             },
-            pass: {
-                color: [color],
-                depth_stencil: {depth_stencil},
+            // com MSAA desenhamos em imagens com várias amostras que são
+            // resolvidas para as imagens HDR no final do subpass
+            RenderPath::Forward => {
+                let intermediary = graph.transient("intermediary", HDR_FORMAT, samples);
+                let ambient_intermediary =
+                    graph.transient("ambient_intermediary", HDR_FORMAT, samples);
+                ScenePasses::Forward {
+                    scene: graph
+                        .add_pass("scene")
+                        .color(intermediary, clear_color)
+                        .color(ambient_intermediary, clear_ambient)
+                        .resolve(color)
+                        .resolve(ambient)
+                        .depth(depth, clear_depth)
+                        .id(),
+                }
+            }
+            RenderPath::Deferred => {
+                // o albedo pode passar de 1.0 com o tint
+                let albedo = graph.transient("albedo", HDR_FORMAT, samples);
+                let normal = graph.transient("normal", HDR_FORMAT, samples);
+                let material = graph.transient("material", Format::R8G8B8A8_UNORM, samples);
+                // normal com w = 0 é um pixel sem luz
+                let clear_gbuffer = Some([0.0, 0.0, 0.0, 0.0].into());
+                ScenePasses::Deferred {
+                    gbuffer: graph
+                        .add_pass("gbuffer")
+                        .color(albedo, clear_gbuffer)
+                        .color(normal, clear_gbuffer)
+                        .color(material, clear_gbuffer)
+                        .depth(depth, clear_depth)
+                        .id(),
+                    lighting: graph
+                        .add_pass("lighting")
+                        .color(color, clear_color)
+                        .color(ambient, clear_ambient)
+                        .input(albedo)
+                        .input(normal)
+                        .input(material)
+                        .input(depth)
+                        .id(),
+                    transparent: graph
+                        .add_pass("transparent")
+                        .color(color, None)
+                        .color(ambient, None)
+                        .depth(depth, None)
+                        .id(),
+                    gbuffer_images: [albedo, normal, material],
+                }
+            }
+        };
+        (
+            passes,
+            SceneImages {
+                color,
+                ambient,
+                depth,
             },
         )
-        .unwrap()
     }
 
-    fn create_framebuffers(
-        images: &[Arc<Image>],
-        render_pass: Arc<RenderPass>,
-        device: &GPU,
-    ) -> Vec<Arc<Framebuffer>> {
-        let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
-        let depth_buffer = ImageView::new_default(
-            Image::new(
-                memory_allocator,
-                ImageCreateInfo {
-                    image_type: ImageType::Dim2d,
-                    format: Format::D16_UNORM,
-                    extent: images[0].extent(),
-                    usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
-                    ..Default::default()
-                },
-                AllocationCreateInfo::default(),
-            )
-            .unwrap(),
-        )
-        .unwrap();
-        images
-            .iter()
-            .map(|image| {
-                let view = ImageView::new_default(image.clone()).unwrap();
-                Framebuffer::new(
-                    render_pass.clone(),
-                    FramebufferCreateInfo {
-                        attachments: vec![view, depth_buffer.clone()],
-                        ..Default::default()
-                    },
-                )
-                .unwrap()
-            })
-            .collect::<Vec<_>>()
+    // grafo do frame no formato do graphviz (veja RenderGraph::to_dot)
+    pub fn graph_dot(&self) -> String {
+        self.declared.to_dot()
     }
 
-    pub fn create_command_buffer(
+    fn vertex_buffer<T: BufferContents + Copy>(&self, data: &[T]) -> Subbuffer<[T]> {
+        let buffer = self
+            .vertex_buffer_allocator
+            .allocate_slice(data.len() as u64)
+            .unwrap();
+        buffer.write().unwrap().copy_from_slice(data);
+        buffer
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_command_buffer<'a>(
         &self,
         queue: &Arc<Queue>,
-        prerender: &PreRenderer,
-        uniforms: &shaders::vs::Data,
-    ) -> Vec<Arc<PrimaryAutoCommandBuffer>> {
-        self.framebuffers
-            .iter()
-            .map(|framebuffer| {
-                let buffer: Subbuffer<shaders::vs::Data> =
-                    self.uniform_buffer_allocator.allocate_sized().unwrap();
-                *buffer.write().unwrap() = *uniforms;
-
-                let descriptor_set = {
-                    let descriptor_set_layouts = prerender.layout.set_layouts();
-                    let descriptor_set_layout = descriptor_set_layouts.get(0).unwrap();
-                    PersistentDescriptorSet::new(
-                        &self.descriptor_set_allocator,
-                        descriptor_set_layout.clone(),
-                        [WriteDescriptorSet::buffer(0, buffer.clone())], // 0 is the binding
-                        [],
-                    )
-                    .unwrap()
+        prerender: &'a PreRenderer,
+        camera: &Camera,
+        objects: &[Object],
+        instanced: &[InstancedObject],
+        lights: &[PointLight],
+        particles: &Subbuffer<[DebugVertex]>,
+        debug: &DebugDraw,
+    ) -> (Vec<Arc<PrimaryAutoCommandBuffer>>, CullingStats) {
+        // só grava o desenho do que aparece na câmera, cada objeto vira um
+        // desenho com uma instância só
+        let frustum = Frustum::from_camera(camera);
+        let (visible, mut stats) = frustum.cull(objects);
+        let lights = light::visible(&frustum, lights);
+
+        // opacos primeiro, depois os transparentes de trás para frente com a
+        // profundidade de cada um
+        let mut draws: Vec<Draw> = vec![];
+        let mut transparent: Vec<(f32, Draw)> = vec![];
+        let mut push = |draw: Draw<'a>, depth: Option<f32>| match depth {
+            Some(depth) => transparent.push((depth, draw)),
+            None => draws.push(draw),
+        };
+
+        for &index in &visible {
+            let object = &objects[index];
+            let meshes = &prerender.meshes[index];
+            let textures = &prerender.texture_sets[index];
+            let pipeline = prerender.pipeline(&object.material.state);
+            let level = object.lod.current();
+            let depth = object
+                .material
+                .state
+                .blend_mode
+                .is_transparent()
+                .then(|| camera.view_depth(object.world_sphere().center));
+
+            match object.lod.fading() {
+                None => push(
+                    Draw {
+                        pipeline,
+                        mesh: &meshes[level],
+                        textures,
+                        instances: self.vertex_buffer(&[object.instance_data()]),
+                    },
+                    depth,
+                ),
+                // durante a transição os dois níveis são desenhados
+                Some((previous, progress)) => {
+                    for (level, fade) in [(level, progress), (previous, progress - 1.0)] {
+                        let instance = InstanceData {
+                            instance_fade: fade,
+                            ..object.instance_data()
+                        };
+                        push(
+                            Draw {
+                                pipeline,
+                                mesh: &meshes[level],
+                                textures,
+                                instances: self.vertex_buffer(&[instance]),
+                            },
+                            depth,
+                        );
+                    }
+                }
+            }
+        }
+
+        for (index, instanced) in instanced.iter().enumerate() {
+            let (mut instances, instanced_stats) = frustum.cull_instances(instanced);
+            stats += instanced_stats;
+            if instances.is_empty() {
+                continue;
+            }
+
+            // as instâncias também são ordenadas entre si, o grupo entra na
+            // ordem com a profundidade da mais distante
+            let depth = instanced.material.state.blend_mode.is_transparent().then(|| {
+                let depth = |instance: &InstanceData| {
+                    camera.view_depth(Vec4::from(instance.instance_model[3]).truncate())
                 };
+                instances.sort_by(|a, b| depth(b).total_cmp(&depth(a)));
+                depth(&instances[0])
+            });
+            push(
+                Draw {
+                    pipeline: prerender.pipeline(&instanced.material.state),
+                    mesh: &prerender.instanced_meshes[index],
+                    textures: &prerender.instanced_texture_sets[index],
+                    instances: self.vertex_buffer(&instances),
+                },
+                depth,
+            );
+        }
+
+        // no deferred os opacos vão para o G-buffer e os transparentes para
+        // a última pass
+        let opaque = draws.len();
+        transparent.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        draws.extend(transparent.into_iter().map(|(_, draw)| draw));
+
+        let buffer: Subbuffer<shaders::vs::Data> =
+            self.uniform_buffer_allocator.allocate_sized().unwrap();
+        *buffer.write().unwrap() = shaders::vs::Data {
+            camera: (camera.projection * camera.view).to_cols_array_2d(),
+            camera_position: camera.translation.extend(1.0).to_array(),
+        };
 
+        // o storage buffer não pode ser vazio
+        let lights_buffer = self.vertex_buffer(if lights.is_empty() {
+            &[LightData::NONE]
+        } else {
+            &lights
+        });
+
+        let ibl = &prerender.skybox.ibl;
+        let scene_writes = || {
+            vec![
+                WriteDescriptorSet::buffer(0, buffer.clone()), // 0 is the binding
+                // ambiente refletido pelos materiais
+                WriteDescriptorSet::image_view_sampler(
+                    1,
+                    prerender.skybox.environment.view.clone(),
+                    prerender.skybox.environment.sampler.clone(),
+                ),
+                // luz ambiente (veja Ibl)
+                WriteDescriptorSet::image_view_sampler(
+                    2,
+                    ibl.irradiance.view.clone(),
+                    ibl.irradiance.sampler.clone(),
+                ),
+                WriteDescriptorSet::image_view_sampler(
+                    3,
+                    ibl.specular.view.clone(),
+                    ibl.specular.sampler.clone(),
+                ),
+                WriteDescriptorSet::image_view_sampler(
+                    4,
+                    ibl.brdf_lut.view.clone(),
+                    ibl.brdf_lut.sampler.clone(),
+                ),
+            ]
+        };
+        let descriptor_set = {
+            let descriptor_set_layouts = prerender.layout.set_layouts();
+            let descriptor_set_layout = descriptor_set_layouts.get(0).unwrap();
+            let mut writes = scene_writes();
+            writes.push(WriteDescriptorSet::buffer(5, lights_buffer.clone()));
+            PersistentDescriptorSet::new(
+                &self.descriptor_set_allocator,
+                descriptor_set_layout.clone(),
+                writes,
+                [],
+            )
+            .unwrap()
+        };
+        // a luz do deferred usa o mesmo set sem as luzes pontuais, que viram
+        // instâncias dos volumes
+        let deferred = self.deferred.as_ref().map(|deferred| {
+            let set = PersistentDescriptorSet::new(
+                &self.descriptor_set_allocator,
+                deferred.layout.set_layouts()[0].clone(),
+                scene_writes(),
+                [],
+            )
+            .unwrap();
+            let push_constants =
+                Deferred::push_constants(camera, self.reversed_z, self.viewport.extent);
+            (deferred, set, push_constants)
+        });
+
+        // as linhas usam o mesmo buffer da câmera num set do layout delas, as
+        // faíscas (veja ParticleSystem) já estão num buffer da GPU
+        let debug_set = |pipeline: &Arc<GraphicsPipeline>| {
+            PersistentDescriptorSet::new(
+                &self.descriptor_set_allocator,
+                pipeline.layout().set_layouts()[0].clone(),
+                [WriteDescriptorSet::buffer(0, buffer.clone())],
+                [],
+            )
+            .unwrap()
+        };
+        let debug_lines = [
+            (&prerender.debug_pipeline, &debug.lines),
+            (&prerender.debug_overlay_pipeline, &debug.overlay),
+        ]
+        .into_iter()
+        .filter(|(_, lines)| !lines.is_empty())
+        .map(|(pipeline, lines)| (pipeline, debug_set(pipeline), self.vertex_buffer(lines)))
+        .chain([(
+            &prerender.debug_pipeline,
+            debug_set(&prerender.debug_pipeline),
+            particles.clone(),
+        )])
+        .collect::<Vec<_>>();
+
+        let sky = Skybox::push_constants(camera, self.reversed_z);
+
+        // a cena é a mesma para todas as imagens da swapchain, só o final
+        // do pós-processamento muda
+        let command_buffers = (0..self.images.len())
+            .map(|image_index| {
                 let mut builder = AutoCommandBufferBuilder::primary(
                     &self.command_buffer_allocator,
                     queue.queue_family_index(),
@@ -242,26 +821,119 @@ impl Renderer {
                 )
                 .unwrap();
 
+                let last = self.passes.last();
+                self.post.begin(&mut builder, image_index);
+                let outputs = self.post.outputs(image_index);
+                self.graph.execute(&mut builder, &outputs, |pass, builder| {
+                    if !self.passes.contains(pass) {
+                        self.post.record(
+                            &self.graph,
+                            pass,
+                            builder,
+                            image_index,
+                            camera,
+                            self.reversed_z,
+                        );
+                        return;
+                    }
+                    match (&self.passes, &deferred) {
+                        (ScenePasses::Deferred { gbuffer, .. }, _) if pass == *gbuffer => {
+                            Self::record_draws(
+                                builder,
+                                prerender,
+                                &descriptor_set,
+                                &draws[..opaque],
+                            );
+                        }
+                        (
+                            ScenePasses::Deferred { lighting, .. },
+                            Some((deferred, set, push_constants)),
+                        ) if pass == *lighting => {
+                            Self::record_sky(builder, &prerender.skybox, sky);
+                            deferred.record(
+                                builder,
+                                set.clone(),
+                                *push_constants,
+                                (!lights.is_empty()).then_some(&lights_buffer),
+                            );
+                        }
+                        (ScenePasses::Deferred { .. }, _) => {
+                            Self::record_draws(
+                                builder,
+                                prerender,
+                                &descriptor_set,
+                                &draws[opaque..],
+                            );
+                        }
+                        (ScenePasses::Forward { .. }, _) => {
+                            Self::record_sky(builder, &prerender.skybox, sky);
+                            Self::record_draws(builder, prerender, &descriptor_set, &draws);
+                        }
+                    }
+                    if pass != last {
+                        return;
+                    }
+
+                    for (pipeline, set, lines) in &debug_lines {
+                        builder
+                            .bind_pipeline_graphics((*pipeline).clone())
+                            .unwrap()
+                            .bind_descriptor_sets(
+                                vulkano::pipeline::PipelineBindPoint::Graphics,
+                                pipeline.layout().clone(),
+                                0,
+                                set.clone(),
+                            )
+                            .unwrap()
+                            .bind_vertex_buffers(0, lines.clone())
+                            .unwrap()
+                            .draw(lines.len() as u32, 1, 0, 0)
+                            .unwrap();
+                    }
+                });
+
+                builder.build().unwrap()
+            })
+            .collect();
+
+        (command_buffers, stats)
+    }
+
+    // o céu não escreve profundidade, a cena desenha por cima
+    fn record_sky(
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        skybox: &Skybox,
+        push_constants: shaders::sky_fs::Sky,
+    ) {
+        builder
+            .bind_pipeline_graphics(skybox.pipeline.clone())
+            .unwrap()
+            .bind_descriptor_sets(
+                vulkano::pipeline::PipelineBindPoint::Graphics,
+                skybox.pipeline.layout().clone(),
+                0,
+                skybox.set.clone(),
+            )
+            .unwrap()
+            .push_constants(skybox.pipeline.layout().clone(), 0, push_constants)
+            .unwrap()
+            .draw(3, 1, 0, 0)
+            .unwrap();
+    }
+
+    fn record_draws(
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        prerender: &PreRenderer,
+        descriptor_set: &Arc<PersistentDescriptorSet>,
+        draws: &[Draw],
+    ) {
+        // todas as pipelines usam o mesmo layout, então os sets
+        // continuam ligados quando a pipeline troca
+        let mut bound: Option<&Arc<GraphicsPipeline>> = None;
+        for draw in draws {
+            if !bound.is_some_and(|bound| Arc::ptr_eq(bound, draw.pipeline)) {
                 builder
-                    .begin_render_pass(
-                        RenderPassBeginInfo {
-                            clear_values: vec![
-                                Some([0.22, 0.22, 0.22, 1.0].into()),
-                                Some(1f32.into()),
-                            ],
-                            ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
-                        },
-                        SubpassBeginInfo {
-                            contents: SubpassContents::Inline,
-                            ..Default::default()
-                        },
-                    )
-                    .unwrap()
-                    .bind_pipeline_graphics(prerender.pipeline.clone())
-                    .unwrap()
-                    .bind_vertex_buffers(0, prerender.vertex_buffer.clone())
-                    .unwrap()
-                    .bind_index_buffer(prerender.indices_buffer.clone())
+                    .bind_pipeline_graphics(draw.pipeline.clone())
                     .unwrap()
                     .bind_descriptor_sets(
                         vulkano::pipeline::PipelineBindPoint::Graphics,
@@ -269,14 +941,32 @@ impl Renderer {
                         0,
                         descriptor_set.clone(),
                     )
-                    .unwrap()
-                    .draw_indexed(prerender.indices_buffer.len() as u32, 1, 0, 0, 0)
-                    .unwrap()
-                    .end_render_pass(Default::default())
                     .unwrap();
-
-                builder.build().unwrap()
-            })
-            .collect()
+                bound = Some(draw.pipeline);
+            }
+            builder
+                .bind_descriptor_sets(
+                    vulkano::pipeline::PipelineBindPoint::Graphics,
+                    prerender.layout.clone(),
+                    1,
+                    draw.textures.clone(),
+                )
+                .unwrap()
+                .bind_vertex_buffers(
+                    0,
+                    (draw.mesh.vertex_buffer.clone(), draw.instances.clone()),
+                )
+                .unwrap()
+                .bind_index_buffer(draw.mesh.indices_buffer.clone())
+                .unwrap()
+                .draw_indexed(
+                    draw.mesh.indices_buffer.len() as u32,
+                    draw.instances.len() as u32,
+                    0,
+                    0,
+                    0,
+                )
+                .unwrap();
+        }
     }
 }
@@ -8,31 +8,518 @@ pub mod vs {
             layout(location = 1) in vec3 color;
             layout(location = 2) in vec3 normal;
             layout(location = 3) in vec2 texcoord;
+            layout(location = 10) in vec4 tangent;
+
+            // dados por instância (veja InstanceData)
+            layout(location = 4) in mat4 instance_model;
+            layout(location = 8) in vec3 instance_tint;
+            layout(location = 9) in float instance_fade;
+            layout(location = 11) in float instance_opacity;
+            layout(location = 12) in float instance_alpha_cutoff;
+            layout(location = 13) in float instance_reflectivity;
+            layout(location = 14) in vec2 instance_roughness_metallic;
 
             layout(location = 0) out vec3 fragColor;
+            layout(location = 1) flat out float fragFade;
+            layout(location = 2) out vec3 fragNormal;
+            layout(location = 3) out vec4 fragTangent;
+            layout(location = 4) out vec2 fragTexcoord;
+            layout(location = 5) out vec3 fragPosition;
+            layout(location = 6) flat out float fragOpacity;
+            layout(location = 7) flat out float fragAlphaCutoff;
+            layout(location = 8) flat out float fragReflectivity;
+            layout(location = 9) flat out vec2 fragRoughnessMetallic;
+
+            layout(set = 0, binding = 0) uniform Data {
+                mat4 camera;
+                vec4 camera_position;
+            } uniforms;
+
+            void main() {
+                vec4 worldPosition = instance_model * vec4(position, 1.0);
+                gl_Position = uniforms.camera * worldPosition;
+                fragPosition = worldPosition.xyz;
+
+                // a luz agora é calculada no fragment shader por causa do
+                // normal map
+                fragNormal = mat3(instance_model) * normal;
+                fragTangent = vec4(mat3(instance_model) * tangent.xyz, tangent.w);
+                fragTexcoord = texcoord;
+
+                fragColor = color * instance_tint;
+                fragFade = instance_fade;
+                fragOpacity = instance_opacity;
+                fragAlphaCutoff = instance_alpha_cutoff;
+                fragReflectivity = instance_reflectivity;
+                fragRoughnessMetallic = instance_roughness_metallic;
+            }
+        ",
+    }
+}
+
+pub mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 460
+
+            layout(location = 0) in vec3 color;
+            layout(location = 1) flat in float fade;
+            layout(location = 2) in vec3 normal;
+            layout(location = 3) in vec4 tangent;
+            layout(location = 4) in vec2 texcoord;
+            layout(location = 5) in vec3 position;
+            layout(location = 6) flat in float opacity;
+            layout(location = 7) flat in float alphaCutoff;
+            layout(location = 8) flat in float reflectivity;
+            layout(location = 9) flat in vec2 roughnessMetallic;
+
+            layout(location = 0) out vec4 f_color;
+            // só a parte de f_color que veio da luz ambiente, escurecida
+            // depois pela oclusão (veja ssao.rs)
+            layout(location = 1) out vec4 f_ambient;
 
+            // mesmo bloco do vs
             layout(set = 0, binding = 0) uniform Data {
                 mat4 camera;
-                mat4 modelMatrix;
+                vec4 camera_position;
             } uniforms;
+            // o mesmo cubemap do skybox (veja skybox.rs)
+            layout(set = 0, binding = 1) uniform samplerCube environment;
+            // luz ambiente tirada do environment (veja ibl.rs)
+            layout(set = 0, binding = 2) uniform samplerCube irradianceMap;
+            layout(set = 0, binding = 3) uniform samplerCube specularMap;
+            layout(set = 0, binding = 4) uniform sampler2D brdfLut;
+            // luzes pontuais visíveis (veja light.rs), raio 0 = nenhuma
+            struct Light {
+                vec4 position_radius;
+                vec4 color;
+            };
+            layout(set = 0, binding = 5) readonly buffer Lights {
+                Light data[];
+            } lights;
+
+            layout(set = 1, binding = 0) uniform sampler2D normalMap;
+            layout(set = 1, binding = 1) uniform sampler2D albedoMap;
+
+            // modo de visualização (veja ViewMode::shader_mode)
+            layout(constant_id = 0) const int VIEW_MODE = 0;
+            layout(constant_id = 1) const bool REVERSED_Z = false;
 
             const vec3 DIRECTION_TO_LIGHT = normalize(vec3(1.0, -3.0, -1.0));
-            const float AMBIENT = 0.02;
+
+            // matriz de bayer 4x4 normalizada
+            const float DITHER[16] = float[](
+                0.0 / 16.0,  8.0 / 16.0,  2.0 / 16.0,  10.0 / 16.0,
+                12.0 / 16.0, 4.0 / 16.0,  14.0 / 16.0, 6.0 / 16.0,
+                3.0 / 16.0,  11.0 / 16.0, 1.0 / 16.0,  9.0 / 16.0,
+                15.0 / 16.0, 7.0 / 16.0,  13.0 / 16.0, 5.0 / 16.0
+            );
+
+            // o cubemap tem +y para cima e o mundo tem +y para baixo
+            vec3 cubeDirection(vec3 direction) {
+                return vec3(direction.x, -direction.y, -direction.z);
+            }
+
+            // inverso do quadrado suavizado, com uma janela que zera no raio
+            vec3 pointLight(vec3 position, vec3 n, vec3 diffuseColor, Light light) {
+                vec3 toLight = light.position_radius.xyz - position;
+                float distance = length(toLight);
+                float window = clamp(1.0 - pow(distance / light.position_radius.w, 4.0), 0.0, 1.0);
+                float attenuation = window * window / (distance * distance + 1.0);
+                float lambert = max(dot(n, toLight / max(distance, 1e-4)), 0.0);
+                return lambert * attenuation * diffuseColor * light.color.rgb;
+            }
+
+            void main() {
+                // transição entre LODs com dithering, o nível que entra
+                // desenha os pixels que o nível que sai descarta
+                ivec2 pixel = ivec2(gl_FragCoord.xy) % 4;
+                float threshold = DITHER[pixel.y * 4 + pixel.x];
+                if (fade >= 0.0 ? threshold >= fade : threshold < fade + 1.0) {
+                    discard;
+                }
+
+                // recorte (cutout) vale em todos os modos
+                vec4 albedo = texture(albedoMap, texcoord);
+                float alpha = albedo.a * opacity;
+                if (alpha < alphaCutoff) {
+                    discard;
+                }
+                vec3 baseColor = color * albedo.rgb;
+                f_ambient = vec4(0.0, 0.0, 0.0, alpha);
+
+                vec3 normalWorldSpace = normalize(normal);
+
+                if (VIEW_MODE == 5) {
+                    f_color = vec4(baseColor, alpha);
+                    return;
+                }
+                if (VIEW_MODE == 3) {
+                    // sem reversed-Z o near é 0.0, a raiz deixa o longe visível
+                    float depth = REVERSED_Z ? gl_FragCoord.z : 1.0 - gl_FragCoord.z;
+                    f_color = vec4(vec3(pow(depth, 0.25)), 1.0);
+                    return;
+                }
+                if (VIEW_MODE == 4) {
+                    // normal do triângulo, virada para o mesmo lado da normal do vértice
+                    vec3 face = normalize(cross(dFdx(position), dFdy(position)));
+                    normalWorldSpace = dot(face, normalWorldSpace) < 0.0 ? -face : face;
+                }
+
+                // modelos sem coordenada de textura ficam sem tangente
+                if (VIEW_MODE != 4 && dot(tangent.xyz, tangent.xyz) > 0.0) {
+                    vec3 t = normalize(tangent.xyz - normalWorldSpace * dot(normalWorldSpace, tangent.xyz));
+                    vec3 b = cross(normalWorldSpace, t) * tangent.w;
+                    vec3 sampled = texture(normalMap, texcoord).xyz * 2.0 - 1.0;
+                    normalWorldSpace = normalize(mat3(t, b, normalWorldSpace) * sampled);
+                }
+
+                if (VIEW_MODE == 1) {
+                    f_color = vec4(normalWorldSpace * 0.5 + 0.5, 1.0);
+                    return;
+                }
+
+                if (VIEW_MODE == 2) {
+                    // xadrez 8x8 no espaço de textura
+                    ivec2 cell = ivec2(floor(texcoord * 8.0));
+                    baseColor = ((cell.x + cell.y) & 1) == 0 ? vec3(0.9) : vec3(0.2);
+                }
+
+                float roughness = roughnessMetallic.x;
+                float metallic = roughnessMetallic.y;
+                vec3 view = normalize(position - uniforms.camera_position.xyz);
+                vec3 r = reflect(view, normalWorldSpace);
+                float nv = max(dot(normalWorldSpace, -view), 0.0);
+
+                // ambiente com a aproximação split-sum: difuso da irradiância e
+                // especular do mip com a roughness do material
+                vec3 f0 = mix(vec3(0.04), baseColor, metallic);
+                vec3 fresnel = f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(1.0 - nv, 5.0);
+                vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * baseColor;
+                float level = roughness * float(textureQueryLevels(specularMap) - 1);
+                vec3 prefiltered = textureLod(specularMap, cubeDirection(r), level).rgb;
+                vec2 brdf = texture(brdfLut, vec2(nv, roughness)).rg;
+                vec3 ambient = diffuse * texture(irradianceMap, cubeDirection(normalWorldSpace)).rgb
+                    + prefiltered * (fresnel * brdf.x + brdf.y);
+
+                float lightIntensity = max(dot(normalWorldSpace, DIRECTION_TO_LIGHT), 0);
+                vec3 lit = ambient + lightIntensity * (1.0 - metallic) * baseColor;
+                for (int i = 0; i < lights.data.length(); i++) {
+                    if (lights.data[i].position_radius.w > 0.0) {
+                        lit += pointLight(position, normalWorldSpace, (1.0 - metallic) * baseColor, lights.data[i]);
+                    }
+                }
+                if (VIEW_MODE == 0 && reflectivity > 0.0) {
+                    lit = mix(lit, texture(environment, cubeDirection(r)).rgb, reflectivity);
+                    ambient *= 1.0 - reflectivity;
+                }
+
+                f_color = vec4(lit, alpha);
+                f_ambient = vec4(ambient, alpha);
+            }
+        ",
+    }
+}
+
+// G-buffer do deferred (veja Renderer::scene_graph), o mesmo
+// material do fs sem a luz
+pub mod gbuffer_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 460
+
+            layout(location = 0) in vec3 color;
+            layout(location = 1) flat in float fade;
+            layout(location = 2) in vec3 normal;
+            layout(location = 3) in vec4 tangent;
+            layout(location = 4) in vec2 texcoord;
+            layout(location = 5) in vec3 position;
+            layout(location = 6) flat in float opacity;
+            layout(location = 7) flat in float alphaCutoff;
+            layout(location = 8) flat in float reflectivity;
+            layout(location = 9) flat in vec2 roughnessMetallic;
+
+            layout(location = 0) out vec4 f_albedo;
+            // w = 0 nos modos de visualização sem luz, o albedo vai direto
+            // para a tela
+            layout(location = 1) out vec4 f_normal;
+            // roughness, metallic, reflectivity
+            layout(location = 2) out vec4 f_material;
+
+            layout(set = 1, binding = 0) uniform sampler2D normalMap;
+            layout(set = 1, binding = 1) uniform sampler2D albedoMap;
+
+            layout(constant_id = 0) const int VIEW_MODE = 0;
+            layout(constant_id = 1) const bool REVERSED_Z = false;
+
+            const float DITHER[16] = float[](
+                0.0 / 16.0,  8.0 / 16.0,  2.0 / 16.0,  10.0 / 16.0,
+                12.0 / 16.0, 4.0 / 16.0,  14.0 / 16.0, 6.0 / 16.0,
+                3.0 / 16.0,  11.0 / 16.0, 1.0 / 16.0,  9.0 / 16.0,
+                15.0 / 16.0, 7.0 / 16.0,  13.0 / 16.0, 5.0 / 16.0
+            );
 
             void main() {
-                gl_Position = uniforms.camera * uniforms.modelMatrix * vec4(position, 1.0);
+                ivec2 pixel = ivec2(gl_FragCoord.xy) % 4;
+                float threshold = DITHER[pixel.y * 4 + pixel.x];
+                if (fade >= 0.0 ? threshold >= fade : threshold < fade + 1.0) {
+                    discard;
+                }
+
+                vec4 albedo = texture(albedoMap, texcoord);
+                if (albedo.a * opacity < alphaCutoff) {
+                    discard;
+                }
+                vec3 baseColor = color * albedo.rgb;
+                f_normal = vec4(0.0);
+                f_material = vec4(roughnessMetallic, VIEW_MODE == 0 ? reflectivity : 0.0, 0.0);
 
-                vec3 normalWorldSpace = normalize(mat3(uniforms.modelMatrix) * normal);
+                vec3 normalWorldSpace = normalize(normal);
 
-                float lightIntensity = AMBIENT + max(dot(normalWorldSpace, DIRECTION_TO_LIGHT), 0);
+                if (VIEW_MODE == 5) {
+                    f_albedo = vec4(baseColor, 1.0);
+                    return;
+                }
+                if (VIEW_MODE == 3) {
+                    float depth = REVERSED_Z ? gl_FragCoord.z : 1.0 - gl_FragCoord.z;
+                    f_albedo = vec4(vec3(pow(depth, 0.25)), 1.0);
+                    return;
+                }
+                if (VIEW_MODE == 4) {
+                    vec3 face = normalize(cross(dFdx(position), dFdy(position)));
+                    normalWorldSpace = dot(face, normalWorldSpace) < 0.0 ? -face : face;
+                }
 
-                fragColor = lightIntensity * color;
+                if (VIEW_MODE != 4 && dot(tangent.xyz, tangent.xyz) > 0.0) {
+                    vec3 t = normalize(tangent.xyz - normalWorldSpace * dot(normalWorldSpace, tangent.xyz));
+                    vec3 b = cross(normalWorldSpace, t) * tangent.w;
+                    vec3 sampled = texture(normalMap, texcoord).xyz * 2.0 - 1.0;
+                    normalWorldSpace = normalize(mat3(t, b, normalWorldSpace) * sampled);
+                }
+
+                if (VIEW_MODE == 1) {
+                    f_albedo = vec4(normalWorldSpace * 0.5 + 0.5, 1.0);
+                    return;
+                }
+
+                if (VIEW_MODE == 2) {
+                    ivec2 cell = ivec2(floor(texcoord * 8.0));
+                    baseColor = ((cell.x + cell.y) & 1) == 0 ? vec3(0.9) : vec3(0.2);
+                }
+
+                f_albedo = vec4(baseColor, 1.0);
+                f_normal = vec4(normalWorldSpace, 1.0);
             }
         ",
     }
 }
 
-pub mod fs {
+// luz direcional e ambiente do deferred em tela cheia, lendo o G-buffer
+// (o vertex shader é o post_vs)
+pub mod deferred_light_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 460
+
+            layout(location = 0) out vec4 f_color;
+            layout(location = 1) out vec4 f_ambient;
+
+            // o mesmo set 0 do fs, sem as luzes pontuais
+            layout(set = 0, binding = 0) uniform Data {
+                mat4 camera;
+                vec4 camera_position;
+            } uniforms;
+            layout(set = 0, binding = 1) uniform samplerCube environment;
+            layout(set = 0, binding = 2) uniform samplerCube irradianceMap;
+            layout(set = 0, binding = 3) uniform samplerCube specularMap;
+            layout(set = 0, binding = 4) uniform sampler2D brdfLut;
+
+            layout(input_attachment_index = 0, set = 1, binding = 0) uniform subpassInput albedoInput;
+            layout(input_attachment_index = 1, set = 1, binding = 1) uniform subpassInput normalInput;
+            layout(input_attachment_index = 2, set = 1, binding = 2) uniform subpassInput materialInput;
+            layout(input_attachment_index = 3, set = 1, binding = 3) uniform subpassInput depthInput;
+
+            layout(push_constant) uniform Lighting {
+                mat4 inverse_view_projection;
+                vec2 inverse_screen_size;
+                float far_depth;
+            } lighting;
+
+            const vec3 DIRECTION_TO_LIGHT = normalize(vec3(1.0, -3.0, -1.0));
+
+            vec3 cubeDirection(vec3 direction) {
+                return vec3(direction.x, -direction.y, -direction.z);
+            }
+
+            void main() {
+                // o céu já foi desenhado no fundo
+                float depth = subpassLoad(depthInput).r;
+                if (depth == lighting.far_depth) {
+                    discard;
+                }
+                vec3 baseColor = subpassLoad(albedoInput).rgb;
+                vec4 normal = subpassLoad(normalInput);
+                vec3 material = subpassLoad(materialInput).rgb;
+                f_ambient = vec4(0.0);
+                if (normal.w == 0.0) {
+                    f_color = vec4(baseColor, 1.0);
+                    return;
+                }
+
+                vec2 ndc = gl_FragCoord.xy * lighting.inverse_screen_size * 2.0 - 1.0;
+                vec4 world = lighting.inverse_view_projection * vec4(ndc, depth, 1.0);
+                vec3 position = world.xyz / world.w;
+
+                // daqui para baixo igual ao fs
+                vec3 n = normal.xyz;
+                float roughness = material.r;
+                float metallic = material.g;
+                float reflectivity = material.b;
+                vec3 view = normalize(position - uniforms.camera_position.xyz);
+                vec3 r = reflect(view, n);
+                float nv = max(dot(n, -view), 0.0);
+
+                vec3 f0 = mix(vec3(0.04), baseColor, metallic);
+                vec3 fresnel = f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(1.0 - nv, 5.0);
+                vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * baseColor;
+                float level = roughness * float(textureQueryLevels(specularMap) - 1);
+                vec3 prefiltered = textureLod(specularMap, cubeDirection(r), level).rgb;
+                vec2 brdf = texture(brdfLut, vec2(nv, roughness)).rg;
+                vec3 ambient = diffuse * texture(irradianceMap, cubeDirection(n)).rgb
+                    + prefiltered * (fresnel * brdf.x + brdf.y);
+
+                float lightIntensity = max(dot(n, DIRECTION_TO_LIGHT), 0);
+                vec3 lit = ambient + lightIntensity * (1.0 - metallic) * baseColor;
+                if (reflectivity > 0.0) {
+                    lit = mix(lit, texture(environment, cubeDirection(r)).rgb, reflectivity);
+                    ambient *= 1.0 - reflectivity;
+                }
+
+                f_color = vec4(lit, 1.0);
+                f_ambient = vec4(ambient, 1.0);
+            }
+        ",
+    }
+}
+
+// volumes das luzes pontuais do deferred, uma icosfera por luz
+pub mod deferred_volume_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+            #version 460
+
+            layout(location = 0) in vec3 position;
+            // veja LightData
+            layout(location = 1) in vec4 light_position_radius;
+            layout(location = 2) in vec4 light_color;
+
+            layout(location = 0) flat out vec4 positionRadius;
+            layout(location = 1) flat out vec3 color;
+
+            layout(set = 0, binding = 0) uniform Data {
+                mat4 camera;
+                vec4 camera_position;
+            } uniforms;
+
+            // as faces da icosfera de raio 1 ficam dentro da esfera, um pouco
+            // maior garante que cobre todos os pixels da luz
+            const float VOLUME_SCALE = 1.25;
+
+            void main() {
+                vec3 world = light_position_radius.xyz
+                    + position * light_position_radius.w * VOLUME_SCALE;
+                gl_Position = uniforms.camera * vec4(world, 1.0);
+                positionRadius = light_position_radius;
+                color = light_color.rgb;
+            }
+        ",
+    }
+}
+
+// soma uma luz pontual nos pixels cobertos pelo volume
+pub mod deferred_volume_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 460
+
+            layout(location = 0) flat in vec4 positionRadius;
+            layout(location = 1) flat in vec3 color;
+
+            layout(location = 0) out vec4 f_color;
+            layout(location = 1) out vec4 f_ambient;
+
+            layout(input_attachment_index = 0, set = 1, binding = 0) uniform subpassInput albedoInput;
+            layout(input_attachment_index = 1, set = 1, binding = 1) uniform subpassInput normalInput;
+            layout(input_attachment_index = 2, set = 1, binding = 2) uniform subpassInput materialInput;
+            layout(input_attachment_index = 3, set = 1, binding = 3) uniform subpassInput depthInput;
+
+            // o mesmo do deferred_light_fs
+            layout(push_constant) uniform Lighting {
+                mat4 inverse_view_projection;
+                vec2 inverse_screen_size;
+                float far_depth;
+            } lighting;
+
+            void main() {
+                float depth = subpassLoad(depthInput).r;
+                vec4 normal = subpassLoad(normalInput);
+                if (depth == lighting.far_depth || normal.w == 0.0) {
+                    discard;
+                }
+                vec3 baseColor = subpassLoad(albedoInput).rgb;
+                vec3 material = subpassLoad(materialInput).rgb;
+
+                vec2 ndc = gl_FragCoord.xy * lighting.inverse_screen_size * 2.0 - 1.0;
+                vec4 world = lighting.inverse_view_projection * vec4(ndc, depth, 1.0);
+                vec3 position = world.xyz / world.w;
+
+                // mesma conta do pointLight do fs, o reflexo cobre a luz como
+                // no mix do fs
+                vec3 toLight = positionRadius.xyz - position;
+                float distance = length(toLight);
+                float window = clamp(1.0 - pow(distance / positionRadius.w, 4.0), 0.0, 1.0);
+                float attenuation = window * window / (distance * distance + 1.0);
+                float lambert = max(dot(normal.xyz, toLight / max(distance, 1e-4)), 0.0);
+                vec3 diffuse = (1.0 - material.g) * (1.0 - material.b) * baseColor;
+
+                f_color = vec4(lambert * attenuation * diffuse * color, 0.0);
+                f_ambient = vec4(0.0);
+            }
+        ",
+    }
+}
+
+// linhas de debug (veja debug_draw.rs)
+pub mod debug_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec3 position;
+            layout(location = 1) in vec3 color;
+
+            layout(location = 0) out vec3 fragColor;
+
+            // mesmo bloco do vs, usa o mesmo buffer
+            layout(set = 0, binding = 0) uniform Data {
+                mat4 camera;
+            } uniforms;
+
+            void main() {
+                gl_Position = uniforms.camera * vec4(position, 1.0);
+                fragColor = color;
+            }
+        ",
+    }
+}
+
+pub mod debug_fs {
     vulkano_shaders::shader! {
         ty: "fragment",
         src: "
@@ -48,3 +535,1008 @@ pub mod fs {
         ",
     }
 }
+
+// fundo desenhado antes da cena com um triângulo que cobre a tela toda
+pub mod sky_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+            #version 460
+
+            layout(location = 0) out vec2 ndc;
+
+            void main() {
+                ndc = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0;
+                gl_Position = vec4(ndc, 0.0, 1.0);
+            }
+        ",
+    }
+}
+
+pub mod sky_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 460
+
+            layout(location = 0) in vec2 ndc;
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 0, binding = 0) uniform samplerCube environment;
+
+            // inversa de projeção * rotação da câmera (sem a translação)
+            layout(push_constant) uniform Sky {
+                mat4 inverse_view_projection;
+                float near_depth;
+            } sky;
+
+            vec3 unproject(float depth) {
+                vec4 point = sky.inverse_view_projection * vec4(ndc, depth, 1.0);
+                return point.xyz / point.w;
+            }
+
+            void main() {
+                // igual a Camera::screen_ray, 0.5 é finito mesmo com o far no infinito
+                vec3 direction = normalize(unproject(0.5) - unproject(sky.near_depth));
+                // o cubemap tem +y para cima e o mundo tem +y para baixo
+                f_color = vec4(texture(environment, vec3(direction.x, -direction.y, -direction.z)).rgb, 1.0);
+            }
+        ",
+    }
+}
+
+// converte uma imagem equiretangular para as seis faces de um cubemap
+pub mod equirect_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: "
+            #version 460
+
+            layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0) uniform sampler2D equirectangular;
+            layout(set = 0, binding = 1, rgba16f) uniform writeonly image2DArray cubemap;
+
+            // direção do texel em cada face, igual a skybox::face_direction
+            vec3 faceDirection(uint face, vec2 uv) {
+                switch (face) {
+                    case 0: return vec3(1.0, -uv.y, -uv.x);
+                    case 1: return vec3(-1.0, -uv.y, uv.x);
+                    case 2: return vec3(uv.x, 1.0, uv.y);
+                    case 3: return vec3(uv.x, -1.0, -uv.y);
+                    case 4: return vec3(uv.x, -uv.y, 1.0);
+                    default: return vec3(-uv.x, -uv.y, -1.0);
+                }
+            }
+
+            void main() {
+                ivec3 texel = ivec3(gl_GlobalInvocationID);
+                ivec2 size = imageSize(cubemap).xy;
+                if (texel.x >= size.x || texel.y >= size.y) {
+                    return;
+                }
+
+                vec2 uv = (vec2(texel.xy) + 0.5) / vec2(size) * 2.0 - 1.0;
+                vec3 direction = normalize(faceDirection(texel.z, uv));
+                // longitude no u, o v = 0 é o topo (+y)
+                vec2 coord = vec2(
+                    atan(direction.z, direction.x) / (2.0 * 3.14159265) + 0.5,
+                    acos(clamp(direction.y, -1.0, 1.0)) / 3.14159265
+                );
+                imageStore(cubemap, texel, texture(equirectangular, coord));
+            }
+        ",
+    }
+}
+
+// luz ambiente (veja ibl.rs), todos usam as direções de skybox::face_direction
+pub mod irradiance_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: "
+            #version 460
+
+            layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0) uniform samplerCube environment;
+            layout(set = 0, binding = 1, rgba16f) uniform writeonly image2DArray irradiance;
+
+            const float PI = 3.14159265;
+            const float DELTA = 0.025;
+
+            vec3 faceDirection(uint face, vec2 uv) {
+                switch (face) {
+                    case 0: return vec3(1.0, -uv.y, -uv.x);
+                    case 1: return vec3(-1.0, -uv.y, uv.x);
+                    case 2: return vec3(uv.x, 1.0, uv.y);
+                    case 3: return vec3(uv.x, -1.0, -uv.y);
+                    case 4: return vec3(uv.x, -uv.y, 1.0);
+                    default: return vec3(-uv.x, -uv.y, -1.0);
+                }
+            }
+
+            void main() {
+                ivec3 texel = ivec3(gl_GlobalInvocationID);
+                ivec2 size = imageSize(irradiance).xy;
+                if (texel.x >= size.x || texel.y >= size.y) {
+                    return;
+                }
+
+                vec2 uv = (vec2(texel.xy) + 0.5) / vec2(size) * 2.0 - 1.0;
+                vec3 n = normalize(faceDirection(texel.z, uv));
+                vec3 up = abs(n.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(0.0, 0.0, 1.0);
+                vec3 right = normalize(cross(up, n));
+                up = cross(n, right);
+
+                // integral do cosseno sobre o hemisfério da normal
+                vec3 sum = vec3(0.0);
+                float count = 0.0;
+                for (float phi = 0.0; phi < 2.0 * PI; phi += DELTA) {
+                    for (float theta = 0.0; theta < 0.5 * PI; theta += DELTA) {
+                        vec3 local = vec3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+                        vec3 direction = local.x * right + local.y * up + local.z * n;
+                        sum += textureLod(environment, direction, 0.0).rgb * cos(theta) * sin(theta);
+                        count += 1.0;
+                    }
+                }
+                imageStore(irradiance, texel, vec4(PI * sum / count, 1.0));
+            }
+        ",
+    }
+}
+
+pub mod prefilter_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: "
+            #version 460
+
+            layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+            layout(set = 0, binding = 0) uniform samplerCube environment;
+            layout(set = 0, binding = 1, rgba16f) uniform writeonly image2DArray specular;
+
+            // roughness do mip sendo gerado (veja ibl::level_roughness)
+            layout(push_constant) uniform Prefilter {
+                float roughness;
+            } prefilter;
+
+            const float PI = 3.14159265;
+            const uint SAMPLES = 512;
+
+            vec3 faceDirection(uint face, vec2 uv) {
+                switch (face) {
+                    case 0: return vec3(1.0, -uv.y, -uv.x);
+                    case 1: return vec3(-1.0, -uv.y, uv.x);
+                    case 2: return vec3(uv.x, 1.0, uv.y);
+                    case 3: return vec3(uv.x, -1.0, -uv.y);
+                    case 4: return vec3(uv.x, -uv.y, 1.0);
+                    default: return vec3(-uv.x, -uv.y, -1.0);
+                }
+            }
+
+            vec2 hammersley(uint i, uint count) {
+                return vec2(float(i) / float(count), float(bitfieldReverse(i)) * 2.3283064365386963e-10);
+            }
+
+            // meia direção com a distribuição GGX em volta de n
+            vec3 importanceSampleGgx(vec2 xi, vec3 n, float roughness) {
+                float a = roughness * roughness;
+                float phi = 2.0 * PI * xi.x;
+                float cosTheta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+                float sinTheta = sqrt(1.0 - cosTheta * cosTheta);
+                vec3 up = abs(n.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+                vec3 tangent = normalize(cross(up, n));
+                vec3 bitangent = cross(n, tangent);
+                return normalize(tangent * cos(phi) * sinTheta + bitangent * sin(phi) * sinTheta + n * cosTheta);
+            }
+
+            void main() {
+                ivec3 texel = ivec3(gl_GlobalInvocationID);
+                ivec2 size = imageSize(specular).xy;
+                if (texel.x >= size.x || texel.y >= size.y) {
+                    return;
+                }
+
+                vec2 uv = (vec2(texel.xy) + 0.5) / vec2(size) * 2.0 - 1.0;
+                // supõe que a direção de visão é a própria normal
+                vec3 n = normalize(faceDirection(texel.z, uv));
+
+                vec3 sum = vec3(0.0);
+                float weight = 0.0;
+                for (uint i = 0; i < SAMPLES; i++) {
+                    vec3 h = importanceSampleGgx(hammersley(i, SAMPLES), n, prefilter.roughness);
+                    vec3 l = normalize(2.0 * dot(n, h) * h - n);
+                    float nl = dot(n, l);
+                    if (nl > 0.0) {
+                        sum += textureLod(environment, l, 0.0).rgb * nl;
+                        weight += nl;
+                    }
+                }
+                imageStore(specular, texel, vec4(sum / weight, 1.0));
+            }
+        ",
+    }
+}
+
+pub mod brdf_lut_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: "
+            #version 460
+
+            layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+            // só rg é usado: escala e soma do f0
+            layout(set = 0, binding = 0, rgba16f) uniform writeonly image2D lut;
+
+            const float PI = 3.14159265;
+            const uint SAMPLES = 512;
+
+            vec2 hammersley(uint i, uint count) {
+                return vec2(float(i) / float(count), float(bitfieldReverse(i)) * 2.3283064365386963e-10);
+            }
+
+            vec3 importanceSampleGgx(vec2 xi, float roughness) {
+                float a = roughness * roughness;
+                float phi = 2.0 * PI * xi.x;
+                float cosTheta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+                float sinTheta = sqrt(1.0 - cosTheta * cosTheta);
+                return vec3(cos(phi) * sinTheta, sin(phi) * sinTheta, cosTheta);
+            }
+
+            // Smith com o k usado para IBL
+            float geometry(float nv, float nl, float roughness) {
+                float k = roughness * roughness / 2.0;
+                return nv / (nv * (1.0 - k) + k) * nl / (nl * (1.0 - k) + k);
+            }
+
+            void main() {
+                ivec2 texel = ivec2(gl_GlobalInvocationID.xy);
+                ivec2 size = imageSize(lut);
+                if (texel.x >= size.x || texel.y >= size.y) {
+                    return;
+                }
+
+                vec2 coord = (vec2(texel) + 0.5) / vec2(size);
+                float nv = coord.x;
+                float roughness = coord.y;
+                // normal em +z
+                vec3 v = vec3(sqrt(1.0 - nv * nv), 0.0, nv);
+
+                float scale = 0.0;
+                float bias = 0.0;
+                for (uint i = 0; i < SAMPLES; i++) {
+                    vec3 h = importanceSampleGgx(hammersley(i, SAMPLES), roughness);
+                    vec3 l = normalize(2.0 * dot(v, h) * h - v);
+                    float nl = max(l.z, 0.0);
+                    float nh = max(h.z, 0.0);
+                    float vh = max(dot(v, h), 0.0);
+                    if (nl > 0.0) {
+                        float visibility = geometry(nv, nl, roughness) * vh / (nh * nv);
+                        float fresnel = pow(1.0 - vh, 5.0);
+                        scale += (1.0 - fresnel) * visibility;
+                        bias += fresnel * visibility;
+                    }
+                }
+                imageStore(lut, texel, vec4(scale / float(SAMPLES), bias / float(SAMPLES), 0.0, 1.0));
+            }
+        ",
+    }
+}
+
+// faíscas simuladas na GPU (veja particles.rs), desenhadas como linhas de
+// debug
+pub mod particles_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: "
+            #version 460
+
+            layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
+
+            struct Particle {
+                // w = vida restante em segundos
+                vec4 position_life;
+                vec4 velocity;
+            };
+
+            layout(set = 0, binding = 0) buffer Particles {
+                Particle particles[];
+            };
+            // dois DebugVertex por partícula, 6 floats cada (o vec3 do std430
+            // teria alinhamento de 16)
+            layout(set = 0, binding = 1) writeonly buffer Lines {
+                float lines[];
+            };
+
+            layout(push_constant) uniform Simulation {
+                // xyz = posição, w = tempo
+                vec4 emitter;
+                // xyz = gravidade, w = delta
+                vec4 gravity;
+                float lifetime;
+                float speed;
+            } simulation;
+
+            uint hash(uint x) {
+                x ^= x >> 16;
+                x *= 0x7feb352du;
+                x ^= x >> 15;
+                x *= 0x846ca68bu;
+                x ^= x >> 16;
+                return x;
+            }
+
+            float random(uint seed) {
+                return float(hash(seed)) / 4294967295.0;
+            }
+
+            void writeVertex(uint vertex, vec3 position, vec3 color) {
+                for (uint i = 0; i < 3; i++) {
+                    lines[vertex * 6 + i] = position[i];
+                    lines[vertex * 6 + 3 + i] = color[i];
+                }
+            }
+
+            void main() {
+                uint index = gl_GlobalInvocationID.x;
+                if (index >= particles.length()) {
+                    return;
+                }
+
+                Particle particle = particles[index];
+                float delta = simulation.gravity.w;
+                particle.position_life.w -= delta;
+                if (particle.position_life.w <= 0.0) {
+                    // renasce no emissor indo para cima (-y) num cone
+                    uint seed = hash(index) ^ floatBitsToUint(simulation.emitter.w);
+                    float angle = random(seed) * 6.2831853;
+                    float spread = random(seed + 1u) * 0.35;
+                    vec3 direction =
+                        normalize(vec3(cos(angle) * spread, -1.0, sin(angle) * spread));
+                    float life = simulation.lifetime * (0.5 + 0.5 * random(seed + 2u));
+                    float speed = simulation.speed * (0.7 + 0.3 * random(seed + 3u));
+                    particle.position_life = vec4(simulation.emitter.xyz, life);
+                    particle.velocity = vec4(direction * speed, 0.0);
+                } else {
+                    particle.velocity.xyz += simulation.gravity.xyz * delta;
+                    particle.position_life.xyz += particle.velocity.xyz * delta;
+                }
+                particles[index] = particle;
+
+                // rastro curto atrás da faísca, esfria no fim da vida
+                float heat = clamp(particle.position_life.w / simulation.lifetime, 0.0, 1.0);
+                vec3 color = mix(vec3(0.8, 0.1, 0.0), vec3(4.0, 2.0, 0.5), heat);
+                vec3 head = particle.position_life.xyz;
+                writeVertex(index * 2, head, color);
+                writeVertex(index * 2 + 1, head - particle.velocity.xyz * 0.03, color * 0.2);
+            }
+        ",
+    }
+}
+
+// passes de tela cheia depois da cena (veja post.rs)
+pub mod post_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+            #version 460
+
+            layout(location = 0) out vec2 uv;
+
+            void main() {
+                uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+                gl_Position = vec4(uv * 2.0 - 1.0, 0.0, 1.0);
+            }
+        ",
+    }
+}
+
+pub mod tone_map_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 460
+
+            layout(location = 0) in vec2 uv;
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 0, binding = 0) uniform sampler2D scene;
+            // metade do tamanho da tela (veja bloom.rs)
+            layout(set = 0, binding = 1) uniform sampler2D bloomMap;
+
+            // veja post::ToneMapping
+            layout(push_constant) uniform ToneMap {
+                float exposure;
+                int operator;
+                float gamma;
+                // 0.0 com o bloom desligado
+                float bloom_intensity;
+            } tone_map;
+
+            vec3 reinhard(vec3 color) {
+                return color / (1.0 + color);
+            }
+
+            // ajuste da curva do ACES feito pelo Krzysztof Narkowicz
+            vec3 aces(vec3 color) {
+                return clamp(
+                    (color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14),
+                    0.0,
+                    1.0
+                );
+            }
+
+            // AgX com a curva polinomial do Benjamin Wrensch
+            vec3 agxContrast(vec3 x) {
+                vec3 x2 = x * x;
+                vec3 x4 = x2 * x2;
+                return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4
+                    - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+            }
+
+            vec3 agx(vec3 color) {
+                const mat3 inset = mat3(
+                    0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+                    0.0784335999999992, 0.878468636469772, 0.0784336,
+                    0.0792237451477643, 0.0791661274605434, 0.879142973793104
+                );
+                const mat3 outset = mat3(
+                    1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+                    -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+                    -0.0990297440797205, -0.0989611768448433, 1.15107367264116
+                );
+                const float minEv = -12.47393;
+                const float maxEv = 4.026069;
+
+                color = inset * color;
+                color = clamp(log2(max(color, 1e-10)), minEv, maxEv);
+                color = (color - minEv) / (maxEv - minEv);
+                color = outset * agxContrast(color);
+                // a curva já devolve a cor com gamma 2.2, volta para linear
+                return pow(max(color, 0.0), vec3(2.2));
+            }
+
+            void main() {
+                vec3 color = texture(scene, uv).rgb;
+                // desligado o bloom não é desenhado e a imagem fica com lixo
+                if (tone_map.bloom_intensity > 0.0) {
+                    color += texture(bloomMap, uv).rgb * tone_map.bloom_intensity;
+                }
+                color *= tone_map.exposure;
+                switch (tone_map.operator) {
+                    case 1: color = reinhard(color); break;
+                    case 2: color = aces(color); break;
+                    case 3: color = agx(color); break;
+                    default: color = clamp(color, 0.0, 1.0); break;
+                }
+                // 1.0 quando a swapchain é sRGB (veja post::output_gamma)
+                color = pow(color, vec3(1.0 / tone_map.gamma));
+                f_color = vec4(color, 1.0);
+            }
+        ",
+    }
+}
+
+// cadeia de mips do bloom (veja bloom.rs)
+pub mod bloom_down_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 460
+
+            layout(location = 0) in vec2 uv;
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 0, binding = 0) uniform sampler2D source;
+
+            layout(push_constant) uniform Downsample {
+                // tamanho de um texel da entrada
+                vec2 texel_size;
+                float threshold;
+                // o primeiro lê a cena, aplica o threshold e a média de Karis
+                int first;
+            } down;
+
+            float luma(vec3 color) {
+                return dot(color, vec3(0.2126, 0.7152, 0.0722));
+            }
+
+            // média com peso menor para pixels muito claros, evita que um
+            // pixel só pisque (fireflies)
+            vec3 karisAverage(vec3 a, vec3 b, vec3 c, vec3 d) {
+                vec3 average = (a + b + c + d) * 0.25;
+                return average / (1.0 + luma(average));
+            }
+
+            vec3 tap(float x, float y) {
+                return texture(source, uv + vec2(x, y) * down.texel_size).rgb;
+            }
+
+            void main() {
+                // 13 amostras em volta do pixel
+                vec3 a = tap(-2.0, -2.0);
+                vec3 b = tap(0.0, -2.0);
+                vec3 c = tap(2.0, -2.0);
+                vec3 d = tap(-2.0, 0.0);
+                vec3 e = tap(0.0, 0.0);
+                vec3 f = tap(2.0, 0.0);
+                vec3 g = tap(-2.0, 2.0);
+                vec3 h = tap(0.0, 2.0);
+                vec3 i = tap(2.0, 2.0);
+                vec3 j = tap(-1.0, -1.0);
+                vec3 k = tap(1.0, -1.0);
+                vec3 l = tap(-1.0, 1.0);
+                vec3 m = tap(1.0, 1.0);
+
+                vec3 color;
+                if (down.first != 0) {
+                    color = karisAverage(j, k, l, m) * 0.5
+                        + karisAverage(a, b, d, e) * 0.125
+                        + karisAverage(b, c, e, f) * 0.125
+                        + karisAverage(d, e, g, h) * 0.125
+                        + karisAverage(e, f, h, i) * 0.125;
+                    // volta para a escala original depois do peso de Karis
+                    color /= max(1.0 - luma(color), 1e-4);
+
+                    // threshold com transição suave (knee) de metade do threshold
+                    float brightness = max(color.r, max(color.g, color.b));
+                    float knee = down.threshold * 0.5;
+                    float soft = clamp(brightness - down.threshold + knee, 0.0, 2.0 * knee);
+                    soft = soft * soft / (4.0 * knee + 1e-4);
+                    color *= max(soft, brightness - down.threshold) / max(brightness, 1e-4);
+                } else {
+                    color = e * 0.125
+                        + (a + c + g + i) * 0.03125
+                        + (b + d + f + h) * 0.0625
+                        + (j + k + l + m) * 0.125;
+                }
+                f_color = vec4(max(color, 0.0), 1.0);
+            }
+        ",
+    }
+}
+
+pub mod bloom_up_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 460
+
+            layout(location = 0) in vec2 uv;
+
+            layout(location = 0) out vec4 f_color;
+
+            // mip menor, somado no maior com blend aditivo
+            layout(set = 0, binding = 0) uniform sampler2D source;
+
+            layout(push_constant) uniform Upsample {
+                // em coordenadas de textura (veja BloomSettings::radius)
+                float radius;
+            } up;
+
+            void main() {
+                // filtro tenda 3x3
+                float r = up.radius;
+                vec3 color = texture(source, uv).rgb * 4.0;
+                color += (
+                    texture(source, uv + vec2(0.0, r)).rgb
+                    + texture(source, uv + vec2(0.0, -r)).rgb
+                    + texture(source, uv + vec2(r, 0.0)).rgb
+                    + texture(source, uv + vec2(-r, 0.0)).rgb
+                ) * 2.0;
+                color += texture(source, uv + vec2(r, r)).rgb
+                    + texture(source, uv + vec2(-r, r)).rgb
+                    + texture(source, uv + vec2(r, -r)).rgb
+                    + texture(source, uv + vec2(-r, -r)).rgb;
+                f_color = vec4(color / 16.0, 1.0);
+            }
+        ",
+    }
+}
+
+// oclusão ambiente em espaço de tela (veja ssao.rs)
+pub mod ssao_position_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 460
+
+            layout(location = 0) in vec2 uv;
+
+            // posição no espaço da câmera, onde só tem o céu fica a direção
+            // com w = 0 (usada pelo TAA, veja taa_motion_fs)
+            layout(location = 0) out vec4 f_position;
+
+            layout(set = 0, binding = 0) uniform sampler2D depthMap;
+
+            layout(push_constant) uniform Position {
+                mat4 inverse_projection;
+                // profundidade com que o depth buffer é limpo
+                float far_depth;
+            } position;
+
+            void main() {
+                float depth = texelFetch(depthMap, ivec2(gl_FragCoord.xy), 0).r;
+                vec4 view = position.inverse_projection * vec4(uv * 2.0 - 1.0, depth, 1.0);
+                // com o far no infinito o w do céu é 0
+                vec3 point = view.w != 0.0 ? view.xyz / view.w : view.xyz;
+                f_position = depth == position.far_depth ? vec4(normalize(point), 0.0) : vec4(point, 1.0);
+            }
+        ",
+    }
+}
+
+// o mesmo que o ssao_position_fs com o depth buffer do MSAA, usa só a
+// primeira amostra
+pub mod ssao_position_ms_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 460
+
+            layout(location = 0) in vec2 uv;
+
+            layout(location = 0) out vec4 f_position;
+
+            layout(set = 0, binding = 0) uniform sampler2DMS depthMap;
+
+            layout(push_constant) uniform Position {
+                mat4 inverse_projection;
+                float far_depth;
+            } position;
+
+            void main() {
+                float depth = texelFetch(depthMap, ivec2(gl_FragCoord.xy), 0).r;
+                vec4 view = position.inverse_projection * vec4(uv * 2.0 - 1.0, depth, 1.0);
+                // com o far no infinito o w do céu é 0
+                vec3 point = view.w != 0.0 ? view.xyz / view.w : view.xyz;
+                f_position = depth == position.far_depth ? vec4(normalize(point), 0.0) : vec4(point, 1.0);
+            }
+        ",
+    }
+}
+
+pub mod ssao_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 460
+
+            layout(location = 0) out vec4 f_occlusion;
+
+            layout(set = 0, binding = 0) uniform sampler2D positions;
+
+            // veja ssao::SsaoSettings
+            layout(push_constant) uniform Occlusion {
+                // pixels cobertos pelo raio a uma unidade da câmera
+                float radius_scale;
+                // na ortográfica o raio na tela não depende da distância
+                int perspective;
+                float radius;
+                float bias;
+                float intensity;
+            } occlusion;
+
+            const int SAMPLES = 16;
+            // ângulo de ouro, espalha as amostras numa espiral
+            const float GOLDEN_ANGLE = 2.39996323;
+
+            vec4 positionAt(ivec2 pixel) {
+                ivec2 size = textureSize(positions, 0);
+                return texelFetch(positions, clamp(pixel, ivec2(0), size - 1), 0);
+            }
+
+            // diferença com o vizinho mais próximo em profundidade, evita
+            // normais erradas nas bordas dos objetos
+            vec3 closest(vec3 center, vec3 a, vec3 b) {
+                return abs(a.z - center.z) < abs(b.z - center.z) ? a - center : center - b;
+            }
+
+            void main() {
+                ivec2 pixel = ivec2(gl_FragCoord.xy);
+                vec4 center = positionAt(pixel);
+                if (center.w == 0.0) {
+                    f_occlusion = vec4(1.0);
+                    return;
+                }
+                vec3 p = center.xyz;
+
+                // normal reconstruída a partir das posições vizinhas
+                vec3 dx = closest(p, positionAt(pixel + ivec2(1, 0)).xyz, positionAt(pixel - ivec2(1, 0)).xyz);
+                vec3 dy = closest(p, positionAt(pixel + ivec2(0, 1)).xyz, positionAt(pixel - ivec2(0, 1)).xyz);
+                vec3 n = normalize(cross(dx, dy));
+                if (dot(n, p) > 0.0) {
+                    n = -n;
+                }
+
+                float screenRadius = occlusion.radius_scale / (occlusion.perspective != 0 ? p.z : 1.0);
+                // rotação diferente em cada pixel de um bloco 4x4, o blur
+                // tira o padrão (veja ssao_blur_fs)
+                ivec2 tile = pixel % 4;
+                float rotation = float(tile.y * 4 + tile.x) / 16.0 * 6.28318531;
+
+                // Alchemy AO (McGuire et al.): vetores até as amostras que
+                // ficam acima do plano da superfície ocluem
+                float radius2 = occlusion.radius * occlusion.radius;
+                float sum = 0.0;
+                for (int i = 0; i < SAMPLES; i++) {
+                    float t = (float(i) + 0.5) / float(SAMPLES);
+                    float angle = rotation + float(i) * GOLDEN_ANGLE;
+                    vec2 offset = vec2(cos(angle), sin(angle)) * t * screenRadius;
+                    vec4 s = positionAt(pixel + ivec2(offset));
+                    if (s.w == 0.0) {
+                        continue;
+                    }
+                    vec3 v = s.xyz - p;
+                    float vv = dot(v, v);
+                    float falloff = max(1.0 - vv / radius2, 0.0);
+                    sum += max(dot(v, n) - occlusion.bias, 0.0) / (vv + 0.01) * falloff;
+                }
+                float ao = max(1.0 - 2.0 * occlusion.intensity * sum / float(SAMPLES), 0.0);
+                f_occlusion = vec4(ao);
+            }
+        ",
+    }
+}
+
+pub mod ssao_blur_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 460
+
+            layout(location = 0) out vec4 f_occlusion;
+
+            layout(set = 0, binding = 0) uniform sampler2D occlusionMap;
+            layout(set = 0, binding = 1) uniform sampler2D positions;
+
+            void main() {
+                ivec2 pixel = ivec2(gl_FragCoord.xy);
+                ivec2 size = textureSize(positions, 0);
+                float depth = texelFetch(positions, pixel, 0).z;
+
+                // média do bloco 4x4 das rotações do ssao_fs, só com os
+                // vizinhos numa profundidade parecida para não borrar as bordas
+                float sum = 0.0;
+                float weights = 0.0;
+                for (int y = -2; y < 2; y++) {
+                    for (int x = -2; x < 2; x++) {
+                        ivec2 neighbor = clamp(pixel + ivec2(x, y), ivec2(0), size - 1);
+                        vec4 position = texelFetch(positions, neighbor, 0);
+                        float weight = position.w * max(1.0 - abs(position.z - depth) / (0.1 * abs(depth) + 1e-4), 0.0);
+                        sum += texelFetch(occlusionMap, neighbor, 0).r * weight;
+                        weights += weight;
+                    }
+                }
+                f_occlusion = vec4(weights > 0.0 ? sum / weights : 1.0);
+            }
+        ",
+    }
+}
+
+pub mod ssao_composite_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 460
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 0, binding = 0) uniform sampler2D scene;
+            // parte da cor da cena que veio da luz ambiente (veja shaders::fs)
+            layout(set = 0, binding = 1) uniform sampler2D ambientMap;
+            layout(set = 0, binding = 2) uniform sampler2D occlusionMap;
+
+            layout(push_constant) uniform Composite {
+                // 0.0 com o SSAO desligado, a oclusão não é calculada
+                float strength;
+            } composite;
+
+            void main() {
+                ivec2 pixel = ivec2(gl_FragCoord.xy);
+                vec4 color = texelFetch(scene, pixel, 0);
+                vec3 ambient = texelFetch(ambientMap, pixel, 0).rgb;
+                float ao = mix(1.0, texelFetch(occlusionMap, pixel, 0).r, composite.strength);
+                f_color = vec4(color.rgb - ambient * (1.0 - ao), color.a);
+            }
+        ",
+    }
+}
+
+// anti-aliasing depois da cena (veja antialiasing.rs)
+pub mod fxaa_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 460
+
+            layout(location = 0) in vec2 uv;
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 0, binding = 0) uniform sampler2D source;
+
+            layout(push_constant) uniform Fxaa {
+                vec2 texel_size;
+                // 0 só copia a imagem (anti-aliasing desligado)
+                int enabled;
+            } fxaa;
+
+            const float REDUCE_MIN = 1.0 / 128.0;
+            const float REDUCE_MUL = 1.0 / 8.0;
+            const float SPAN_MAX = 8.0;
+
+            // a imagem ainda é HDR, a luminância é comprimida para as bordas
+            // claras não dominarem
+            float luma(vec3 color) {
+                float l = dot(color, vec3(0.299, 0.587, 0.114));
+                return l / (1.0 + l);
+            }
+
+            vec3 tap(vec2 offset) {
+                return texture(source, uv + offset * fxaa.texel_size).rgb;
+            }
+
+            void main() {
+                vec4 center = texture(source, uv);
+                if (fxaa.enabled == 0) {
+                    f_color = center;
+                    return;
+                }
+
+                // FXAA do Timothy Lottes na versão simples: acha a direção da
+                // borda pela luminância dos cantos e borra ao longo dela
+                float lumaNW = luma(tap(vec2(-1.0, -1.0)));
+                float lumaNE = luma(tap(vec2(1.0, -1.0)));
+                float lumaSW = luma(tap(vec2(-1.0, 1.0)));
+                float lumaSE = luma(tap(vec2(1.0, 1.0)));
+                float lumaM = luma(center.rgb);
+                float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+                float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+
+                vec2 direction = vec2(
+                    -((lumaNW + lumaNE) - (lumaSW + lumaSE)),
+                    (lumaNW + lumaSW) - (lumaNE + lumaSE)
+                );
+                float reduce = max((lumaNW + lumaNE + lumaSW + lumaSE) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+                float scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+                direction = clamp(direction * scale, -SPAN_MAX, SPAN_MAX);
+
+                vec3 a = 0.5 * (tap(direction * (1.0 / 3.0 - 0.5)) + tap(direction * (2.0 / 3.0 - 0.5)));
+                vec3 b = a * 0.5 + 0.25 * (tap(direction * -0.5) + tap(direction * 0.5));
+                float lumaB = luma(b);
+                // b pegou algo de fora da borda, fica com a média mais curta
+                f_color = vec4(lumaB < lumaMin || lumaB > lumaMax ? a : b, center.a);
+            }
+        ",
+    }
+}
+
+pub mod taa_motion_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 460
+
+            layout(location = 0) in vec2 uv;
+
+            // quanto o ponto do pixel andou na tela desde o frame anterior,
+            // em coordenadas de textura
+            layout(location = 0) out vec4 f_motion;
+
+            // posições no espaço da câmera (veja ssao_position_fs)
+            layout(set = 0, binding = 0) uniform sampler2D positions;
+
+            layout(push_constant) uniform Motion {
+                // do espaço da câmera atual para o clip do frame anterior, sem jitter
+                mat4 reprojection;
+                // jitter do frame atual em NDC (veja Camera::set_jitter)
+                vec2 jitter;
+            } motion;
+
+            void main() {
+                // o céu (w = 0) é reprojetado como direção, só a rotação conta
+                vec4 position = texelFetch(positions, ivec2(gl_FragCoord.xy), 0);
+                vec4 previous = motion.reprojection * position;
+                vec2 previousUv = previous.xy / previous.w * 0.5 + 0.5;
+                // o pixel viu o ponto deslocado pelo jitter
+                vec2 currentUv = uv - motion.jitter * 0.5;
+                f_motion = vec4(currentUv - previousUv, 0.0, 0.0);
+            }
+        ",
+    }
+}
+
+pub mod taa_resolve_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 460
+
+            layout(location = 0) in vec2 uv;
+
+            layout(location = 0) out vec4 f_color;
+            // o mesmo resultado, lido como histórico no próximo frame
+            layout(location = 1) out vec4 f_history;
+
+            layout(set = 0, binding = 0) uniform sampler2D current;
+            layout(set = 0, binding = 1) uniform sampler2D history;
+            layout(set = 0, binding = 2) uniform sampler2D motionMap;
+
+            layout(push_constant) uniform Resolve {
+                // quanto do frame atual entra no histórico, 1.0 descarta o histórico
+                float current_weight;
+            } resolve;
+
+            float luma(vec3 color) {
+                return dot(color, vec3(0.299, 0.587, 0.114));
+            }
+
+            void main() {
+                ivec2 pixel = ivec2(gl_FragCoord.xy);
+                ivec2 size = textureSize(current, 0);
+                vec3 color = texelFetch(current, pixel, 0).rgb;
+
+                // o histórico fica limitado às cores da vizinhança atual, o
+                // que apareceu ou sumiu não deixa rastro
+                vec3 low = color;
+                vec3 high = color;
+                for (int y = -1; y <= 1; y++) {
+                    for (int x = -1; x <= 1; x++) {
+                        vec3 neighbor = texelFetch(current, clamp(pixel + ivec2(x, y), ivec2(0), size - 1), 0).rgb;
+                        low = min(low, neighbor);
+                        high = max(high, neighbor);
+                    }
+                }
+
+                vec2 historyUv = uv - texelFetch(motionMap, pixel, 0).rg;
+                float weight = resolve.current_weight;
+                if (any(lessThan(historyUv, vec2(0.0))) || any(greaterThan(historyUv, vec2(1.0)))) {
+                    weight = 1.0;
+                }
+                // o histórico descartado pode ter lixo, o FXAA usa a mesma
+                // pass sem escrever nele (veja AntiAliasing::declare)
+                vec3 result = color;
+                if (weight < 1.0) {
+                    vec3 previous = clamp(texture(history, historyUv).rgb, low, high);
+
+                    // pesos de Karis, pixels muito claros não piscam
+                    float currentWeight = weight / (1.0 + luma(color));
+                    float historyWeight = (1.0 - weight) / (1.0 + luma(previous));
+                    result = (color * currentWeight + previous * historyWeight) / (currentWeight + historyWeight);
+                }
+                f_color = vec4(result, 1.0);
+                f_history = f_color;
+            }
+        ",
+    }
+}
+
+// pass extra do PostChain (veja PostChain::add_pass), escurece os cantos
+pub mod vignette_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 460
+
+            layout(location = 0) in vec2 uv;
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 0, binding = 0) uniform sampler2D source;
+
+            void main() {
+                vec4 color = texture(source, uv);
+                // 1.0 no centro, cai devagar até os cantos
+                vec2 offset = uv - 0.5;
+                float vignette = 1.0 - smoothstep(0.4, 0.8, length(offset));
+                f_color = vec4(color.rgb * mix(0.35, 1.0, vignette), color.a);
+            }
+        ",
+    }
+}
@@ -0,0 +1,368 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use glam::{DVec3, Vec3};
+
+use crate::object::Model;
+
+// simplificação por métrica de erro quadrático (Garland & Heckbert)
+//
+// usa colapso de meia aresta: um vértice é movido para cima do outro, assim
+// o vértice que fica mantém a própria normal e coordenada de textura. As
+// costuras de UV e normais já aparecem como vértices duplicados no modelo
+// (veja Model::load), então ficam na borda da topologia e esses vértices
+// nunca são movidos.
+
+// ratio é a fração de triângulos que deve sobrar (0.0 a 1.0), o resultado
+// pode ficar com mais triângulos se não tiver mais nada que dê para colapsar
+pub fn simplify(model: &Model, ratio: f32) -> Model {
+    let target = ((model.indices.len() / 3) as f32 * ratio.clamp(0.0, 1.0)) as usize;
+    Simplifier::new(model).run(target.max(1)).into_model(model)
+}
+
+// cadeia de LODs, cada ratio é em relação ao modelo original
+pub fn generate_lods(model: &Model, ratios: &[f32]) -> Vec<Model> {
+    ratios.iter().map(|&ratio| simplify(model, ratio)).collect()
+}
+
+// matriz simétrica 4x4, só a metade de cima
+#[derive(Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn from_plane(normal: DVec3, d: f64) -> Quadric {
+        let [a, b, c] = normal.to_array();
+        Quadric([
+            a * a,
+            a * b,
+            a * c,
+            a * d,
+            b * b,
+            b * c,
+            b * d,
+            c * c,
+            c * d,
+            d * d,
+        ])
+    }
+
+    fn add(&self, other: &Quadric) -> Quadric {
+        let mut sum = self.0;
+        for (value, other) in sum.iter_mut().zip(other.0) {
+            *value += other;
+        }
+        Quadric(sum)
+    }
+
+    fn error(&self, point: DVec3) -> f64 {
+        let [q00, q01, q02, q03, q11, q12, q13, q22, q23, q33] = self.0;
+        let [x, y, z] = point.to_array();
+        q00 * x * x + 2.0 * q01 * x * y + 2.0 * q02 * x * z + 2.0 * q03 * x
+            + q11 * y * y
+            + 2.0 * q12 * y * z
+            + 2.0 * q13 * y
+            + q22 * z * z
+            + 2.0 * q23 * z
+            + q33
+    }
+}
+
+// colapso de from para cima de to
+#[derive(PartialEq)]
+struct Collapse {
+    cost: f64,
+    from: u32,
+    to: u32,
+    // versões dos vértices quando o custo foi calculado, se mudarem o
+    // colapso está desatualizado
+    versions: (u32, u32),
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.cost.total_cmp(&other.cost)
+    }
+}
+
+struct Simplifier {
+    positions: Vec<DVec3>,
+    triangles: Vec<[u32; 3]>,
+    removed: Vec<bool>,
+    // triângulos que usam cada vértice
+    adjacency: Vec<Vec<usize>>,
+    quadrics: Vec<Quadric>,
+    locked: Vec<bool>,
+    versions: Vec<u32>,
+    alive_triangles: usize,
+}
+
+impl Simplifier {
+    fn new(model: &Model) -> Simplifier {
+        let positions: Vec<DVec3> = model
+            .vertices
+            .iter()
+            .map(|vertex| Vec3::from(vertex.position).as_dvec3())
+            .collect();
+        let triangles: Vec<[u32; 3]> = model
+            .indices
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+            .collect();
+
+        let mut adjacency = vec![vec![]; positions.len()];
+        let mut quadrics = vec![Quadric::default(); positions.len()];
+        let mut edges: HashMap<(u32, u32), u32> = HashMap::new();
+
+        for (index, triangle) in triangles.iter().enumerate() {
+            let [a, b, c] = triangle.map(|vertex| positions[vertex as usize]);
+            let normal = (b - a).cross(c - a).normalize_or_zero();
+            let quadric = Quadric::from_plane(normal, -normal.dot(a));
+
+            for i in 0..3 {
+                let vertex = triangle[i] as usize;
+                adjacency[vertex].push(index);
+                quadrics[vertex] = quadrics[vertex].add(&quadric);
+
+                let next = triangle[(i + 1) % 3];
+                let edge = (triangle[i].min(next), triangle[i].max(next));
+                *edges.entry(edge).or_default() += 1;
+            }
+        }
+
+        // arestas com um triângulo só são borda (ou costura)
+        let mut locked = vec![false; positions.len()];
+        for (&(a, b), &count) in &edges {
+            if count != 2 {
+                locked[a as usize] = true;
+                locked[b as usize] = true;
+            }
+        }
+
+        Simplifier {
+            versions: vec![0; positions.len()],
+            removed: vec![false; triangles.len()],
+            alive_triangles: triangles.len(),
+            positions,
+            triangles,
+            adjacency,
+            quadrics,
+            locked,
+        }
+    }
+
+    fn run(mut self, target: usize) -> Simplifier {
+        let mut heap = BinaryHeap::new();
+        for vertex in 0..self.positions.len() as u32 {
+            self.push_collapses(vertex, &mut heap);
+        }
+
+        while self.alive_triangles > target {
+            let Some(Reverse(collapse)) = heap.pop() else {
+                break;
+            };
+            let versions = (
+                self.versions[collapse.from as usize],
+                self.versions[collapse.to as usize],
+            );
+            if versions != collapse.versions || !self.can_collapse(collapse.from, collapse.to) {
+                continue;
+            }
+
+            self.collapse(collapse.from, collapse.to);
+            for vertex in self.neighbors(collapse.to) {
+                self.push_collapses(vertex, &mut heap);
+            }
+            self.push_collapses(collapse.to, &mut heap);
+        }
+
+        self
+    }
+
+    fn neighbors(&self, vertex: u32) -> HashSet<u32> {
+        self.adjacency[vertex as usize]
+            .iter()
+            .flat_map(|&triangle| self.triangles[triangle])
+            .filter(|&other| other != vertex)
+            .collect()
+    }
+
+    fn push_collapses(&self, from: u32, heap: &mut BinaryHeap<Reverse<Collapse>>) {
+        if self.locked[from as usize] {
+            return;
+        }
+        for to in self.neighbors(from) {
+            let quadric = self.quadrics[from as usize].add(&self.quadrics[to as usize]);
+            heap.push(Reverse(Collapse {
+                cost: quadric.error(self.positions[to as usize]),
+                from,
+                to,
+                versions: (self.versions[from as usize], self.versions[to as usize]),
+            }));
+        }
+    }
+
+    fn can_collapse(&self, from: u32, to: u32) -> bool {
+        // os dois vértices só podem ter em comum os vértices opostos dos
+        // triângulos da aresta, senão a malha fica não-manifold
+        let shared_triangles = self.adjacency[from as usize]
+            .iter()
+            .filter(|&&triangle| self.triangles[triangle].contains(&to))
+            .count();
+        let shared_neighbors = self
+            .neighbors(from)
+            .intersection(&self.neighbors(to))
+            .count();
+        if shared_neighbors != shared_triangles {
+            return false;
+        }
+
+        // nenhum triângulo pode virar do avesso
+        let target = self.positions[to as usize];
+        self.adjacency[from as usize].iter().all(|&triangle| {
+            let vertices = self.triangles[triangle];
+            if vertices.contains(&to) {
+                return true;
+            }
+            let [a, b, c] = vertices.map(|vertex| self.positions[vertex as usize]);
+            let before = (b - a).cross(c - a);
+            let [a, b, c] = vertices.map(|vertex| {
+                if vertex == from {
+                    target
+                } else {
+                    self.positions[vertex as usize]
+                }
+            });
+            let after = (b - a).cross(c - a);
+            before.dot(after) > 0.0
+        })
+    }
+
+    fn collapse(&mut self, from: u32, to: u32) {
+        for triangle in std::mem::take(&mut self.adjacency[from as usize]) {
+            if self.triangles[triangle].contains(&to) {
+                // triângulo da aresta some
+                self.removed[triangle] = true;
+                self.alive_triangles -= 1;
+                for vertex in self.triangles[triangle] {
+                    self.adjacency[vertex as usize].retain(|&other| other != triangle);
+                }
+            } else {
+                for vertex in self.triangles[triangle].iter_mut() {
+                    if *vertex == from {
+                        *vertex = to;
+                    }
+                }
+                self.adjacency[to as usize].push(triangle);
+            }
+        }
+
+        self.quadrics[to as usize] = self.quadrics[to as usize].add(&self.quadrics[from as usize]);
+        self.versions[from as usize] += 1;
+        self.versions[to as usize] += 1;
+    }
+
+    // compacta os vértices que sobraram
+    fn into_model(self, model: &Model) -> Model {
+        let mut remap: HashMap<u32, u32> = HashMap::new();
+        let mut vertices = vec![];
+        let mut indices = vec![];
+
+        for (triangle, removed) in self.triangles.iter().zip(self.removed) {
+            if removed {
+                continue;
+            }
+            for &vertex in triangle {
+                let index = *remap.entry(vertex).or_insert_with(|| {
+                    vertices.push(model.vertices[vertex as usize].clone());
+                    (vertices.len() - 1) as u32
+                });
+                indices.push(index);
+            }
+        }
+
+        Model::new(vertices, indices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MyVertex;
+
+    // grade plana de size x size quadrados no plano xz
+    fn grid(size: u32) -> Model {
+        let mut vertices = vec![];
+        for z in 0..=size {
+            for x in 0..=size {
+                vertices.push(MyVertex {
+                    position: [x as f32, 0.0, z as f32],
+                    color: [0.5; 3],
+                    normal: [0.0, 1.0, 0.0],
+                    texcoord: [x as f32 / size as f32, z as f32 / size as f32],
+                    tangent: [0.0; 4],
+                });
+            }
+        }
+        let mut indices = vec![];
+        for z in 0..size {
+            for x in 0..size {
+                let i = z * (size + 1) + x;
+                indices.extend([i, i + size + 1, i + 1, i + 1, i + size + 1, i + size + 2]);
+            }
+        }
+        Model::new(vertices, indices)
+    }
+
+    #[test]
+    fn flat_grid_simplifies_down_to_its_border() {
+        let model = grid(8);
+        let simplified = simplify(&model, 0.1);
+
+        assert!(simplified.indices.len() / 3 < model.indices.len() / 3 / 2);
+        // a borda fica travada, então o tamanho não muda
+        assert_eq!(simplified.aabb, model.aabb);
+        // todos os vértices que sobraram ainda estão no plano
+        assert!(simplified.vertices.iter().all(|vertex| vertex.position[1] == 0.0));
+        // e cobrem a mesma área sem virar triângulos
+        let area: f32 = simplified
+            .indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(simplified.vertices[triangle[i] as usize].position));
+                (b - a).cross(c - a).y * 0.5
+            })
+            .sum();
+        assert!((area - 64.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn keeps_vertex_attributes() {
+        let model = Model::load("obj/vase.obj");
+        let simplified = simplify(&model, 0.25);
+
+        assert!(simplified.indices.len() < model.indices.len());
+        assert!(simplified.indices.len() / 3 >= model.indices.len() / 3 / 4);
+        // cada vértice que sobrou é um vértice original inteiro
+        assert!(simplified
+            .vertices
+            .iter()
+            .all(|vertex| model.vertices.contains(vertex)));
+    }
+
+    #[test]
+    fn lod_chain_gets_smaller() {
+        let model = Model::load("obj/vase.obj");
+        let lods = generate_lods(&model, &[0.5, 0.25]);
+        assert_eq!(lods.len(), 2);
+        assert!(lods[0].indices.len() < model.indices.len());
+        assert!(lods[1].indices.len() < lods[0].indices.len());
+    }
+}
@@ -0,0 +1,439 @@
+use std::sync::Arc;
+
+use glam::{Vec3, Vec4};
+use vulkano::{
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    format::Format,
+    image::{
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::{ImageView, ImageViewCreateInfo, ImageViewType},
+        Image, ImageCreateFlags, ImageCreateInfo, ImageType, ImageUsage, SampleCount,
+    },
+    memory::allocator::AllocationCreateInfo,
+    pipeline::{
+        graphics::{
+            depth_stencil::DepthStencilState,
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            vertex_input::VertexInputState,
+            viewport::{Viewport, ViewportState},
+            GraphicsPipelineCreateInfo,
+        },
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+        GraphicsPipeline, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo,
+    },
+    render_pass::Subpass,
+    shader::ShaderModule,
+};
+
+use crate::{
+    camera::Camera,
+    compute::ComputeKernel,
+    device::GPU,
+    ibl::Ibl,
+    prerender,
+    shaders,
+    texture::{self, Texture, TextureData},
+};
+
+// tamanho de cada face do céu gerado, o gradiente é suave
+const GRADIENT_SIZE: u32 = 32;
+
+// de onde vem o céu, as direções são as do cubemap (+y para cima)
+pub enum Environment {
+    // seis imagens na ordem +x, -x, +y, -y, +z, -z
+    Cubemap([String; 6]),
+    // imagem HDR com a longitude na horizontal, convertida na GPU
+    Equirectangular(String),
+    // cores lineares, o horizonte fica entre o zênite e o chão
+    Gradient {
+        zenith: Vec3,
+        horizon: Vec3,
+        ground: Vec3,
+    },
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Environment::Gradient {
+            zenith: Vec3::new(0.15, 0.3, 0.65),
+            horizon: Vec3::new(0.7, 0.75, 0.8),
+            ground: Vec3::new(0.2, 0.18, 0.16),
+        }
+    }
+}
+
+impl Environment {
+    // --sky céu.hdr ou --sky px.png,nx.png,py.png,ny.png,pz.png,nz.png
+    pub fn from_arg(arg: &str) -> Environment {
+        let files: Vec<String> = arg.split(',').map(str::to_string).collect();
+        match <[String; 6]>::try_from(files) {
+            Ok(faces) => Environment::Cubemap(faces),
+            Err(_) => Environment::Equirectangular(arg.to_string()),
+        }
+    }
+
+    // cor do gradiente numa direção, só vale para Gradient
+    pub fn gradient_color(&self, direction: Vec3) -> Vec3 {
+        let Environment::Gradient {
+            zenith,
+            horizon,
+            ground,
+        } = *self
+        else {
+            panic!("not a gradient environment");
+        };
+        let elevation = direction.normalize().y;
+        if elevation >= 0.0 {
+            horizon.lerp(zenith, elevation.sqrt())
+        } else {
+            horizon.lerp(ground, (-elevation).sqrt())
+        }
+    }
+}
+
+// direção do cubemap que a vulkan amostra no ponto (u, v) da face, u e v de
+// -1 a 1 com v para baixo na imagem
+pub fn face_direction(face: usize, u: f32, v: f32) -> Vec3 {
+    match face {
+        0 => Vec3::new(1.0, -v, -u),
+        1 => Vec3::new(-1.0, -v, u),
+        2 => Vec3::new(u, 1.0, v),
+        3 => Vec3::new(u, -1.0, -v),
+        4 => Vec3::new(u, -v, 1.0),
+        _ => Vec3::new(-u, -v, -1.0),
+    }
+}
+
+pub struct Skybox {
+    // também usado nos reflexos dos materiais (veja Material::reflectivity)
+    pub environment: Texture,
+    // luz ambiente gerada a partir do environment
+    pub ibl: Ibl,
+    vs: Arc<ShaderModule>,
+    fs: Arc<ShaderModule>,
+    pub pipeline: Arc<GraphicsPipeline>,
+    pub set: Arc<PersistentDescriptorSet>,
+}
+
+impl Skybox {
+    pub fn new(
+        device: &GPU,
+        subpass: &Subpass,
+        viewport: &Viewport,
+        samples: SampleCount,
+    ) -> Skybox {
+        let vs = shaders::sky_vs::load(device.clone()).expect("failed to create shader module");
+        let fs = shaders::sky_fs::load(device.clone()).expect("failed to create shader module");
+        let pipeline = Self::get_pipeline(device, &vs, &fs, subpass, viewport, samples);
+        let environment = Self::load(device, &Environment::default());
+        let ibl = Ibl::new(device, &environment);
+        let set = Self::get_set(device, &pipeline, &environment);
+        Skybox {
+            environment,
+            ibl,
+            vs,
+            fs,
+            pipeline,
+            set,
+        }
+    }
+
+    // os descriptor sets que usam o environment antigo precisam ser
+    // recriados (o Renderer cria o set 0 todo frame)
+    pub fn set_environment(&mut self, device: &GPU, environment: &Environment) {
+        self.environment = Self::load(device, environment);
+        self.ibl = Ibl::new(device, &self.environment);
+        self.set = Self::get_set(device, &self.pipeline, &self.environment);
+    }
+
+    pub fn rebuild_pipeline(
+        &mut self,
+        device: &GPU,
+        subpass: &Subpass,
+        viewport: &Viewport,
+        samples: SampleCount,
+    ) {
+        self.pipeline = Self::get_pipeline(device, &self.vs, &self.fs, subpass, viewport, samples);
+        self.set = Self::get_set(device, &self.pipeline, &self.environment);
+    }
+
+    // sem a translação da câmera o céu fica sempre infinitamente longe
+    pub fn push_constants(camera: &Camera, reversed_z: bool) -> shaders::sky_fs::Sky {
+        let mut rotation = camera.view;
+        rotation.w_axis = Vec4::W;
+        shaders::sky_fs::Sky {
+            inverse_view_projection: (camera.projection * rotation).inverse().to_cols_array_2d(),
+            near_depth: if reversed_z { 1.0 } else { 0.0 },
+        }
+    }
+
+    fn load(device: &GPU, environment: &Environment) -> Texture {
+        match environment {
+            Environment::Cubemap(files) => {
+                let faces = files.clone().map(|file| TextureData::load(&file));
+                Texture::cubemap(device, &faces, Format::R8G8B8A8_SRGB)
+            }
+            Environment::Equirectangular(file) => equirectangular_to_cubemap(device, file),
+            Environment::Gradient { .. } => {
+                Texture::cubemap(device, &gradient_faces(environment), Format::R8G8B8A8_SRGB)
+            }
+        }
+    }
+
+    fn get_set(
+        device: &GPU,
+        pipeline: &Arc<GraphicsPipeline>,
+        environment: &Texture,
+    ) -> Arc<PersistentDescriptorSet> {
+        let descriptor_set_allocator =
+            StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+        PersistentDescriptorSet::new(
+            &descriptor_set_allocator,
+            pipeline.layout().set_layouts()[0].clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                environment.view.clone(),
+                environment.sampler.clone(),
+            )],
+            [],
+        )
+        .unwrap()
+    }
+
+    // um triângulo que cobre a tela, desenhado antes de tudo sem tocar na
+    // profundidade
+    fn get_pipeline(
+        device: &GPU,
+        vs: &Arc<ShaderModule>,
+        fs: &Arc<ShaderModule>,
+        subpass: &Subpass,
+        viewport: &Viewport,
+        samples: SampleCount,
+    ) -> Arc<GraphicsPipeline> {
+        let stages = [
+            PipelineShaderStageCreateInfo::new(vs.entry_point("main").unwrap()),
+            PipelineShaderStageCreateInfo::new(fs.entry_point("main").unwrap()),
+        ];
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+                .into_pipeline_layout_create_info(device.clone())
+                .unwrap(),
+        )
+        .unwrap();
+
+        GraphicsPipeline::new(
+            device.clone(),
+            None,
+            GraphicsPipelineCreateInfo {
+                stages: stages.into_iter().collect(),
+                vertex_input_state: Some(VertexInputState::default()),
+                input_assembly_state: Some(InputAssemblyState::default()),
+                viewport_state: Some(ViewportState {
+                    viewports: [viewport.clone()].into_iter().collect(),
+                    ..Default::default()
+                }),
+                rasterization_state: Some(RasterizationState::default()),
+                // no deferred o subpass do céu não tem depth buffer
+                depth_stencil_state: subpass
+                    .subpass_desc()
+                    .depth_stencil_attachment
+                    .is_some()
+                    .then(DepthStencilState::default),
+                multisample_state: Some(MultisampleState {
+                    rasterization_samples: samples,
+                    ..Default::default()
+                }),
+                color_blend_state: Some(prerender::color_only_blend_state(subpass)),
+                subpass: Some(subpass.clone().into()),
+                ..GraphicsPipelineCreateInfo::layout(layout)
+            },
+        )
+        .unwrap()
+    }
+}
+
+// faces do gradiente já em sRGB
+fn gradient_faces(environment: &Environment) -> [TextureData; 6] {
+    let encode = |linear: f32| {
+        let srgb = if linear <= 0.0031308 {
+            linear * 12.92
+        } else {
+            1.055 * linear.powf(1.0 / 2.4) - 0.055
+        };
+        (srgb.clamp(0.0, 1.0) * 255.0).round() as u8
+    };
+    std::array::from_fn(|face| {
+        let pixels = (0..GRADIENT_SIZE * GRADIENT_SIZE)
+            .flat_map(|i| {
+                let texel = |i: u32| (i as f32 + 0.5) / GRADIENT_SIZE as f32 * 2.0 - 1.0;
+                let direction =
+                    face_direction(face, texel(i % GRADIENT_SIZE), texel(i / GRADIENT_SIZE));
+                let color = environment.gradient_color(direction);
+                [encode(color.x), encode(color.y), encode(color.z), 255]
+            })
+            .collect();
+        TextureData {
+            width: GRADIENT_SIZE,
+            height: GRADIENT_SIZE,
+            pixels,
+        }
+    })
+}
+
+// a imagem é desenhada nas seis faces por um compute shader
+// (veja shaders::equirect_cs), as faces ficam com metade da altura da imagem
+fn equirectangular_to_cubemap(device: &GPU, file: &str) -> Texture {
+    let image = image::open(file)
+        .expect("Failed to load image!")
+        .into_rgba32f();
+    let size = (image.height() / 2).max(1);
+    let source = texture::upload(
+        device,
+        image.as_raw(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: Format::R32G32B32A32_SFLOAT,
+            extent: [image.width(), image.height(), 1],
+            usage: ImageUsage::SAMPLED,
+            ..Default::default()
+        },
+    );
+    // filtro linear em R32G32B32A32 é opcional, o nearest sempre funciona
+    let source_sampler = Sampler::new(
+        device.clone(),
+        SamplerCreateInfo {
+            mag_filter: Filter::Nearest,
+            min_filter: Filter::Nearest,
+            address_mode: [
+                SamplerAddressMode::Repeat,
+                SamplerAddressMode::ClampToEdge,
+                SamplerAddressMode::ClampToEdge,
+            ],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let cubemap = Image::new(
+        device.memory_allocator.clone(),
+        ImageCreateInfo {
+            flags: ImageCreateFlags::CUBE_COMPATIBLE,
+            image_type: ImageType::Dim2d,
+            format: Format::R16G16B16A16_SFLOAT,
+            extent: [size, size, 1],
+            array_layers: 6,
+            usage: ImageUsage::STORAGE | ImageUsage::SAMPLED,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )
+    .unwrap();
+    // o shader escreve nas faces como um array de imagens 2D
+    let faces = ImageView::new(
+        cubemap.clone(),
+        ImageViewCreateInfo {
+            view_type: ImageViewType::Dim2dArray,
+            ..ImageViewCreateInfo::from_image(&cubemap)
+        },
+    )
+    .unwrap();
+
+    let kernel = ComputeKernel::new(
+        device,
+        shaders::equirect_cs::load(device.clone()).expect("failed to create shader module"),
+        [8, 8, 1],
+    );
+
+    let descriptor_set_allocator =
+        StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+    let set = kernel.set(
+        &descriptor_set_allocator,
+        0,
+        [
+            WriteDescriptorSet::image_view_sampler(
+                0,
+                ImageView::new_default(source).unwrap(),
+                source_sampler,
+            ),
+            WriteDescriptorSet::image_view(1, faces),
+        ],
+    );
+
+    texture::submit_and_wait(device, |builder| {
+        kernel.bind(builder, set);
+        kernel.dispatch(builder, [size, size, 6]);
+    });
+
+    Texture::from_cube_image(device, cubemap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // seleção de face e coordenadas da especificação da vulkan
+    // (tabela "Cube map face selection")
+    fn sample_face(direction: Vec3) -> (usize, f32, f32) {
+        let abs = direction.abs();
+        let (face, sc, tc, ma) = if abs.x >= abs.y && abs.x >= abs.z {
+            if direction.x > 0.0 {
+                (0, -direction.z, -direction.y, abs.x)
+            } else {
+                (1, direction.z, -direction.y, abs.x)
+            }
+        } else if abs.y >= abs.z {
+            if direction.y > 0.0 {
+                (2, direction.x, direction.z, abs.y)
+            } else {
+                (3, direction.x, -direction.z, abs.y)
+            }
+        } else if direction.z > 0.0 {
+            (4, direction.x, -direction.y, abs.z)
+        } else {
+            (5, -direction.x, -direction.y, abs.z)
+        };
+        (face, sc / ma, tc / ma)
+    }
+
+    #[test]
+    fn face_direction_matches_cube_sampling() {
+        for face in 0..6 {
+            for (u, v) in [(0.0, 0.0), (0.5, -0.25), (-0.75, 0.6)] {
+                assert_eq!(sample_face(face_direction(face, u, v)), (face, u, v));
+            }
+        }
+    }
+
+    #[test]
+    fn gradient_goes_from_ground_to_zenith() {
+        let environment = Environment::default();
+        let Environment::Gradient {
+            zenith,
+            horizon,
+            ground,
+        } = environment
+        else {
+            unreachable!();
+        };
+        assert_eq!(environment.gradient_color(Vec3::Y), zenith);
+        assert_eq!(environment.gradient_color(Vec3::X), horizon);
+        assert_eq!(environment.gradient_color(-Vec3::Y * 2.0), ground);
+    }
+
+    #[test]
+    fn six_files_are_a_cubemap() {
+        assert!(matches!(
+            Environment::from_arg("a,b,c,d,e,f"),
+            Environment::Cubemap(_)
+        ));
+        assert!(matches!(
+            Environment::from_arg("sky.hdr"),
+            Environment::Equirectangular(_)
+        ));
+    }
+}
@@ -0,0 +1,279 @@
+use std::sync::Arc;
+
+use glam::Mat4;
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet},
+    format::Format,
+    image::{
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::ImageView,
+        SampleCount,
+    },
+    pipeline::GraphicsPipeline,
+    shader::ShaderModule,
+};
+
+use crate::{
+    camera::{Camera, Projection},
+    device::GPU,
+    post::{self, SceneImages},
+    render_graph::{CompiledGraph, PassId, RenderGraph, ResourceId},
+    shaders,
+};
+
+// posições no espaço da câmera, meia precisão perde detalhe longe
+const POSITION_FORMAT: Format = Format::R32G32B32A32_SFLOAT;
+const OCCLUSION_FORMAT: Format = Format::R8_UNORM;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SsaoSettings {
+    pub enabled: bool,
+    // distância em unidades do mundo até onde uma superfície oclui a outra
+    pub radius: f32,
+    // ignora superfícies quase no mesmo plano, evita manchas em áreas planas
+    pub bias: f32,
+    pub intensity: f32,
+}
+
+impl Default for SsaoSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            radius: 0.5,
+            bias: 0.02,
+            intensity: 1.0,
+        }
+    }
+}
+
+// pixels cobertos pelo raio a uma unidade da câmera, na perspectiva o
+// shader divide pela distância
+pub fn radius_scale(projection: &Mat4, radius: f32, height: u32) -> f32 {
+    radius * projection.y_axis.y.abs() * 0.5 * height as f32
+}
+
+// passes do SSAO no grafo do frame (veja Ssao::declare)
+pub struct SsaoPasses {
+    position: PassId,
+    occlusion: PassId,
+    blur: PassId,
+    composite: PassId,
+    scene: SceneImages,
+    // posições no espaço da câmera reconstruídas do depth buffer, também
+    // usadas pelo TAA (veja AntiAliasing)
+    pub positions: ResourceId,
+    occlusion_image: ResourceId,
+    blurred: ResourceId,
+    // cena com a oclusão aplicada, entrada do resto do PostChain
+    pub output: ResourceId,
+}
+
+// oclusão ambiente a partir do depth buffer da cena: reconstrói as posições,
+// calcula a oclusão com as normais tiradas das posições, borra e escurece só
+// a luz ambiente que o Renderer separou na segunda imagem de cor
+pub struct Ssao {
+    pub settings: SsaoSettings,
+    passes: SsaoPasses,
+    position: Arc<GraphicsPipeline>,
+    occlusion: Arc<GraphicsPipeline>,
+    blur: Arc<GraphicsPipeline>,
+    composite: Arc<GraphicsPipeline>,
+    position_set: Arc<PersistentDescriptorSet>,
+    occlusion_set: Arc<PersistentDescriptorSet>,
+    blur_set: Arc<PersistentDescriptorSet>,
+    composite_set: Arc<PersistentDescriptorSet>,
+}
+
+impl Ssao {
+    pub fn declare(graph: &mut RenderGraph, scene: SceneImages) -> SsaoPasses {
+        let samples = SampleCount::Sample1;
+        let positions = graph.transient("positions", POSITION_FORMAT, samples);
+        let occlusion = graph.transient("occlusion", OCCLUSION_FORMAT, samples);
+        let blurred = graph.transient("blurred_occlusion", OCCLUSION_FORMAT, samples);
+        let output = graph.transient("ssao", post::HDR_FORMAT, samples);
+        SsaoPasses {
+            position: graph
+                .add_pass("ssao_position")
+                .color(positions, None)
+                .sample(scene.depth)
+                .id(),
+            occlusion: graph
+                .add_pass("ssao_occlusion")
+                .color(occlusion, None)
+                .sample(positions)
+                .id(),
+            blur: graph
+                .add_pass("ssao_blur")
+                .color(blurred, None)
+                .sample(occlusion)
+                .sample(positions)
+                .id(),
+            composite: graph
+                .add_pass("ssao_composite")
+                .color(output, None)
+                .sample(scene.color)
+                .sample(scene.ambient)
+                .sample(blurred)
+                .id(),
+            scene,
+            positions,
+            occlusion_image: occlusion,
+            blurred,
+            output,
+        }
+    }
+
+    pub fn new(
+        device: &GPU,
+        vs: &Arc<ShaderModule>,
+        graph: &CompiledGraph,
+        passes: SsaoPasses,
+    ) -> Ssao {
+        let image = |resource| graph.image(resource).unwrap();
+        let depth = post::depth_view(&image(passes.scene.depth));
+        let pipeline = |fs: Arc<ShaderModule>, pass| {
+            post::fullscreen_pipeline(device, vs, &fs, graph.subpass(pass), None)
+        };
+        let position = pipeline(
+            Self::position_shader(device, depth.image().samples()),
+            passes.position,
+        );
+        let occlusion = pipeline(
+            shaders::ssao_fs::load(device.clone()).expect("failed to create shader module"),
+            passes.occlusion,
+        );
+        let blur = pipeline(
+            shaders::ssao_blur_fs::load(device.clone()).expect("failed to create shader module"),
+            passes.blur,
+        );
+        let composite = pipeline(
+            shaders::ssao_composite_fs::load(device.clone())
+                .expect("failed to create shader module"),
+            passes.composite,
+        );
+
+        // todos os shaders leem com texelFetch
+        let sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Nearest,
+                min_filter: Filter::Nearest,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let descriptor_set_allocator =
+            StandardDescriptorSetAllocator::new(device.clone(), Default::default());
+        let set = |pipeline: &Arc<GraphicsPipeline>, inputs: Vec<Arc<ImageView>>| {
+            post::input_set(&descriptor_set_allocator, &sampler, pipeline, inputs)
+        };
+        let positions = image(passes.positions);
+
+        Ssao {
+            settings: SsaoSettings::default(),
+            position_set: set(&position, vec![depth]),
+            occlusion_set: set(&occlusion, vec![positions.clone()]),
+            blur_set: set(&blur, vec![image(passes.occlusion_image), positions]),
+            composite_set: set(
+                &composite,
+                vec![
+                    image(passes.scene.color),
+                    image(passes.scene.ambient),
+                    image(passes.blurred),
+                ],
+            ),
+            passes,
+            position,
+            occlusion,
+            blur,
+            composite,
+        }
+    }
+
+    // com o SSAO desligado as passes ficam vazias e o composite só copia a
+    // cena. As posições são gravadas também quando o TAA está ligado
+    pub fn record(
+        &self,
+        graph: &CompiledGraph,
+        pass: PassId,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        camera: &Camera,
+        reversed_z: bool,
+        taa: bool,
+    ) {
+        let passes = &self.passes;
+        let enabled = self.settings.enabled;
+        let extent = || graph.extent(pass);
+        if pass == passes.position && (enabled || taa) {
+            post::fullscreen_draw(
+                builder,
+                extent(),
+                &self.position,
+                &self.position_set,
+                Some(shaders::ssao_position_fs::Position {
+                    inverse_projection: camera.projection.inverse().to_cols_array_2d(),
+                    far_depth: if reversed_z { 0.0 } else { 1.0 },
+                }),
+            );
+        } else if pass == passes.occlusion && enabled {
+            let [_, height] = extent();
+            post::fullscreen_draw(
+                builder,
+                extent(),
+                &self.occlusion,
+                &self.occlusion_set,
+                Some(shaders::ssao_fs::Occlusion {
+                    radius_scale: radius_scale(&camera.projection, self.settings.radius, height),
+                    perspective: matches!(camera.projection_mode(), Projection::Perspective { .. })
+                        as i32,
+                    radius: self.settings.radius,
+                    bias: self.settings.bias,
+                    intensity: self.settings.intensity,
+                }),
+            );
+        } else if pass == passes.blur && enabled {
+            post::fullscreen_draw::<u32>(builder, extent(), &self.blur, &self.blur_set, None);
+        } else if pass == passes.composite {
+            post::fullscreen_draw(
+                builder,
+                extent(),
+                &self.composite,
+                &self.composite_set,
+                Some(shaders::ssao_composite_fs::Composite {
+                    strength: enabled as i32 as f32,
+                }),
+            );
+        }
+    }
+
+    // o depth buffer com MSAA só pode ser lido como sampler2DMS
+    fn position_shader(device: &GPU, samples: SampleCount) -> Arc<ShaderModule> {
+        if samples == SampleCount::Sample1 {
+            shaders::ssao_position_fs::load(device.clone())
+        } else {
+            shaders::ssao_position_ms_fs::load(device.clone())
+        }
+        .expect("failed to create shader module")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec4;
+
+    #[test]
+    fn radius_scale_matches_projected_size() {
+        let projection = Mat4::perspective_lh(1.2, 16.0 / 9.0, 0.1, 100.0);
+        let height = 720;
+        let radius = 0.5;
+        for distance in [1.0, 4.0, 25.0] {
+            let clip = projection * Vec4::new(0.0, radius, distance, 1.0);
+            let pixels = clip.y / clip.w * 0.5 * height as f32;
+            let expected = radius_scale(&projection, radius, height) / distance;
+            assert!((pixels.abs() - expected).abs() < 1e-3);
+        }
+    }
+}
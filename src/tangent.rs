@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+
+use crate::MyVertex;
+
+// gera as tangentes no padrão MikkTSpace, o mesmo que as ferramentas usam
+// para gerar os normal maps
+//
+// as tangentes dependem de cada triângulo, então a malha é expandida para um
+// vértice por canto e depois os vértices iguais são juntados de novo, um
+// vértice pode virar vários quando a tangente muda entre triângulos
+pub fn with_tangents(vertices: &[MyVertex], indices: &[u32]) -> (Vec<MyVertex>, Vec<u32>) {
+    let mut corners = Corners(
+        indices
+            .iter()
+            .map(|&index| vertices[index as usize].clone())
+            .collect(),
+    );
+    // sem coordenadas de textura o algoritmo falha, nesse caso fica a
+    // tangente que já estava no vértice
+    mikktspace::generate_tangents(&mut corners);
+
+    weld(corners.0)
+}
+
+// junta vértices com todos os atributos iguais
+pub fn weld(corners: Vec<MyVertex>) -> (Vec<MyVertex>, Vec<u32>) {
+    let mut vertices: Vec<MyVertex> = vec![];
+    let mut indices = Vec::with_capacity(corners.len());
+    let mut unique: HashMap<Vec<u32>, u32> = HashMap::new();
+
+    for vertex in corners {
+        let key = [
+            &vertex.position[..],
+            &vertex.color[..],
+            &vertex.normal[..],
+            &vertex.texcoord[..],
+            &vertex.tangent[..],
+        ]
+        .concat()
+        .iter()
+        // -0.0 e 0.0 viram a mesma chave
+        .map(|value| (value + 0.0).to_bits())
+        .collect();
+
+        let index = *unique.entry(key).or_insert_with(|| {
+            vertices.push(vertex);
+            (vertices.len() - 1) as u32
+        });
+        indices.push(index);
+    }
+
+    (vertices, indices)
+}
+
+struct Corners(Vec<MyVertex>);
+
+impl mikktspace::Geometry for Corners {
+    fn num_faces(&self) -> usize {
+        self.0.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.0[face * 3 + vert].position
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.0[face * 3 + vert].normal
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.0[face * 3 + vert].texcoord
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.0[face * 3 + vert].tangent = tangent;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(position: [f32; 3], texcoord: [f32; 2]) -> MyVertex {
+        MyVertex {
+            position,
+            color: [0.5; 3],
+            normal: [0.0, 0.0, 1.0],
+            texcoord,
+            tangent: [0.0; 4],
+        }
+    }
+
+    #[test]
+    fn tangent_follows_u_direction() {
+        let vertices = [
+            vertex([0.0, 0.0, 0.0], [0.0, 0.0]),
+            vertex([1.0, 0.0, 0.0], [1.0, 0.0]),
+            vertex([1.0, 1.0, 0.0], [1.0, 1.0]),
+            vertex([0.0, 1.0, 0.0], [0.0, 1.0]),
+        ];
+        let (vertices, indices) = with_tangents(&vertices, &[0, 1, 2, 0, 2, 3]);
+
+        // o quadrado é plano, então os vértices continuam compartilhados
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(indices, vec![0, 1, 2, 0, 2, 3]);
+        for vertex in &vertices {
+            let [x, y, z, w] = vertex.tangent;
+            assert!((x - 1.0).abs() < 1e-5 && y.abs() < 1e-5 && z.abs() < 1e-5);
+            assert_eq!(w.abs(), 1.0);
+        }
+    }
+
+    #[test]
+    fn mirrored_uvs_flip_handedness() {
+        let vertices = [
+            vertex([0.0, 0.0, 0.0], [0.0, 0.0]),
+            vertex([1.0, 0.0, 0.0], [1.0, 0.0]),
+            vertex([0.0, 1.0, 0.0], [0.0, 1.0]),
+            // mesmo triângulo ao lado com o u espelhado
+            vertex([2.0, 0.0, 0.0], [1.0, 0.0]),
+            vertex([3.0, 0.0, 0.0], [0.0, 0.0]),
+            vertex([2.0, 1.0, 0.0], [1.0, 1.0]),
+        ];
+        let (vertices, _) = with_tangents(&vertices, &[0, 1, 2, 3, 4, 5]);
+        assert_eq!(vertices[3].tangent[0], -1.0);
+        assert_eq!(vertices[0].tangent[3], -vertices[3].tangent[3]);
+    }
+}
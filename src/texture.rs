@@ -0,0 +1,214 @@
+use std::sync::Arc;
+
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        CopyBufferToImageInfo, PrimaryAutoCommandBuffer, PrimaryCommandBufferAbstract,
+    },
+    format::Format,
+    image::{
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::{ImageView, ImageViewCreateInfo, ImageViewType},
+        Image, ImageCreateFlags, ImageCreateInfo, ImageType, ImageUsage,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    sync::GpuFuture,
+};
+
+use crate::device::GPU;
+
+// imagem RGBA8 na memória, antes de ir para a GPU
+#[derive(Clone)]
+pub struct TextureData {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl TextureData {
+    pub fn load(file_name: &str) -> TextureData {
+        let image = image::open(file_name)
+            .expect("Failed to load image!")
+            .into_rgba8();
+        TextureData {
+            width: image.width(),
+            height: image.height(),
+            pixels: image.into_raw(),
+        }
+    }
+
+    pub fn from_color(color: [u8; 4]) -> TextureData {
+        TextureData {
+            width: 1,
+            height: 1,
+            pixels: color.to_vec(),
+        }
+    }
+
+    pub fn white() -> TextureData {
+        Self::from_color([255, 255, 255, 255])
+    }
+
+    // xadrez de cells x cells quadrados alternando entre as duas cores
+    pub fn checker(size: u32, cells: u32, a: [u8; 4], b: [u8; 4]) -> TextureData {
+        let cell_size = (size / cells.max(1)).max(1);
+        let pixels = (0..size * size)
+            .flat_map(|i| {
+                let (x, y) = (i % size / cell_size, i / size / cell_size);
+                if (x + y) % 2 == 0 {
+                    a
+                } else {
+                    b
+                }
+            })
+            .collect();
+        TextureData {
+            width: size,
+            height: size,
+            pixels,
+        }
+    }
+
+    // normal map que não muda a normal (0, 0, 1)
+    pub fn flat_normal() -> TextureData {
+        Self::from_color([128, 128, 255, 255])
+    }
+}
+
+pub struct Texture {
+    pub view: Arc<ImageView>,
+    pub sampler: Arc<Sampler>,
+}
+
+impl Texture {
+    // normal maps usam formato linear, cores usam sRGB
+    pub fn new(device: &GPU, data: &TextureData, format: Format) -> Texture {
+        let image = upload(
+            device,
+            &data.pixels,
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format,
+                extent: [data.width, data.height, 1],
+                usage: ImageUsage::SAMPLED,
+                ..Default::default()
+            },
+        );
+
+        Texture {
+            view: ImageView::new_default(image).unwrap(),
+            sampler: Sampler::new(device.clone(), SamplerCreateInfo::simple_repeat_linear())
+                .unwrap(),
+        }
+    }
+
+    // faces na ordem +x, -x, +y, -y, +z, -z, todas do mesmo tamanho
+    pub fn cubemap(device: &GPU, faces: &[TextureData; 6], format: Format) -> Texture {
+        let pixels: Vec<u8> = faces.iter().flat_map(|face| face.pixels.clone()).collect();
+        let image = upload(
+            device,
+            &pixels,
+            ImageCreateInfo {
+                flags: ImageCreateFlags::CUBE_COMPATIBLE,
+                image_type: ImageType::Dim2d,
+                format,
+                extent: [faces[0].width, faces[0].height, 1],
+                array_layers: 6,
+                usage: ImageUsage::SAMPLED,
+                ..Default::default()
+            },
+        );
+        Self::from_cube_image(device, image)
+    }
+
+    pub fn from_cube_image(device: &GPU, image: Arc<Image>) -> Texture {
+        let view = ImageView::new(
+            image.clone(),
+            ImageViewCreateInfo {
+                view_type: ImageViewType::Cube,
+                ..ImageViewCreateInfo::from_image(&image)
+            },
+        )
+        .unwrap();
+        Texture {
+            view,
+            sampler: Sampler::new(
+                device.clone(),
+                SamplerCreateInfo {
+                    mag_filter: Filter::Linear,
+                    min_filter: Filter::Linear,
+                    address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                    ..Default::default()
+                },
+            )
+            .unwrap(),
+        }
+    }
+}
+
+// cria a imagem e copia os pixels para ela, com mais de uma camada os pixels
+// de cada camada ficam um depois do outro
+pub fn upload<T: BufferContents + Copy>(
+    device: &GPU,
+    pixels: &[T],
+    create_info: ImageCreateInfo,
+) -> Arc<Image> {
+    let staging = Buffer::from_iter(
+        device.memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        pixels.iter().copied(),
+    )
+    .unwrap();
+
+    let image = Image::new(
+        device.memory_allocator.clone(),
+        ImageCreateInfo {
+            usage: create_info.usage | ImageUsage::TRANSFER_DST,
+            ..create_info
+        },
+        AllocationCreateInfo::default(),
+    )
+    .unwrap();
+
+    submit_and_wait(device, |builder| {
+        builder
+            .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(staging, image.clone()))
+            .unwrap();
+    });
+    image
+}
+
+// grava um command buffer de uso único e espera a GPU terminar, usado só
+// no carregamento
+pub fn submit_and_wait(
+    device: &GPU,
+    record: impl FnOnce(&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>),
+) {
+    let command_buffer_allocator =
+        StandardCommandBufferAllocator::new(device.clone(), Default::default());
+    let mut builder = AutoCommandBufferBuilder::primary(
+        &command_buffer_allocator,
+        device.graphics_queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+    record(&mut builder);
+    builder
+        .build()
+        .unwrap()
+        .execute(device.graphics_queue.clone())
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap()
+        .wait(None)
+        .unwrap();
+}
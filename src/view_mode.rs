@@ -0,0 +1,52 @@
+use vulkano::pipeline::graphics::rasterization::PolygonMode;
+
+use crate::keyboard;
+
+// modos de visualização para inspecionar os modelos, cada um tem a própria
+// pipeline criada no começo (veja PreRenderer::pipeline)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ViewMode {
+    Shaded,
+    Wireframe,
+    Normals,
+    UvChecker,
+    Depth,
+    Flat,
+    Unlit,
+}
+
+impl ViewMode {
+    // na ordem em que a tecla troca os modos
+    pub const ALL: [ViewMode; 7] = [
+        ViewMode::Shaded,
+        ViewMode::Wireframe,
+        ViewMode::Normals,
+        ViewMode::UvChecker,
+        ViewMode::Depth,
+        ViewMode::Flat,
+        ViewMode::Unlit,
+    ];
+
+    pub fn next(self) -> ViewMode {
+        keyboard::cycle(&Self::ALL, self)
+    }
+
+    // valor da constante VIEW_MODE do shaders::fs
+    pub fn shader_mode(self) -> i32 {
+        match self {
+            ViewMode::Shaded | ViewMode::Wireframe => 0,
+            ViewMode::Normals => 1,
+            ViewMode::UvChecker => 2,
+            ViewMode::Depth => 3,
+            ViewMode::Flat => 4,
+            ViewMode::Unlit => 5,
+        }
+    }
+
+    pub fn polygon_mode(self) -> PolygonMode {
+        match self {
+            ViewMode::Wireframe => PolygonMode::Line,
+            _ => PolygonMode::Fill,
+        }
+    }
+}